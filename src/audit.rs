@@ -0,0 +1,104 @@
+use std::{
+	path::PathBuf,
+	sync::Arc,
+};
+
+use anyhow::Context;
+use tokio::task;
+use tracing as log;
+
+use crate::{buffer::DoubleBuffer, server, timelapse, Frame};
+
+
+/// Runs the commands of the lanes on the canvas of `state`, each lane relative to its OFFSET
+pub fn replay(state: &server::State, lanes: &Frame, offsets: &[Option<(u32, u32)>])
+{
+	for (chunks, offset) in lanes.iter().zip(offsets) {
+		let mut offset = offset.unwrap_or((0, 0));
+		for line in chunks.iter().flat_map(|chunk| chunk.lines()) {
+			state.command(line, &mut offset);
+		}
+	}
+}
+
+/// Replays every new frame on a canvas of its own and saves the result as numbered PNGs into `dir`,
+/// to see what the server should show without asking it
+///
+/// Frames only patching the previous one are replayed on top of it, like on the server.
+pub async fn run(dir: PathBuf, canvas: (u32, u32), frames: Arc<DoubleBuffer<Frame>>, offsets: Vec<Option<(u32, u32)>>) -> anyhow::Result<()>
+{
+	std::fs::create_dir_all(&dir)
+		.with_context(|| format!("failed to create {}", dir.display()))?;
+	let mut n = timelapse::first_free(&dir);
+	let state = Arc::new(server::State::new(canvas));
+	let offsets = Arc::new(offsets);
+	let (mut generation, mut lanes) = frames.load();
+	loop {
+		let path = timelapse::frame_path(&dir, n);
+		let (state, offsets) = (state.clone(), offsets.clone());
+		let saved = task::spawn_blocking(move || {
+			replay(&state, &lanes, &offsets);
+			let canvas = state.canvas.lock().unwrap().clone();
+			canvas.save(&path).map(|_| path)
+		}).await?;
+		match saved {
+			Ok(path) => log::debug!("saved audit frame {}", path.display()),
+			Err(err) => log::warn!("failed to save audit frame: {}", err),
+		}
+		n += 1;
+		frames.changed(generation).await;
+		(generation, lanes) = frames.load();
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::store::Chunk;
+
+	#[tokio::test]
+	async fn frames()
+	{
+		let dir = std::env::temp_dir().join(format!("pixelspray-audit-{}", std::process::id()));
+		let lanes = |s: &str| Arc::new(vec![ vec![ Chunk::from(s.to_owned()) ], vec![ Chunk::from("PX 0 0 00ff00\n".to_owned()) ] ]);
+		let frames = Arc::new(DoubleBuffer::new(lanes("PX 0 0 ff0000\nPX 1 0 0000ff\n")));
+		let audit = tokio::spawn(run(dir.clone(), (4, 2), frames.clone(), vec![None, Some((3, 1))]));
+
+		let saved = |n| {
+			let path = timelapse::frame_path(&dir, n);
+			async move {
+				while !path.exists() {
+					tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+				}
+				// until it is written completely
+				loop {
+					if let Ok(image) = image::open(&path) {
+						return image.into_rgba8();
+					}
+					tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+				}
+			}
+		};
+		let first = saved(0).await;
+		assert_eq!(first.get_pixel(0, 0).0, [0xff, 0, 0, 0xff]);
+		assert_eq!(first.get_pixel(1, 0).0, [0, 0, 0xff, 0xff]);
+		assert_eq!(first.get_pixel(3, 1).0, [0, 0xff, 0, 0xff]);
+
+		// a patch keeps what was painted before
+		frames.publish(lanes("PX 0 0 ffffff\n"));
+		let second = saved(1).await;
+		assert_eq!(second.get_pixel(0, 0).0, [0xff; 4]);
+		assert_eq!(second.get_pixel(1, 0).0, [0, 0, 0xff, 0xff]);
+
+		// translucent pixels are blended over what is there, not put in its place
+		frames.publish(lanes("PX 1 0 ff000080\n"));
+		let third = saved(2).await;
+		assert_eq!(third.get_pixel(1, 0).0, [0x80, 0, 0x7f, 0xff]);
+		assert_eq!(third.get_pixel(0, 0).0, [0xff; 4]);
+
+		audit.abort();
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,98 @@
+use std::sync::{
+	atomic::{AtomicU64, Ordering},
+	Arc, Mutex,
+};
+
+use tokio::sync::Notify;
+
+
+/// Two slots for the current and the next value, switched by bumping a generation counter
+///
+/// Readers only compare the generation on their hot path and take the new value once
+/// it changed, so publishing never waits for them. There is a single publisher.
+#[derive(Debug)]
+pub struct DoubleBuffer<T>
+{
+	slots: [Mutex<Arc<T>>; 2],
+	generation: AtomicU64,
+	changed: Notify,
+}
+
+impl<T> DoubleBuffer<T>
+{
+	pub fn new(first: Arc<T>) -> Self
+	{
+		Self {
+			slots: [Mutex::new(first.clone()), Mutex::new(first)],
+			generation: AtomicU64::new(0),
+			changed: Notify::new(),
+		}
+	}
+
+	/// Fills the back slot and makes it the front, returning its generation
+	pub fn publish(&self, value: Arc<T>) -> u64
+	{
+		let next = self.generation.load(Ordering::Acquire) + 1;
+		*self.slots[next as usize % 2].lock().unwrap() = value;
+		self.generation.store(next, Ordering::Release);
+		self.changed.notify_waiters();
+		next
+	}
+
+	pub fn generation(&self) -> u64
+	{
+		self.generation.load(Ordering::Acquire)
+	}
+
+	/// The current value and its generation
+	pub fn load(&self) -> (u64, Arc<T>)
+	{
+		let generation = self.generation();
+		let value = self.slots[generation as usize % 2].lock().unwrap().clone();
+		(generation, value)
+	}
+
+	/// Waits for a newer generation than `since`
+	pub async fn changed(&self, since: u64)
+	{
+		loop {
+			let notified = self.changed.notified();
+			tokio::pin!(notified);
+			notified.as_mut().enable();
+			if self.generation() != since {
+				return;
+			}
+			notified.await;
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[tokio::test]
+	async fn swap()
+	{
+		let buffer = Arc::new(DoubleBuffer::new(Arc::new("a")));
+		assert_eq!(buffer.load(), (0, Arc::new("a")));
+
+		let waiter = tokio::spawn({
+			let buffer = buffer.clone();
+			async move {
+				buffer.changed(0).await;
+				buffer.load()
+			}
+		});
+		tokio::task::yield_now().await;
+		assert_eq!(buffer.publish(Arc::new("b")), 1);
+		assert_eq!(waiter.await.unwrap(), (1, Arc::new("b")));
+
+		buffer.publish(Arc::new("c"));
+		assert_eq!(buffer.load(), (2, Arc::new("c")));
+		// already newer, so no waiting
+		buffer.changed(1).await;
+	}
+}
@@ -0,0 +1,95 @@
+use clap::ValueEnum;
+
+use crate::source::RawFormat;
+
+#[cfg(target_os = "linux")]
+mod wayland;
+#[cfg(target_os = "linux")]
+mod x11;
+
+
+/// A screenshot as tightly packed pixels
+#[derive(Debug)]
+pub struct Frame
+{
+	pub size: (u32, u32),
+	pub format: RawFormat,
+	pub data: Vec<u8>,
+}
+
+/// A connection to a display server to take screenshots with
+pub trait Capture: Send
+{
+	/// Takes a screenshot of the whole screen, blocking until it is done
+	fn grab(&mut self) -> anyhow::Result<Frame>;
+}
+
+/// Display server protocol to capture the screen with
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum Backend
+{
+	/// X11 through MIT-SHM if the server is local, which XWayland serves too
+	X11,
+	/// wlr-screencopy, as offered by wlroots compositors like Sway, but not the PipeWire portal of GNOME and KDE
+	Wayland,
+}
+
+impl Backend
+{
+	/// The backend of the session, by its environment
+	pub fn detect() -> Option<Self>
+	{
+		let set = |var| std::env::var_os(var).is_some_and(|value| !value.is_empty());
+		if set("WAYLAND_DISPLAY") {
+			Some(Backend::Wayland)
+		} else if set("DISPLAY") {
+			Some(Backend::X11)
+		} else {
+			None
+		}
+	}
+}
+
+/// Connects to the display server of `backend`, or of the session without one
+#[cfg(target_os = "linux")]
+pub fn open(backend: Option<Backend>) -> anyhow::Result<Box<dyn Capture>>
+{
+	let backend = backend.or_else(Backend::detect)
+		.ok_or_else(|| anyhow::anyhow!("neither WAYLAND_DISPLAY nor DISPLAY is set"))?;
+	Ok(match backend {
+		Backend::X11 => Box::new(x11::X11::connect()?),
+		Backend::Wayland => Box::new(wayland::Screencopy::connect()?),
+	})
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open(_backend: Option<Backend>) -> anyhow::Result<Box<dyn Capture>>
+{
+	anyhow::bail!("screen capture is only supported on Linux")
+}
+
+/// Rows of `stride` bytes cut down to `row` bytes each, optionally bottom to top
+fn pack(data: &[u8], stride: usize, row: usize, flip: bool) -> Vec<u8>
+{
+	let rows = data.chunks_exact(stride).map(|line| &line[..row]);
+	if flip {
+		rows.rev().flatten().copied().collect()
+	} else {
+		rows.flatten().copied().collect()
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn packed()
+	{
+		let data = [1, 2, 0, 3, 4, 0];
+		assert_eq!(pack(&data, 3, 2, false), [1, 2, 3, 4]);
+		assert_eq!(pack(&data, 3, 2, true), [3, 4, 1, 2]);
+	}
+}
@@ -0,0 +1,155 @@
+use std::{
+	convert::TryInto,
+	os::unix::{fs::FileExt, io::AsFd},
+};
+
+use anyhow::Context;
+use wayland_client::{
+	delegate_noop,
+	globals::{registry_queue_init, GlobalListContents},
+	protocol::{wl_buffer::WlBuffer, wl_output::WlOutput, wl_registry::WlRegistry, wl_shm, wl_shm_pool::WlShmPool},
+	Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+	zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+	zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+use super::{Capture, Frame};
+use crate::source::RawFormat;
+
+
+/// Pixel layout of a `wl_shm` format, all of them little endian
+fn shm_format(format: wl_shm::Format) -> Option<RawFormat>
+{
+	// the alpha of a screen means nothing
+	match format {
+		wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 => Some(RawFormat::Bgrx),
+		wl_shm::Format::Abgr8888 | wl_shm::Format::Xbgr8888 => Some(RawFormat::Rgbx),
+		_ => None,
+	}
+}
+
+/// What the compositor told about the frame being copied
+#[derive(Debug, Default)]
+struct Copy
+{
+	/// Format, size and stride of the buffer it wants
+	buffer: Option<(WEnum<wl_shm::Format>, u32, u32, u32)>,
+	flip: bool,
+	/// Whether it was copied, once it is done
+	done: Option<bool>,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Copy
+{
+	fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as wayland_client::Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for Copy
+{
+	fn event(copy: &mut Self, _: &ZwlrScreencopyFrameV1, event: zwlr_screencopy_frame_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>)
+	{
+		use zwlr_screencopy_frame_v1::Event;
+		match event {
+			Event::Buffer { format, width, height, stride } => copy.buffer = Some((format, width, height, stride)),
+			Event::Flags { flags } => copy.flip = matches!(flags, WEnum::Value(flags) if flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert)),
+			Event::Ready { .. } => copy.done = Some(true),
+			Event::Failed => copy.done = Some(false),
+			_ => {},
+		}
+	}
+}
+
+delegate_noop!(Copy: ignore wl_shm::WlShm);
+delegate_noop!(Copy: ignore WlOutput);
+delegate_noop!(Copy: ignore WlBuffer);
+delegate_noop!(Copy: WlShmPool);
+delegate_noop!(Copy: ZwlrScreencopyManagerV1);
+
+/// Screenshots of the first output by the wlr-screencopy protocol
+pub struct Screencopy
+{
+	queue: EventQueue<Copy>,
+	shm: wl_shm::WlShm,
+	output: WlOutput,
+	manager: ZwlrScreencopyManagerV1,
+}
+
+impl Screencopy
+{
+	/// Connects to the compositor of `WAYLAND_DISPLAY`
+	pub fn connect() -> anyhow::Result<Self>
+	{
+		let conn = Connection::connect_to_env().context("failed to connect to the compositor")?;
+		let (globals, queue) = registry_queue_init::<Copy>(&conn)?;
+		let qh = queue.handle();
+		let shm = globals.bind(&qh, 1..=1, ())?;
+		let output = globals.bind(&qh, 1..=1, ())?;
+		// GNOME and KDE only share the screen through the PipeWire portal
+		let manager = globals.bind(&qh, 1..=1, ())
+			.context("the compositor does not offer wlr-screencopy, try XWayland with screen:x11")?;
+		Ok(Self { queue, shm, output, manager })
+	}
+
+	/// Dispatches events until `until` holds
+	fn wait(&mut self, copy: &mut Copy, until: impl Fn(&Copy) -> bool) -> anyhow::Result<()>
+	{
+		while !until(copy) {
+			self.queue.blocking_dispatch(copy)?;
+		}
+		Ok(())
+	}
+}
+
+impl Capture for Screencopy
+{
+	fn grab(&mut self) -> anyhow::Result<Frame>
+	{
+		let qh = self.queue.handle();
+		let mut copy = Copy::default();
+		let frame = self.manager.capture_output(0, &self.output, &qh, ());
+		self.wait(&mut copy, |copy| copy.buffer.is_some() || copy.done.is_some())?;
+		let Some((format, w, h, stride)) = copy.buffer else {
+			frame.destroy();
+			anyhow::bail!("the compositor offered no buffer");
+		};
+		let (Some(raw), WEnum::Value(format)) = (format.into_result().ok().and_then(shm_format), format) else {
+			frame.destroy();
+			anyhow::bail!("unsupported screen format {:?}", format);
+		};
+
+		let size = stride as u64 * h as u64;
+		let file = tempfile::tempfile().context("failed to create the screen buffer")?;
+		file.set_len(size)?;
+		let pool = self.shm.create_pool(file.as_fd(), size.try_into().context("screen is too large")?, &qh, ());
+		let buffer = pool.create_buffer(0, w as i32, h as i32, stride as i32, format, &qh, ());
+		frame.copy(&buffer);
+		let copied = self.wait(&mut copy, |copy| copy.done.is_some());
+		frame.destroy();
+		buffer.destroy();
+		pool.destroy();
+		copied?;
+		anyhow::ensure!(copy.done == Some(true), "the compositor failed to copy the screen");
+
+		let mut data = vec![0; size as usize];
+		file.read_exact_at(&mut data, 0)?;
+		let data = super::pack(&data, stride as usize, w as usize * 4, copy.flip);
+		Ok(Frame { size: (w, h), format: raw, data })
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn formats()
+	{
+		assert_eq!(shm_format(wl_shm::Format::Xrgb8888), Some(RawFormat::Bgrx));
+		assert_eq!(shm_format(wl_shm::Format::Xbgr8888), Some(RawFormat::Rgbx));
+		assert_eq!(shm_format(wl_shm::Format::Rgb565), None);
+	}
+}
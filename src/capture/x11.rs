@@ -0,0 +1,126 @@
+use std::{fs::File, os::unix::fs::FileExt};
+
+use anyhow::Context;
+use tracing as log;
+use x11rb::{
+	connection::Connection,
+	protocol::{
+		shm::{self, ConnectionExt as _},
+		xproto::{ConnectionExt as _, ImageFormat, ImageOrder, Setup},
+	},
+	rust_connection::RustConnection,
+};
+
+use super::{Capture, Frame};
+use crate::source::RawFormat;
+
+
+/// Pixel layout of the screens of depth `depth`
+fn pixel_format(setup: &Setup, depth: u8) -> anyhow::Result<RawFormat>
+{
+	let bpp = setup.pixmap_formats.iter()
+		.find(|format| format.depth == depth)
+		.map(|format| format.bits_per_pixel);
+	Ok(match (depth, bpp, setup.image_byte_order) {
+		(24 | 32, Some(32), ImageOrder::LSB_FIRST) => RawFormat::Bgrx,
+		_ => anyhow::bail!("unsupported screen of depth {} at {:?} bits per pixel", depth, bpp),
+	})
+}
+
+/// A MIT-SHM segment the X server copies the screen into
+struct Segment
+{
+	id: shm::Seg,
+	file: File,
+}
+
+impl Segment
+{
+	/// Shares a file of `len` bytes with the X server, which has to be local for it
+	fn attach(conn: &RustConnection, len: u64) -> anyhow::Result<Self>
+	{
+		let version = conn.shm_query_version()?.reply()?;
+		anyhow::ensure!((version.major_version, version.minor_version) >= (1, 2), "MIT-SHM {}.{} can not share files", version.major_version, version.minor_version);
+		let file = tempfile::tempfile()?;
+		file.set_len(len)?;
+		let id = conn.generate_id()?;
+		conn.shm_attach_fd(id, file.try_clone()?, false)?.check()?;
+		Ok(Self { id, file })
+	}
+}
+
+/// Screenshots of the first screen, through MIT-SHM if the X server offers it
+pub struct X11
+{
+	conn: RustConnection,
+	root: u32,
+	size: (u16, u16),
+	format: RawFormat,
+	shm: Option<Segment>,
+}
+
+impl X11
+{
+	/// Connects to the X server of `DISPLAY`
+	pub fn connect() -> anyhow::Result<Self>
+	{
+		let (conn, screen) = x11rb::connect(None).context("failed to connect to the X server")?;
+		let screen = &conn.setup().roots[screen];
+		let (root, size) = (screen.root, (screen.width_in_pixels, screen.height_in_pixels));
+		let format = pixel_format(conn.setup(), screen.root_depth)?;
+		let shm = Segment::attach(&conn, size.0 as u64 * size.1 as u64 * 4)
+			.map_err(|err| log::debug!("capturing without MIT-SHM: {:#}", err))
+			.ok();
+		Ok(Self { conn, root, size, format, shm })
+	}
+}
+
+impl Capture for X11
+{
+	fn grab(&mut self) -> anyhow::Result<Frame>
+	{
+		let (w, h) = self.size;
+		let len = w as usize * h as usize * 4;
+		let data = match &self.shm {
+			Some(segment) => {
+				self.conn.shm_get_image(self.root, 0, 0, w, h, u32::MAX, ImageFormat::Z_PIXMAP.into(), segment.id, 0)?
+					.reply().context("GetImage of MIT-SHM failed")?;
+				let mut data = vec![0; len];
+				segment.file.read_exact_at(&mut data, 0)?;
+				data
+			},
+			None => {
+				let mut data = self.conn.get_image(ImageFormat::Z_PIXMAP, self.root, 0, 0, w, h, u32::MAX)?
+					.reply().context("GetImage failed")?
+					.data;
+				// 32 bit pixels need no padding at the end of the lines
+				data.truncate(len);
+				data
+			},
+		};
+		Ok(Frame { size: (w as u32, h as u32), format: self.format, data })
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use x11rb::protocol::xproto::Format;
+
+	#[test]
+	fn setup()
+	{
+		let format = |depth, bits_per_pixel| Format { depth, bits_per_pixel, scanline_pad: 32 };
+		let mut setup = Setup {
+			image_byte_order: ImageOrder::LSB_FIRST,
+			pixmap_formats: vec![ format(1, 1), format(24, 32) ],
+			..Setup::default()
+		};
+		assert_eq!(pixel_format(&setup, 24).unwrap(), RawFormat::Bgrx);
+		assert!(pixel_format(&setup, 16).is_err());
+		setup.image_byte_order = ImageOrder::MSB_FIRST;
+		assert!(pixel_format(&setup, 24).is_err());
+	}
+}
@@ -0,0 +1,109 @@
+use std::{
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::store::Chunk;
+
+
+/// Faults injected into the workers, to see the supervisor, resume and repairs at work
+#[derive(Debug, Clone)]
+pub struct Chaos
+{
+	/// Chance of a connection being killed instead of sending a batch
+	pub drop_rate: f64,
+	/// Chance of a chunk being garbled on the wire
+	pub corrupt_rate: f64,
+	/// Average lifetime of a connection
+	pub reconnect: Option<Duration>,
+	pub seed: u64,
+	/// Connections made so far, so each gets faults of its own
+	connects: Arc<AtomicU64>,
+}
+
+impl Chaos
+{
+	pub fn new(drop_rate: f64, corrupt_rate: f64, reconnect: Option<Duration>, seed: u64) -> Self
+	{
+		Self { drop_rate, corrupt_rate, reconnect, seed, connects: Arc::default() }
+	}
+
+	/// The faults of the next connection, the same for every run with the same seed
+	pub fn connection(&self) -> Faults
+	{
+		let n = self.connects.fetch_add(1, Ordering::Relaxed);
+		let mut rng = StdRng::seed_from_u64(self.seed ^ n.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+		let lifetime = self.reconnect.map(|every| every.mul_f64(rng.gen_range(0.5..1.5)));
+		Faults { drop_rate: self.drop_rate, corrupt_rate: self.corrupt_rate, lifetime, rng }
+	}
+}
+
+/// The faults of a single connection
+#[derive(Debug)]
+pub struct Faults
+{
+	drop_rate: f64,
+	corrupt_rate: f64,
+	/// When the connection is killed
+	pub lifetime: Option<Duration>,
+	rng: StdRng,
+}
+
+impl Faults
+{
+	/// Whether to kill the connection instead of sending the next batch
+	pub fn kill(&mut self) -> bool
+	{
+		self.rng.gen_bool(self.drop_rate)
+	}
+
+	/// A copy of the chunk with one byte replaced, if it is to be garbled
+	pub fn corrupt(&mut self, chunk: &Chunk) -> Option<Chunk>
+	{
+		if chunk.is_empty() || !self.rng.gen_bool(self.corrupt_rate) {
+			return None;
+		}
+		let at = self.rng.gen_range(0..chunk.len());
+		let mut bytes = chunk.as_bytes().to_vec();
+		// an invalid command, unless it hits a separator, which also breaks one
+		bytes[at] = b'?';
+		String::from_utf8(bytes).ok().map(Chunk::from)
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn reproducible()
+	{
+		let chaos = Chaos::new(0.5, 0.5, Some(Duration::from_secs(10)), 7);
+		let again = Chaos::new(0.5, 0.5, Some(Duration::from_secs(10)), 7);
+		let chunk = Chunk::from("PX 1 2 ff0000\nPX 3 4 00ff00\n".to_owned());
+		for _ in 0..4 {
+			let (mut a, mut b) = (chaos.connection(), again.connection());
+			assert_eq!(a.lifetime, b.lifetime);
+			assert!((5..15).contains(&a.lifetime.unwrap().as_secs()));
+			for _ in 0..32 {
+				assert_eq!(a.kill(), b.kill());
+				let garbled = a.corrupt(&chunk);
+				assert_eq!(garbled, b.corrupt(&chunk));
+				if let Some(garbled) = garbled {
+					assert_eq!(garbled.len(), chunk.len());
+					assert_eq!(garbled.bytes().zip(chunk.bytes()).filter(|(a, b)| a != b).count(), 1);
+				}
+			}
+		}
+
+		let mut calm = Chaos::new(0.0, 0.0, None, 7).connection();
+		assert!(!calm.kill() && calm.corrupt(&chunk).is_none() && calm.lifetime.is_none());
+	}
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+
+/// Size chunks are packed to, and the smallest write
+pub const MIN: usize = 1420;
+/// Largest write the queued chunks are joined to
+pub const MAX: usize = 64 << 10;
+/// Writes taking longer on average mean the connection can not take more at once
+const SLOW: Duration = Duration::from_millis(10);
+/// Writes measured before the size is changed
+const SAMPLES: u32 = 16;
+/// Throughput a larger size may lose before it is given up again
+const TOLERANCE: f64 = 0.9;
+
+/// Bytes written at once on a connection, grown while the writes stay fast
+#[derive(Debug)]
+pub struct ChunkSize
+{
+	size: usize,
+	writes: u32,
+	bytes: usize,
+	elapsed: Duration,
+	/// Throughput at the previous size in bytes per second
+	rate: f64,
+}
+
+impl Default for ChunkSize
+{
+	fn default() -> Self
+	{
+		Self { size: MIN, writes: 0, bytes: 0, elapsed: Duration::ZERO, rate: 0.0 }
+	}
+}
+
+impl ChunkSize
+{
+	pub fn get(&self) -> usize
+	{
+		self.size
+	}
+
+	/// Takes a write of `bytes` that took `elapsed`, returning the new size if it changed
+	pub fn record(&mut self, bytes: usize, elapsed: Duration) -> Option<usize>
+	{
+		self.writes += 1;
+		self.bytes += bytes;
+		self.elapsed += elapsed;
+		if self.writes < SAMPLES {
+			return None;
+		}
+
+		let latency = self.elapsed / self.writes;
+		let rate = self.bytes as f64 / self.elapsed.as_secs_f64().max(1e-9);
+		let size = if latency > SLOW || rate < self.rate * TOLERANCE {
+			(self.size / 2).max(MIN)
+		} else {
+			(self.size * 2).min(MAX)
+		};
+		let before = self.size;
+		*self = Self { size, rate, ..Self::default() };
+		(size != before).then_some(size)
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn tuning()
+	{
+		let mut size = ChunkSize::default();
+		let fast = Duration::from_micros(100);
+		for _ in 1..SAMPLES {
+			assert_eq!(size.record(size.get(), fast), None);
+		}
+		assert_eq!(size.record(size.get(), fast), Some(2 * MIN));
+		while size.get() < MAX {
+			let bytes = size.get();
+			// twice the bytes in the same time
+			(0..SAMPLES).for_each(|_| { size.record(bytes, fast); });
+		}
+		(0..SAMPLES).for_each(|_| { size.record(MAX, fast); });
+		assert_eq!(size.get(), MAX);
+
+		// a congested link backs off
+		let slow = Duration::from_millis(50);
+		(0..SAMPLES).for_each(|_| { size.record(MAX, slow); });
+		assert_eq!(size.get(), MAX / 2);
+	}
+}
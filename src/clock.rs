@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use futures::future::{BoxFuture, FutureExt};
+use image::{DynamicImage, Rgba, RgbaImage};
+use tokio::{sync::mpsc, time};
+
+use crate::source::Source;
+
+
+/// Canvas pixels per font pixel
+const SCALE: u32 = 8;
+/// Font pixels per glyph, with a column of space after each
+const GLYPH: (u32, u32) = (5, 7);
+pub const FOREGROUND: Rgba<u8> = Rgba([0xff, 0xff, 0xff, 0xff]);
+/// Opaque, so the pixels of a digit that are off paint over the previous one
+pub const BACKGROUND: Rgba<u8> = Rgba([0, 0, 0, 0xff]);
+
+/// Rows of a glyph, the highest of the 5 bits on the left
+fn glyph(c: char) -> [u8; 7]
+{
+	match c {
+		'0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+		'1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+		'2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+		'3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+		'4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+		'5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+		'6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+		'7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+		'8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+		'9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+		':' => [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00],
+		'.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c],
+		'/' => [0x01, 0x01, 0x02, 0x04, 0x08, 0x10, 0x10],
+		'#' => [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a],
+		'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+		'F' => [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10],
+		'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+		'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+		'S' => [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+		_ => [0; 7],
+	}
+}
+
+/// Draws the text with the built-in font, `scale` canvas pixels per font pixel
+pub fn render(text: &str, scale: u32) -> RgbaImage
+{
+	let (gw, gh) = GLYPH;
+	let chars = text.chars().count() as u32;
+	let mut image = RgbaImage::from_pixel((chars * (gw + 1) - 1).max(1) * scale, gh * scale, BACKGROUND);
+	for (i, c) in text.chars().enumerate() {
+		for (row, bits) in glyph(c).iter().enumerate() {
+			for col in (0..gw).filter(|col| bits >> (gw - 1 - col) & 1 != 0) {
+				let (x, y) = ((i as u32 * (gw + 1) + col) * scale, row as u32 * scale);
+				for (dx, dy) in (0..scale).flat_map(|dy| (0..scale).map(move |dx| (dx, dy))) {
+					image.put_pixel(x + dx, y + dy, FOREGROUND);
+				}
+			}
+		}
+	}
+	image
+}
+
+/// Parses a local time like `2025-01-01T00:00:00` or one with its offset like `2025-01-01T00:00:00+01:00`
+pub fn parse_time(s: &str) -> Result<DateTime<Local>, String>
+{
+	if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+		return Ok(time.with_timezone(&Local));
+	}
+	let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"].iter()
+		.find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+		.ok_or_else(|| format!("expected a time like 2025-01-01T00:00:00, got '{}'", s))?;
+	Local.from_local_datetime(&naive).earliest()
+		.ok_or_else(|| format!("{} does not exist in the local time zone", s))
+}
+
+/// What the clock shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode
+{
+	/// The local time of day
+	Time,
+	/// The time left until then
+	Countdown(DateTime<Local>),
+}
+
+/// The text of the clock at `now` as `HH:MM:SS`, with at least `digits` digits for the hours
+fn text(mode: Mode, now: DateTime<Local>, digits: usize) -> String
+{
+	match mode {
+		Mode::Time => now.format("%H:%M:%S").to_string(),
+		Mode::Countdown(end) => {
+			// rounded up, so zero shows up right when the time is over
+			let left = (end - now).num_milliseconds().max(0) as u64;
+			let secs = left.div_ceil(1000);
+			format!("{:0digits$}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60, digits = digits)
+		},
+	}
+}
+
+/// A clock or countdown drawn anew every second
+///
+/// Only the digits changing differ between frames, so only their regions are sent again.
+#[derive(Debug)]
+pub struct Clock
+{
+	mode: Mode,
+}
+
+impl Clock
+{
+	pub fn new(mode: Mode) -> Self
+	{
+		Self { mode }
+	}
+}
+
+impl Source for Clock
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, _refresh: Option<Duration>, _max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		async move {
+			// the image keeps its size while the hours count down
+			let digits = text(self.mode, Local::now(), 2).split(':').next().map_or(2, str::len);
+			let mut last = None;
+			loop {
+				let now = Local::now();
+				let text = text(self.mode, now, digits);
+				if last.as_ref() != Some(&text) {
+					if tx.send(DynamicImage::ImageRgba8(render(&text, SCALE))).await.is_err() {
+						return Ok(());
+					}
+					last = Some(text);
+				}
+				// right after the next full second
+				let ms = 1000 - now.timestamp_subsec_millis().min(999) as u64;
+				time::sleep(Duration::from_millis(ms)).await;
+			}
+		}.boxed()
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn font()
+	{
+		let image = render("1:", SCALE);
+		assert_eq!(image.dimensions(), (11 * SCALE, 7 * SCALE));
+		// the foot of the 1 and the upper dot of the colon
+		assert_eq!(*image.get_pixel(SCALE, 6 * SCALE), FOREGROUND);
+		assert_eq!(*image.get_pixel(8 * SCALE, SCALE), FOREGROUND);
+		assert_eq!(*image.get_pixel(0, 0), BACKGROUND);
+		assert_eq!(*image.get_pixel(5 * SCALE, 6 * SCALE), BACKGROUND);
+	}
+
+	#[test]
+	fn countdown()
+	{
+		let now = parse_time("2025-01-01T00:00:00+00:00").unwrap();
+		let end = |s| Mode::Countdown(parse_time(s).unwrap());
+		assert_eq!(text(end("2025-01-01T01:02:03+00:00"), now, 2), "01:02:03");
+		assert_eq!(text(end("2025-01-05T00:00:00+00:00"), now, 2), "96:00:00");
+		assert_eq!(text(end("2025-01-01T00:00:10+00:00"), now, 3), "000:00:10");
+		assert_eq!(text(end("2024-12-31T00:00:00+00:00"), now, 2), "00:00:00");
+		assert_eq!(text(end("2025-01-01T00:00:00.200+00:00"), now, 2), "00:00:01");
+		assert!(parse_time("2025-01-01 12:30").is_ok());
+		assert!(parse_time("tomorrow").is_err());
+	}
+}
@@ -0,0 +1,152 @@
+use std::str::FromStr;
+
+use image::Rgba;
+
+
+/// A color given on the command line
+///
+/// Accepts `#RGB`, `#RRGGBB`, `#RRGGBBAA` or the same without `#` for six and
+/// eight digits, `rgb(r, g, b)` and `rgba(r, g, b, a)` with channels from 0 to 255
+/// or in percent and alpha from 0 to 1, CSS color names, and a plain number
+/// from 0 to 255 as grey level.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorArg(pub Rgba<u8>);
+
+impl FromStr for ColorArg
+{
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err>
+	{
+		let s = s.trim();
+		parse(&s.to_ascii_lowercase())
+			.map(ColorArg)
+			.ok_or_else(|| format!("invalid color '{}', expected e.g. #ff8000, rgb(255, 128, 0), orange or a grey level", s))
+	}
+}
+
+fn parse(s: &str) -> Option<Rgba<u8>>
+{
+	if let Ok(grey) = s.parse::<u8>() {
+		return Some(Rgba([grey, grey, grey, 0xff]));
+	}
+	if let Some(args) = s.strip_prefix("rgba(").or_else(|| s.strip_prefix("rgb(")) {
+		let args = args.strip_suffix(')')?.split(',').map(str::trim).collect::<Vec<_>>();
+		let alpha = match (s.starts_with("rgba"), args.len()) {
+			(false, 3) => 0xff,
+			(true, 4) => (fraction(args[3])? * 255.0).round() as u8,
+			_ => return None,
+		};
+		let channel = |v: &str| match v.ends_with('%') {
+			true => fraction(v).map(|f| (f * 255.0).round() as u8),
+			false => v.parse::<u8>().ok(),
+		};
+		return Some(Rgba([channel(args[0])?, channel(args[1])?, channel(args[2])?, alpha]));
+	}
+	if let Some(hex) = s.strip_prefix('#') {
+		if hex.len() == 3 {
+			let v = u16::from_str_radix(hex, 16).ok()?;
+			let [r, g, b] = [v >> 8, v >> 4 & 0xf, v & 0xf].map(|c| c as u8 * 0x11);
+			return Some(Rgba([r, g, b, 0xff]));
+		}
+		return hex_color(hex);
+	}
+	if let Some(&(_, rgba)) = NAMES.iter().find(|(name, _)| *name == s) {
+		return Some(Rgba(rgba.to_be_bytes()));
+	}
+	hex_color(s)
+}
+
+fn hex_color(s: &str) -> Option<Rgba<u8>>
+{
+	if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return None;
+	}
+	let v = u32::from_str_radix(s, 16).ok()?;
+	match s.len() {
+		6 => Some(Rgba([(v >> 16) as u8, (v >> 8) as u8, v as u8, 0xff])),
+		8 => Some(Rgba(v.to_be_bytes())),
+		_ => None,
+	}
+}
+
+/// A number from 0 to 1, or a percentage
+fn fraction(s: &str) -> Option<f32>
+{
+	let v = match s.strip_suffix('%') {
+		Some(p) => p.trim().parse::<f32>().ok()? / 100.0,
+		None => s.parse::<f32>().ok()?,
+	};
+	(0.0..=1.0).contains(&v).then_some(v)
+}
+
+/// The CSS named colors as RGBA
+const NAMES: &[(&str, u32)] = &[
+	("transparent", 0x00000000),
+	("aliceblue", 0xf0f8ffff), ("antiquewhite", 0xfaebd7ff), ("aqua", 0x00ffffff), ("aquamarine", 0x7fffd4ff),
+	("azure", 0xf0ffffff), ("beige", 0xf5f5dcff), ("bisque", 0xffe4c4ff), ("black", 0x000000ff),
+	("blanchedalmond", 0xffebcdff), ("blue", 0x0000ffff), ("blueviolet", 0x8a2be2ff), ("brown", 0xa52a2aff),
+	("burlywood", 0xdeb887ff), ("cadetblue", 0x5f9ea0ff), ("chartreuse", 0x7fff00ff), ("chocolate", 0xd2691eff),
+	("coral", 0xff7f50ff), ("cornflowerblue", 0x6495edff), ("cornsilk", 0xfff8dcff), ("crimson", 0xdc143cff),
+	("cyan", 0x00ffffff), ("darkblue", 0x00008bff), ("darkcyan", 0x008b8bff), ("darkgoldenrod", 0xb8860bff),
+	("darkgray", 0xa9a9a9ff), ("darkgreen", 0x006400ff), ("darkgrey", 0xa9a9a9ff), ("darkkhaki", 0xbdb76bff),
+	("darkmagenta", 0x8b008bff), ("darkolivegreen", 0x556b2fff), ("darkorange", 0xff8c00ff), ("darkorchid", 0x9932ccff),
+	("darkred", 0x8b0000ff), ("darksalmon", 0xe9967aff), ("darkseagreen", 0x8fbc8fff), ("darkslateblue", 0x483d8bff),
+	("darkslategray", 0x2f4f4fff), ("darkslategrey", 0x2f4f4fff), ("darkturquoise", 0x00ced1ff), ("darkviolet", 0x9400d3ff),
+	("deeppink", 0xff1493ff), ("deepskyblue", 0x00bfffff), ("dimgray", 0x696969ff), ("dimgrey", 0x696969ff),
+	("dodgerblue", 0x1e90ffff), ("firebrick", 0xb22222ff), ("floralwhite", 0xfffaf0ff), ("forestgreen", 0x228b22ff),
+	("fuchsia", 0xff00ffff), ("gainsboro", 0xdcdcdcff), ("ghostwhite", 0xf8f8ffff), ("gold", 0xffd700ff),
+	("goldenrod", 0xdaa520ff), ("gray", 0x808080ff), ("green", 0x008000ff), ("greenyellow", 0xadff2fff),
+	("grey", 0x808080ff), ("honeydew", 0xf0fff0ff), ("hotpink", 0xff69b4ff), ("indianred", 0xcd5c5cff),
+	("indigo", 0x4b0082ff), ("ivory", 0xfffff0ff), ("khaki", 0xf0e68cff), ("lavender", 0xe6e6faff),
+	("lavenderblush", 0xfff0f5ff), ("lawngreen", 0x7cfc00ff), ("lemonchiffon", 0xfffacdff), ("lightblue", 0xadd8e6ff),
+	("lightcoral", 0xf08080ff), ("lightcyan", 0xe0ffffff), ("lightgoldenrodyellow", 0xfafad2ff), ("lightgray", 0xd3d3d3ff),
+	("lightgreen", 0x90ee90ff), ("lightgrey", 0xd3d3d3ff), ("lightpink", 0xffb6c1ff), ("lightsalmon", 0xffa07aff),
+	("lightseagreen", 0x20b2aaff), ("lightskyblue", 0x87cefaff), ("lightslategray", 0x778899ff), ("lightslategrey", 0x778899ff),
+	("lightsteelblue", 0xb0c4deff), ("lightyellow", 0xffffe0ff), ("lime", 0x00ff00ff), ("limegreen", 0x32cd32ff),
+	("linen", 0xfaf0e6ff), ("magenta", 0xff00ffff), ("maroon", 0x800000ff), ("mediumaquamarine", 0x66cdaaff),
+	("mediumblue", 0x0000cdff), ("mediumorchid", 0xba55d3ff), ("mediumpurple", 0x9370dbff), ("mediumseagreen", 0x3cb371ff),
+	("mediumslateblue", 0x7b68eeff), ("mediumspringgreen", 0x00fa9aff), ("mediumturquoise", 0x48d1ccff), ("mediumvioletred", 0xc71585ff),
+	("midnightblue", 0x191970ff), ("mintcream", 0xf5fffaff), ("mistyrose", 0xffe4e1ff), ("moccasin", 0xffe4b5ff),
+	("navajowhite", 0xffdeadff), ("navy", 0x000080ff), ("oldlace", 0xfdf5e6ff), ("olive", 0x808000ff),
+	("olivedrab", 0x6b8e23ff), ("orange", 0xffa500ff), ("orangered", 0xff4500ff), ("orchid", 0xda70d6ff),
+	("palegoldenrod", 0xeee8aaff), ("palegreen", 0x98fb98ff), ("paleturquoise", 0xafeeeeff), ("palevioletred", 0xdb7093ff),
+	("papayawhip", 0xffefd5ff), ("peachpuff", 0xffdab9ff), ("peru", 0xcd853fff), ("pink", 0xffc0cbff),
+	("plum", 0xdda0ddff), ("powderblue", 0xb0e0e6ff), ("purple", 0x800080ff), ("rebeccapurple", 0x663399ff),
+	("red", 0xff0000ff), ("rosybrown", 0xbc8f8fff), ("royalblue", 0x4169e1ff), ("saddlebrown", 0x8b4513ff),
+	("salmon", 0xfa8072ff), ("sandybrown", 0xf4a460ff), ("seagreen", 0x2e8b57ff), ("seashell", 0xfff5eeff),
+	("sienna", 0xa0522dff), ("silver", 0xc0c0c0ff), ("skyblue", 0x87ceebff), ("slateblue", 0x6a5acdff),
+	("slategray", 0x708090ff), ("slategrey", 0x708090ff), ("snow", 0xfffafaff), ("springgreen", 0x00ff7fff),
+	("steelblue", 0x4682b4ff), ("tan", 0xd2b48cff), ("teal", 0x008080ff), ("thistle", 0xd8bfd8ff),
+	("tomato", 0xff6347ff), ("turquoise", 0x40e0d0ff), ("violet", 0xee82eeff), ("wheat", 0xf5deb3ff),
+	("white", 0xffffffff), ("whitesmoke", 0xf5f5f5ff), ("yellow", 0xffff00ff), ("yellowgreen", 0x9acd32ff),
+];
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn color(s: &str) -> Result<[u8; 4], String>
+	{
+		s.parse::<ColorArg>().map(|c| c.0.0)
+	}
+
+	#[test]
+	fn forms()
+	{
+		assert_eq!(color("#FF8000"), Ok([0xff, 0x80, 0, 0xff]));
+		assert_eq!(color("ff800080"), Ok([0xff, 0x80, 0, 0x80]));
+		assert_eq!(color("#f80"), Ok([0xff, 0x88, 0, 0xff]));
+		assert_eq!(color("rgb(255, 50%, 0)"), Ok([0xff, 0x80, 0, 0xff]));
+		assert_eq!(color("rgba(0,0,255,0.5)"), Ok([0, 0, 0xff, 0x80]));
+		assert_eq!(color("RebeccaPurple"), Ok([0x66, 0x33, 0x99, 0xff]));
+		assert_eq!(color("128"), Ok([0x80, 0x80, 0x80, 0xff]));
+		assert!(color("rgb(1, 2)").is_err());
+		assert!(color("rgba(1, 2, 3, 2)").is_err());
+		assert!(color("#12345").is_err());
+		assert!(color("+12345").is_err());
+		assert!(color("nocolor").is_err());
+	}
+}
@@ -0,0 +1,220 @@
+use std::fmt::Write;
+
+use clap::ValueEnum;
+
+
+/// Shell to generate completions for
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum Shell
+{
+	Bash,
+	Zsh,
+	Fish,
+}
+
+/// An option as the shells need it
+#[derive(Debug)]
+struct Flag
+{
+	long: Option<String>,
+	short: Option<char>,
+	/// First line of the help
+	help: String,
+	takes_value: bool,
+	/// Possible values, if limited
+	values: Vec<String>,
+}
+
+fn flags(cmd: &clap::Command) -> Vec<Flag>
+{
+	cmd.get_arguments()
+		.filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+		.map(|arg| Flag {
+			long: arg.get_long().map(str::to_owned),
+			short: arg.get_short(),
+			help: arg.get_help().map(|help| help.to_string()).unwrap_or_default()
+				.lines().next().unwrap_or_default().to_owned(),
+			takes_value: arg.get_action().takes_values(),
+			values: arg.get_possible_values().iter()
+				.filter(|value| !value.is_hide_set())
+				.map(|value| value.get_name().to_owned())
+				.collect(),
+		})
+		.collect()
+}
+
+/// Names of the flag as typed, like `-f` and `--filter`
+fn names(flag: &Flag) -> Vec<String>
+{
+	flag.short.map(|short| format!("-{}", short)).into_iter()
+		.chain(flag.long.as_ref().map(|long| format!("--{}", long)))
+		.collect()
+}
+
+fn about(cmd: &clap::Command) -> String
+{
+	cmd.get_about().map(|about| about.to_string()).unwrap_or_default()
+}
+
+/// The completion script of `cmd` for `shell`, the command has to be built
+pub fn generate(cmd: &clap::Command, shell: Shell) -> String
+{
+	match shell {
+		Shell::Bash => bash(cmd),
+		Shell::Zsh => zsh(cmd),
+		Shell::Fish => fish(cmd),
+	}
+}
+
+fn bash(cmd: &clap::Command) -> String
+{
+	let name = cmd.get_name();
+	let words = |cmd: &clap::Command| flags(cmd).iter().flat_map(names).collect::<Vec<_>>().join(" ");
+	let subcommands = cmd.get_subcommands().map(clap::Command::get_name).collect::<Vec<_>>().join(" ");
+
+	let mut out = String::new();
+	writeln!(out, "_{}()\n{{", name).unwrap();
+	writeln!(out, "\tlocal cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\" words").unwrap();
+	writeln!(out, "\tcase \"${{COMP_WORDS[1]}}\" in").unwrap();
+	for sub in cmd.get_subcommands() {
+		writeln!(out, "\t\t{}) words=\"{}\";;", sub.get_name(), words(sub)).unwrap();
+	}
+	writeln!(out, "\t\t*) words=\"{}\";;", words(cmd)).unwrap();
+	writeln!(out, "\tesac").unwrap();
+
+	writeln!(out, "\tcase \"$prev\" in").unwrap();
+	let mut seen = std::collections::HashSet::new();
+	for flag in std::iter::once(cmd).chain(cmd.get_subcommands()).flat_map(flags) {
+		let names = names(&flag).join("|");
+		if flag.values.is_empty() || !seen.insert(names.clone()) {
+			continue;
+		}
+		writeln!(out, "\t\t{}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return;;", names, flag.values.join(" ")).unwrap();
+	}
+	writeln!(out, "\tesac").unwrap();
+
+	writeln!(out, "\tif [[ $cur == -* ]]; then").unwrap();
+	writeln!(out, "\t\tCOMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))").unwrap();
+	writeln!(out, "\telse").unwrap();
+	writeln!(out, "\t\tCOMPREPLY=($(compgen -f -- \"$cur\"))").unwrap();
+	writeln!(out, "\t\t(( COMP_CWORD == 1 )) && COMPREPLY+=($(compgen -W \"{}\" -- \"$cur\"))", subcommands).unwrap();
+	writeln!(out, "\tfi\n}}").unwrap();
+	writeln!(out, "complete -o filenames -F _{} {}", name, name).unwrap();
+	out
+}
+
+fn zsh(cmd: &clap::Command) -> String
+{
+	let escape = |s: &str| s.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:");
+	let specs = |cmd: &clap::Command| {
+		flags(cmd).iter()
+			.flat_map(|flag| {
+				let value = if !flag.takes_value {
+					String::new()
+				} else if flag.values.is_empty() {
+					":value:_files".to_owned()
+				} else {
+					format!(":value:({})", flag.values.join(" "))
+				};
+				let help = escape(&flag.help);
+				flag.short.map(|short| format!("'-{}{}[{}]{}'", short, if flag.takes_value { "+" } else { "" }, help, value)).into_iter()
+					.chain(flag.long.as_ref().map(|long| format!("'--{}{}[{}]{}'", long, if flag.takes_value { "=" } else { "" }, help, value)))
+					.collect::<Vec<_>>()
+			})
+			.map(|spec| format!(" \\\n\t\t\t{}", spec))
+			.collect::<String>()
+	};
+	let subcommands = cmd.get_subcommands()
+		.map(|sub| format!("{}\\:\"{}\"", sub.get_name(), escape(&about(sub)).replace('"', "\\\"")))
+		.collect::<Vec<_>>()
+		.join(" ");
+
+	let mut out = String::new();
+	writeln!(out, "#compdef {}\n", cmd.get_name()).unwrap();
+	writeln!(out, "case $words[2] in").unwrap();
+	for sub in cmd.get_subcommands() {
+		writeln!(out, "\t{})\n\t\t_arguments{} \\\n\t\t\t'*:file:_files';;", sub.get_name(), specs(sub)).unwrap();
+	}
+	writeln!(out, "\t*)\n\t\t_arguments{} \\\n\t\t\t'1:command or host:(({}))' \\\n\t\t\t'*:file:_files';;", specs(cmd), subcommands).unwrap();
+	writeln!(out, "esac").unwrap();
+	out
+}
+
+fn fish(cmd: &clap::Command) -> String
+{
+	let name = cmd.get_name();
+	let escape = |s: &str| s.replace('\\', "\\\\").replace('\'', "\\'");
+	let line = |out: &mut String, condition: &str, flag: &Flag| {
+		write!(out, "complete -c {} -n '{}'", name, condition).unwrap();
+		if let Some(short) = flag.short {
+			write!(out, " -s {}", short).unwrap();
+		}
+		if let Some(long) = flag.long.as_ref() {
+			write!(out, " -l {}", long).unwrap();
+		}
+		write!(out, " -d '{}'", escape(&flag.help)).unwrap();
+		if !flag.values.is_empty() {
+			write!(out, " -xa '{}'", flag.values.join(" ")).unwrap();
+		} else if flag.takes_value {
+			write!(out, " -r").unwrap();
+		}
+		writeln!(out).unwrap();
+	};
+	let subcommands = cmd.get_subcommands().map(clap::Command::get_name).collect::<Vec<_>>().join(" ");
+
+	let mut out = String::new();
+	for sub in cmd.get_subcommands() {
+		writeln!(out, "complete -c {} -n '__fish_use_subcommand' -a {} -d '{}'", name, sub.get_name(), escape(&about(sub))).unwrap();
+	}
+	for flag in flags(cmd) {
+		line(&mut out, &format!("not __fish_seen_subcommand_from {}", subcommands), &flag);
+	}
+	for sub in cmd.get_subcommands() {
+		for flag in flags(sub) {
+			line(&mut out, &format!("__fish_seen_subcommand_from {}", sub.get_name()), &flag);
+		}
+	}
+	out
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use clap::{Arg, ArgAction, Command};
+
+	fn command() -> Command
+	{
+		let mut cmd = Command::new("spray")
+			.arg(Arg::new("host"))
+			.arg(Arg::new("filter").short('f').long("filter").help("Filter to use").value_parser(["mask", "grey"]))
+			.arg(Arg::new("delta").long("delta").help("Only send changes, it's [fast]").action(ArgAction::SetTrue))
+			.arg(Arg::new("secret").long("secret").hide(true))
+			.subcommand(Command::new("serve").about("Run a server")
+				.arg(Arg::new("snapshot").long("snapshot").help("Save the canvas")));
+		cmd.build();
+		cmd
+	}
+
+	#[test]
+	fn scripts()
+	{
+		let cmd = command();
+		let bash = generate(&cmd, Shell::Bash);
+		assert!(bash.contains("\t\t-f|--filter) COMPREPLY=($(compgen -W \"mask grey\" -- \"$cur\")); return;;"));
+		assert!(bash.contains("serve) words=\"--snapshot -h --help\";;"));
+		assert!(!bash.contains("--secret"));
+		assert!(bash.ends_with("complete -o filenames -F _spray spray\n"));
+
+		let zsh = generate(&cmd, Shell::Zsh);
+		assert!(zsh.contains("'-f+[Filter to use]:value:(mask grey)'"));
+		assert!(zsh.contains("'--delta[Only send changes, it'\\''s \\[fast\\]]'"));
+		assert!(zsh.contains("'1:command or host:((serve\\:\"Run a server\" help\\:"));
+
+		let fish = generate(&cmd, Shell::Fish);
+		assert!(fish.contains("complete -c spray -n 'not __fish_seen_subcommand_from serve help' -s f -l filter -d 'Filter to use' -xa 'mask grey'\n"));
+		assert!(fish.contains("complete -c spray -n '__fish_seen_subcommand_from serve' -l snapshot -d 'Save the canvas' -r\n"));
+		assert!(fish.contains("-l delta -d 'Only send changes, it\\'s [fast]'\n"));
+	}
+}
@@ -0,0 +1,93 @@
+use std::{
+	ffi::OsString,
+	path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+
+/// Finds the path given with `--config` on the command line
+pub fn find(args: impl Iterator<Item = OsString>) -> Option<PathBuf>
+{
+	let mut args = args.skip(1);
+	while let Some(arg) = args.next() {
+		let arg = arg.to_string_lossy();
+		if arg == "--config" {
+			return args.next().map(PathBuf::from);
+		}
+		if let Some(path) = arg.strip_prefix("--config=") {
+			return Some(path.into());
+		}
+	}
+	None
+}
+
+/// Reads command line arguments from a file
+///
+/// Each line holds an option, optionally followed by its value after a space
+/// (e.g. `--offset 10x10`), or a whole positional argument like the image path.
+/// Empty lines and those starting with `#` are skipped.
+pub fn args(path: &Path) -> anyhow::Result<Vec<OsString>>
+{
+	let text = std::fs::read_to_string(path)
+		.with_context(|| format!("failed to read {}", path.display()))?;
+	Ok(parse(&text))
+}
+
+fn parse(text: &str) -> Vec<OsString>
+{
+	text.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.flat_map(|line| match line.split_once(char::is_whitespace).filter(|_| line.starts_with('-')) {
+			Some((arg, value)) => vec![ arg.into(), value.trim_start().into() ],
+			None => vec![ line.into() ],
+		})
+		.collect()
+}
+
+/// Resolves on every SIGHUP, or never where there are no such signals
+pub struct Hangup
+{
+	#[cfg(unix)]
+	signal: tokio::signal::unix::Signal,
+}
+
+impl Hangup
+{
+	pub fn new() -> std::io::Result<Self>
+	{
+		Ok(Self {
+			#[cfg(unix)]
+			signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?,
+		})
+	}
+
+	pub async fn recv(&mut self)
+	{
+		#[cfg(unix)]
+		self.signal.recv().await;
+		#[cfg(not(unix))]
+		futures::future::pending::<()>().await;
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn arguments()
+	{
+		let args = parse("# placement\n--offset 10x10\n\n  --mirror\n/tmp/my image.png\n");
+		assert_eq!(args, ["--offset", "10x10", "--mirror", "/tmp/my image.png"]);
+
+		let cli = ["pixelspray", "-n", "4", "--config=a.conf"].map(OsString::from);
+		assert_eq!(find(cli.iter().cloned()), Some("a.conf".into()));
+		let cli = ["pixelspray", "--config", "b.conf"].map(OsString::from);
+		assert_eq!(find(cli.iter().cloned()), Some("b.conf".into()));
+		assert_eq!(find(std::iter::once(OsString::from("--config"))), None);
+	}
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+
+/// Reports in a row with too many lost pixels before a conflict is declared
+pub const PERSISTENT: u32 = 3;
+/// Pixels read back to look for a free area
+const GRID_SAMPLES: u32 = 4096;
+
+/// Tells apart another client painting over us from a few stray pixels
+#[derive(Debug)]
+pub struct Conflict
+{
+	/// Share of the sampled pixels that may be lost
+	threshold: f64,
+	/// Reports in a row above the threshold
+	lost: u32,
+}
+
+impl Conflict
+{
+	pub fn new(threshold: f64) -> Self
+	{
+		Self { threshold, lost: 0 }
+	}
+
+	/// Takes a report of `ours` out of `total` sampled pixels, true once the losses persist
+	pub fn report(&mut self, ours: u64, total: u64) -> bool
+	{
+		if total == 0 {
+			return false;
+		}
+		let lost = 1.0 - ours as f64 / total as f64;
+		self.lost = if lost > self.threshold { self.lost + 1 } else { 0 };
+		if self.lost < PERSISTENT {
+			return false;
+		}
+		self.lost = 0;
+		true
+	}
+}
+
+/// Coordinates of a coarse grid over the canvas and its spacing
+pub fn grid(canvas: (u32, u32)) -> (Vec<(u32, u32)>, u32)
+{
+	let step = ((canvas.0 as f64 * canvas.1 as f64 / GRID_SAMPLES as f64).sqrt() as u32).max(1);
+	let coords = (0..canvas.1).step_by(step as usize)
+		.flat_map(|y| (0..canvas.0).step_by(step as usize).map(move |x| (x, y)))
+		.collect();
+	(coords, step)
+}
+
+/// Finds the most uniformly colored place for a `footprint` apart from the one `at`
+///
+/// Areas nobody paints on tend to be of one color, so the spread of the
+/// `pixels` read back on a grid of `step` is used as measure of activity.
+pub fn free_area(pixels: &HashMap<(u32, u32), [u8; 3]>, step: u32, canvas: (u32, u32), footprint: (u32, u32), at: (u32, u32)) -> Option<(u32, u32)>
+{
+	let overlaps = |(x, y): (u32, u32)| x < at.0 + footprint.0 && at.0 < x + footprint.0
+		&& y < at.1 + footprint.1 && at.1 < y + footprint.1;
+	let spread = |(x, y): (u32, u32)| {
+		let colors = pixels.iter()
+			.filter(|((px, py), _)| (x..x + footprint.0).contains(px) && (y..y + footprint.1).contains(py))
+			.map(|(_, c)| c.map(f64::from))
+			.collect::<Vec<_>>();
+		let n = colors.len().max(1) as f64;
+		let mean = (0..3).map(|i| colors.iter().map(|c| c[i]).sum::<f64>() / n).collect::<Vec<_>>();
+		colors.iter()
+			.map(|c| (0..3).map(|i| (c[i] - mean[i]).powi(2)).sum::<f64>())
+			.sum::<f64>() / n
+	};
+	let (w, h) = (canvas.0.checked_sub(footprint.0)?, canvas.1.checked_sub(footprint.1)?);
+	(0..=h).step_by(step as usize)
+		.flat_map(|y| (0..=w).step_by(step as usize).map(move |x| (x, y)))
+		.filter(|&place| !overlaps(place))
+		.map(|place| (spread(place), place))
+		.min_by(|a, b| a.0.total_cmp(&b.0))
+		.map(|(_, place)| place)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn persistence()
+	{
+		let mut conflict = Conflict::new(0.5);
+		assert!(!conflict.report(10, 100));
+		assert!(!conflict.report(10, 100));
+		// a good report starts the count over
+		assert!(!conflict.report(90, 100));
+		assert!(!conflict.report(0, 0));
+		for _ in 1..PERSISTENT {
+			assert!(!conflict.report(10, 100));
+		}
+		assert!(conflict.report(10, 100));
+		assert!(!conflict.report(10, 100));
+	}
+
+	#[test]
+	fn free_areas()
+	{
+		let canvas = (64, 64);
+		let (coords, step) = grid(canvas);
+		assert_eq!((coords.len(), step), (4096, 1));
+		// noise everywhere but the bottom right quarter
+		let pixels = coords.into_iter()
+			.map(|(x, y)| ((x, y), match x >= 32 && y >= 32 {
+				true => [0, 0, 0],
+				false => [(x * 37 % 256) as u8, (y * 91 % 256) as u8, ((x ^ y) * 13 % 256) as u8],
+			}))
+			.collect();
+		assert_eq!(free_area(&pixels, step, canvas, (32, 32), (0, 0)), Some((32, 32)));
+		// not where we already are
+		assert_ne!(free_area(&pixels, step, canvas, (32, 32), (32, 32)), Some((32, 32)));
+		assert_eq!(free_area(&pixels, step, canvas, (64, 64), (0, 0)), None);
+		assert_eq!(grid((640, 480)).1, 8);
+	}
+}
@@ -0,0 +1,128 @@
+use std::fmt::Write;
+
+
+/// Samples a region needs before its contention is told
+const MIN_SAMPLES: u64 = 8;
+/// Share of the expected throughput below which the canvas counts as saturated
+pub const SATURATED: f64 = 0.5;
+/// Regions named in a report at most
+const TOP: usize = 3;
+
+/// Contention of a region of the canvas over a report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region
+{
+	/// `(x, y, w, h)` on the canvas
+	pub rect: (u32, u32, u32, u32),
+	pub samples: u64,
+	/// From 0 for all pixels kept to 1 for all lost while sending at full speed
+	pub index: f64,
+}
+
+/// Tells where others paint over the image by the verified pixels on a grid of regions
+///
+/// Pixels lost while sending slower than expected may just not be painted yet,
+/// so the losses are weighed by the share of the expected throughput sent.
+#[derive(Debug)]
+pub struct Contention
+{
+	canvas: (u32, u32),
+	grid: (u32, u32),
+	ours: Vec<u64>,
+	total: Vec<u64>,
+}
+
+impl Contention
+{
+	pub fn new(canvas: (u32, u32), grid: (u32, u32)) -> Self
+	{
+		let grid = (grid.0.clamp(1, canvas.0.max(1)), grid.1.clamp(1, canvas.1.max(1)));
+		let cells = (grid.0 * grid.1) as usize;
+		Self { canvas, grid, ours: vec![0; cells], total: vec![0; cells] }
+	}
+
+	fn cell(&self, (x, y): (u32, u32)) -> Option<usize>
+	{
+		let (w, h) = self.canvas;
+		(x < w && y < h).then(|| ((y as u64 * self.grid.1 as u64 / h as u64) * self.grid.0 as u64 + x as u64 * self.grid.0 as u64 / w as u64) as usize)
+	}
+
+	/// Counts a pixel read back at `at`
+	pub fn record(&mut self, at: (u32, u32), matched: bool)
+	{
+		if let Some(cell) = self.cell(at) {
+			self.ours[cell] += matched as u64;
+			self.total[cell] += 1;
+		}
+	}
+
+	/// The regions with enough samples, most contested first, starting over for the next report
+	///
+	/// `delivery` is the share of the expected throughput that was sent.
+	pub fn report(&mut self, delivery: f64) -> Vec<Region>
+	{
+		let (gw, gh) = self.grid;
+		let (w, h) = self.canvas;
+		let edge = |i: u32, n: u32, len: u32| (i as u64 * len as u64 / n as u64) as u32;
+		let mut regions = (0..gh)
+			.flat_map(|row| (0..gw).map(move |col| (col, row)))
+			.zip(self.ours.iter().zip(self.total.iter()))
+			.filter(|(_, (_, &total))| total >= MIN_SAMPLES)
+			.map(|((col, row), (&ours, &total))| {
+				let (x, y) = (edge(col, gw, w), edge(row, gh, h));
+				Region {
+					rect: (x, y, edge(col + 1, gw, w) - x, edge(row + 1, gh, h) - y),
+					samples: total,
+					index: (1.0 - ours as f64 / total as f64) * delivery.clamp(0.0, 1.0),
+				}
+			})
+			.collect::<Vec<_>>();
+		regions.sort_by(|a, b| b.index.total_cmp(&a.index));
+		self.ours.fill(0);
+		self.total.fill(0);
+		regions
+	}
+}
+
+/// The most contested regions in a line like `0.42 at 256x0+256x256, ...`
+pub fn summary(regions: &[Region]) -> String
+{
+	let mut out = String::new();
+	for region in regions.iter().take(TOP) {
+		let (x, y, w, h) = region.rect;
+		write!(out, "{}{:.2} at {}x{}+{}x{}", if out.is_empty() { "" } else { ", " }, region.index, x, y, w, h).unwrap();
+	}
+	out
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn regions()
+	{
+		let mut contention = Contention::new((100, 50), (2, 1));
+		for i in 0..10 {
+			// the right half loses every other pixel
+			contention.record((10, 10), true);
+			contention.record((60, 10), i % 2 == 0);
+		}
+		contention.record((100, 0), false);
+		let regions = contention.report(1.0);
+		assert_eq!(regions, [
+			Region { rect: (50, 0, 50, 50), samples: 10, index: 0.5 },
+			Region { rect: (0, 0, 50, 50), samples: 10, index: 0.0 },
+		]);
+		assert_eq!(summary(&regions), "0.50 at 50x0+50x50, 0.00 at 0x0+50x50");
+
+		// losses while barely sending are no sign of others
+		for _ in 0..10 {
+			contention.record((60, 10), false);
+		}
+		assert_eq!(contention.report(0.2)[0].index, 0.2);
+		assert!(contention.report(1.0).is_empty());
+	}
+}
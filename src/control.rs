@@ -0,0 +1,122 @@
+use std::{
+	collections::VecDeque,
+	net::SocketAddr,
+	time::Duration,
+};
+
+use anyhow::Context;
+use futures::future::FutureExt;
+use tokio::{
+	io::{self, AsyncBufReadExt, AsyncWriteExt},
+	net,
+	sync::mpsc,
+	time,
+};
+use tracing as log;
+
+use crate::probe;
+
+
+/// How often the canvas size is asked for again
+pub const REPROBE: Duration = Duration::from_secs(10);
+/// Read-backs waiting for their reply at most, further samples are dropped
+const MAX_PENDING: usize = 4096;
+
+/// A pixel to read back at absolute coordinates and the color it should have
+pub type Sample = ((u32, u32), [u8; 3]);
+
+/// Runs the control connection, which does all the reading so the workers only write
+///
+/// Reads back the `samples` and reports where and whether they still match to `matches`,
+/// and asks for the canvas size every `reprobe` to notice when it changes.
+pub async fn run(host: SocketAddr, canvas: (u32, u32), reprobe: Duration, samples: mpsc::UnboundedReceiver<Sample>, matches: mpsc::UnboundedSender<((u32, u32), bool)>) -> anyhow::Result<()>
+{
+	let stream = net::TcpStream::connect(host).await
+		.context("failed to connect control connection")?;
+	let (stream, probed) = probe::canvas(stream, &[], None, true).await;
+	for line in probed.help.iter() {
+		log::debug!("control: {}", line);
+	}
+	let mut size = probed.size;
+	if size != canvas {
+		log::warn!("control: canvas is {}x{} now", size.0, size.1);
+	}
+
+	let (rd, mut wr) = stream.into_split();
+	let mut lines = io::BufReader::new(rd).lines();
+	let mut pending = VecDeque::<Sample>::new();
+	let mut ticker = time::interval(reprobe);
+	ticker.tick().await;
+	// no more samples once all workers are gone, but the size is still watched
+	let mut samples = Some(samples);
+	loop {
+		let sample = async {
+			match samples.as_mut() {
+				Some(samples) => samples.recv().await,
+				None => futures::future::pending().await,
+			}
+		};
+		futures::select! {
+			sample = sample.fuse() => {
+				let Some(sample) = sample else {
+					samples = None;
+					continue;
+				};
+				if pending.len() >= MAX_PENDING {
+					continue;
+				}
+				let ((x, y), _) = sample;
+				wr.write_all(format!("PX {} {}\n", x, y).as_bytes()).await
+					.context("failed to send read-back")?;
+				pending.push_back(sample);
+			},
+			line = lines.next_line().fuse() => {
+				let Some(line) = line.context("failed to read control connection")? else {
+					anyhow::bail!("server closed the control connection");
+				};
+				if let Some(now) = probe::parse_size_reply(&line) {
+					if now != size {
+						log::warn!("control: canvas changed from {}x{} to {}x{}", size.0, size.1, now.0, now.1);
+						size = now;
+					}
+				} else if let Some((at, color)) = probe::parse_px_reply(&line) {
+					if let Some(matched) = check(&mut pending, at, color) {
+						matches.send((at, matched)).ok();
+					}
+				}
+			},
+			_ = ticker.tick().fuse() => {
+				wr.write_all(b"SIZE\n").await
+					.context("failed to send SIZE")?;
+			},
+		}
+	}
+}
+
+/// Matches a read-back reply to its sample, skipping those the server did not answer
+fn check(pending: &mut VecDeque<Sample>, at: (u32, u32), color: [u8; 3]) -> Option<bool>
+{
+	while let Some((read, expected)) = pending.pop_front() {
+		if read == at {
+			return Some(expected == color);
+		}
+	}
+	None
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn replies()
+	{
+		let mut pending = VecDeque::from([((1, 1), [1, 2, 3]), ((2, 2), [4, 5, 6]), ((3, 3), [7, 8, 9])]);
+		assert_eq!(check(&mut pending, (2, 2), [4, 5, 6]), Some(true));
+		assert_eq!(check(&mut pending, (3, 3), [0, 0, 0]), Some(false));
+		assert_eq!(check(&mut pending, (9, 9), [0, 0, 0]), None);
+		assert!(pending.is_empty());
+	}
+}
@@ -0,0 +1,205 @@
+use std::{
+	io::Read,
+	path::Path,
+	time::Duration,
+};
+
+use anyhow::Context;
+use futures::future::{BoxFuture, FutureExt};
+use image::{DynamicImage, RgbaImage};
+use tokio::{sync::mpsc, time};
+
+use crate::source::Source;
+
+
+/// Shortest time between two frames, however fast a range cycles
+const MIN_STEP: Duration = Duration::from_millis(20);
+
+/// Palette entries rotated at a speed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range
+{
+	pub first: u8,
+	pub last: u8,
+	/// Entries moved per second, negative ones cycle backwards
+	pub speed: f32,
+}
+
+/// Parses `first-last:speed`, e.g. `32-47:10`
+pub fn parse_range(s: &str) -> Result<Range, String>
+{
+	let (range, speed) = s.split_once(':')
+		.ok_or_else(|| format!("expected first-last:speed, got '{}'", s))?;
+	let (first, last) = range.split_once('-')
+		.ok_or_else(|| format!("expected first-last, got '{}'", range))?;
+	let index = |i: &str| i.parse::<u8>().map_err(|err| format!("invalid palette index '{}': {}", i, err));
+	let (first, last) = (index(first)?, index(last)?);
+	if first >= last {
+		return Err(format!("range {}-{} is empty", first, last));
+	}
+	let speed = speed.parse::<f32>()
+		.map_err(|err| format!("invalid speed '{}': {}", speed, err))?;
+	Ok(Range { first, last, speed })
+}
+
+impl Range
+{
+	/// How far the range moved `t` seconds in
+	fn offset(&self, t: f32) -> usize
+	{
+		let len = (self.last - self.first) as f32 + 1.0;
+		(t * self.speed).floor().rem_euclid(len) as usize
+	}
+}
+
+/// An image of palette indices
+#[derive(Debug, Clone, PartialEq)]
+pub struct Indexed
+{
+	width: u32,
+	height: u32,
+	indices: Vec<u8>,
+	palette: Vec<[u8; 4]>,
+}
+
+impl Indexed
+{
+	/// Decodes a PNG, which has to be of the indexed color type
+	pub fn decode(r: impl Read) -> anyhow::Result<Self>
+	{
+		let reader = png::Decoder::new(r).read_info()?;
+		let info = reader.info();
+		anyhow::ensure!(info.color_type == png::ColorType::Indexed, "not an indexed image but {:?}", info.color_type);
+		let depth = info.bit_depth as usize;
+		let plte = info.palette.as_deref().context("missing palette")?;
+		let trns = info.trns.as_deref().unwrap_or_default();
+		let palette = plte.chunks_exact(3)
+			.enumerate()
+			.map(|(i, rgb)| [rgb[0], rgb[1], rgb[2], trns.get(i).copied().unwrap_or(0xff)])
+			.collect();
+		let (width, height) = (info.width, info.height);
+
+		let mut reader = reader;
+		let mut buf = vec![0; reader.output_buffer_size()];
+		let frame = reader.next_frame(&mut buf)?;
+		// low bit depths pack several pixels into a byte, leftmost first
+		let mask = ((1u16 << depth) - 1) as u8;
+		let indices = buf.chunks_exact(frame.line_size)
+			.take(height as usize)
+			.flat_map(|row| (0..width as usize).map(move |x| {
+				let bit = x * depth;
+				row[bit / 8] >> (8 - depth - bit % 8) & mask
+			}))
+			.collect();
+		Ok(Self { width, height, indices, palette })
+	}
+
+	/// Draws the image with the `ranges` of the palette moved to where they are `t` seconds in
+	pub fn render(&self, ranges: &[Range], t: f32) -> RgbaImage
+	{
+		let mut palette = self.palette.clone();
+		palette.resize(256, [0; 4]);
+		for range in ranges {
+			let (first, last) = (range.first as usize, range.last as usize);
+			palette[first..=last].rotate_right(range.offset(t));
+		}
+		let pixels = self.indices.iter()
+			.flat_map(|&i| palette[i as usize])
+			.collect();
+		RgbaImage::from_raw(self.width, self.height, pixels).unwrap()
+	}
+}
+
+/// An indexed image animated by rotating parts of its palette
+///
+/// New frames only differ where the cycled colors are, so only those regions are sent again.
+#[derive(Debug)]
+pub struct Cycle
+{
+	image: Indexed,
+	ranges: Vec<Range>,
+}
+
+impl Cycle
+{
+	pub fn open(path: &Path, ranges: Vec<Range>) -> anyhow::Result<Self>
+	{
+		let file = std::fs::File::open(path)
+			.with_context(|| format!("failed to open {}", path.display()))?;
+		let image = Indexed::decode(std::io::BufReader::new(file))
+			.with_context(|| format!("failed to decode {}", path.display()))?;
+		Ok(Self { image, ranges })
+	}
+}
+
+impl Source for Cycle
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, _refresh: Option<Duration>, _max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		async move {
+			// a frame whenever the fastest range moves on
+			let step = self.ranges.iter()
+				.filter(|range| range.speed != 0.0)
+				.map(|range| Duration::from_secs_f32(1.0 / range.speed.abs()))
+				.min()
+				.unwrap_or(Duration::MAX)
+				.max(MIN_STEP);
+			let start = time::Instant::now();
+			let mut ticker = time::interval(step);
+			let mut last = None;
+			loop {
+				ticker.tick().await;
+				let t = start.elapsed().as_secs_f32();
+				let offsets = self.ranges.iter().map(|range| range.offset(t)).collect::<Vec<_>>();
+				if last.as_ref() == Some(&offsets) {
+					continue;
+				}
+				let frame = DynamicImage::ImageRgba8(self.image.render(&self.ranges, t));
+				if tx.send(frame).await.is_err() {
+					return Ok(());
+				}
+				last = Some(offsets);
+			}
+		}.boxed()
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn ranges()
+	{
+		assert_eq!(parse_range("32-47:10"), Ok(Range { first: 32, last: 47, speed: 10.0 }));
+		assert_eq!(parse_range("0-3:-2.5").map(|range| range.offset(1.0)), Ok(1));
+		assert!(parse_range("5-5:1").is_err());
+		assert!(parse_range("0-300:1").is_err());
+		assert!(parse_range("0-3").is_err());
+	}
+
+	#[test]
+	fn cycling()
+	{
+		// 2 bit indices 0 1 2 3 / 3 2 1 0, with the first entry transparent
+		let mut png = Vec::new();
+		let mut encoder = png::Encoder::new(&mut png, 4, 2);
+		encoder.set_color(png::ColorType::Indexed);
+		encoder.set_depth(png::BitDepth::Two);
+		encoder.set_palette(vec![0, 0, 0, 10, 0, 0, 20, 0, 0, 30, 0, 0]);
+		encoder.set_trns(vec![0]);
+		let mut writer = encoder.write_header().unwrap();
+		writer.write_image_data(&[0b00_01_10_11, 0b11_10_01_00]).unwrap();
+		writer.finish().unwrap();
+
+		let image = Indexed::decode(png.as_slice()).unwrap();
+		assert_eq!(image.indices, [0, 1, 2, 3, 3, 2, 1, 0]);
+		let reds = |image: RgbaImage| image.pixels().map(|px| px.0[0]).collect::<Vec<_>>();
+		let range = Range { first: 1, last: 3, speed: 1.0 };
+		assert_eq!(reds(image.render(&[range], 0.5)), [0, 10, 20, 30, 30, 20, 10, 0]);
+		assert_eq!(reds(image.render(&[range], 1.0)), [0, 30, 10, 20, 20, 10, 30, 0]);
+		assert_eq!(image.render(&[range], 1.0).get_pixel(0, 0).0[3], 0);
+	}
+}
@@ -0,0 +1,125 @@
+use std::time::{Duration, Instant};
+
+use crate::{server, store::Chunk};
+
+
+/// Brightness a pixel may lose before it is sent again, as a fraction of full scale
+const TOLERANCE: f32 = 0.1;
+/// Number of brightness groups with their own resend interval
+const TIERS: usize = 4;
+
+/// How often a pixel of `brightness` from 0 to 1 has to be sent on a canvas fading
+/// with `half_life`, or `None` if its fading is not noticeable
+pub fn resend_interval(half_life: Duration, brightness: f32) -> Option<Duration>
+{
+	if brightness <= TOLERANCE {
+		return None;
+	}
+	// b * 2^(-t/h) = b - tolerance
+	Some(half_life.mul_f32(-(1.0 - TOLERANCE / brightness).log2()))
+}
+
+/// Brightness of a `PX x y color` command, scaled by its alpha
+fn brightness(px: &str) -> Option<f32>
+{
+	let color = server::parse_color(px.split_ascii_whitespace().nth(3)?)?;
+	let [r, g, b, a] = color.0;
+	Some(r.max(g).max(b) as f32 / 255.0 * a as f32 / 255.0)
+}
+
+/// Pixels of similar brightness, sent again every `every`
+#[derive(Debug)]
+struct Tier
+{
+	every: Duration,
+	chunks: Vec<Chunk>,
+	next: usize,
+	/// When the current pass over the chunks started, or is due to
+	due: Instant,
+}
+
+/// Resends of the bright pixels of a lane in between the regular cycles
+///
+/// Brighter tiers come due more often, so they get most of the resends.
+#[derive(Debug, Default)]
+pub struct Refresh
+{
+	tiers: Vec<Tier>,
+}
+
+impl Refresh
+{
+	pub fn new(chunks: &[Chunk], half_life: Duration) -> Self
+	{
+		let mut pxls = vec![ Vec::new(); TIERS ];
+		for px in chunks.iter().flat_map(|chunk| chunk.split_inclusive('\n')) {
+			let Some(b) = brightness(px).filter(|&b| b > TOLERANCE) else { continue };
+			let tier = (((b - TOLERANCE) / (1.0 - TOLERANCE) * TIERS as f32) as usize).min(TIERS - 1);
+			pxls[tier].push(px.to_owned());
+		}
+
+		let now = Instant::now();
+		let tiers = pxls.into_iter()
+			.enumerate()
+			.filter(|(_, pxls)| !pxls.is_empty())
+			.filter_map(|(tier, pxls)| {
+				// the brightest pixel of the tier decides
+				let upper = TOLERANCE + (1.0 - TOLERANCE) * (tier + 1) as f32 / TIERS as f32;
+				let every = resend_interval(half_life, upper)?;
+				Some(Tier { every, chunks: crate::chunk(pxls.into_iter()), next: 0, due: now + every })
+			})
+			.collect();
+		Self { tiers }
+	}
+
+	/// The next chunk of the most overdue tier at `now`
+	pub fn next(&mut self, now: Instant) -> Option<Chunk>
+	{
+		let tier = self.tiers.iter_mut()
+			.filter(|tier| tier.due <= now)
+			.min_by_key(|tier| tier.due)?;
+		let chunk = tier.chunks[tier.next].clone();
+		tier.next = (tier.next + 1) % tier.chunks.len();
+		if tier.next == 0 {
+			tier.due += tier.every;
+			// too slow to keep up, start over from now instead of piling up
+			tier.due = tier.due.max(now);
+		}
+		Some(chunk)
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn interval()
+	{
+		let half_life = Duration::from_secs(10);
+		assert_eq!(resend_interval(half_life, 0.05), None);
+		// white may lose a tenth
+		let white = resend_interval(half_life, 1.0).unwrap();
+		assert!((white.as_secs_f32() - 1.52).abs() < 0.01);
+		assert!(resend_interval(half_life, 0.5).unwrap() > white);
+	}
+
+	#[test]
+	fn resends()
+	{
+		let chunks = [Chunk::from("PX 0 0 FFFFFF\nPX 1 0 202020\nPX 2 0 000000\nPX 3 0 808080\n".to_owned())];
+		let mut refresh = Refresh::new(&chunks, Duration::from_secs(10));
+		assert_eq!(refresh.tiers.len(), 3);
+
+		let start = Instant::now();
+		assert_eq!(refresh.next(start), None);
+		let later = start + Duration::from_secs(2);
+		assert_eq!(refresh.next(later).as_deref(), Some("PX 0 0 FFFFFF\n"));
+		// white is done for now, grey is not due yet and black never
+		assert_eq!(refresh.next(later), None);
+		// both are due, grey is so for longer
+		assert_eq!(refresh.next(start + Duration::from_secs(5)).as_deref(), Some("PX 3 0 808080\n"));
+	}
+}
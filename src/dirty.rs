@@ -0,0 +1,106 @@
+use image::{DynamicImage, GenericImageView};
+
+
+/// Region of an image as `(x, y, w, h)`
+pub type Rect = (u32, u32, u32, u32);
+
+/// Regions of an image that changed and need to be encoded again
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Dirty
+{
+	rects: Vec<Rect>,
+}
+
+impl Dirty
+{
+	/// Marks a region as changed, merging it with the regions it overlaps
+	pub fn mark(&mut self, mut rect: Rect)
+	{
+		while let Some(i) = self.rects.iter().position(|&other| overlaps(rect, other)) {
+			rect = union(rect, self.rects.swap_remove(i));
+		}
+		self.rects.push(rect);
+	}
+
+	/// Marks the blocks of `block` pixels in which the images differ
+	pub fn diff(a: &DynamicImage, b: &DynamicImage, block: u32) -> Self
+	{
+		let mut dirty = Self::default();
+		let (w, h) = a.dimensions();
+		for by in (0..h).step_by(block as usize) {
+			for bx in (0..w).step_by(block as usize) {
+				let rect = (bx, by, block.min(w - bx), block.min(h - by));
+				let changed = (by..by + rect.3)
+					.any(|y| (bx..bx + rect.2).any(|x| a.get_pixel(x, y) != b.get_pixel(x, y)));
+				if changed {
+					dirty.mark(rect);
+				}
+			}
+		}
+		dirty
+	}
+
+	pub fn rects(&self) -> &[Rect]
+	{
+		&self.rects
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.rects.is_empty()
+	}
+
+	pub fn contains(&self, x: u32, y: u32) -> bool
+	{
+		self.rects.iter().any(|&(rx, ry, rw, rh)| x >= rx && y >= ry && x < rx + rw && y < ry + rh)
+	}
+
+	/// Number of pixels covered
+	pub fn area(&self) -> u64
+	{
+		self.rects.iter().map(|&(_, _, w, h)| w as u64 * h as u64).sum()
+	}
+}
+
+/// Whether the rectangles overlap or touch
+fn overlaps(a: Rect, b: Rect) -> bool
+{
+	a.0 <= b.0 + b.2 && b.0 <= a.0 + a.2 && a.1 <= b.1 + b.3 && b.1 <= a.1 + a.3
+}
+
+fn union(a: Rect, b: Rect) -> Rect
+{
+	let (x, y) = (a.0.min(b.0), a.1.min(b.1));
+	(x, y, (a.0 + a.2).max(b.0 + b.2) - x, (a.1 + a.3).max(b.1 + b.3) - y)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use image::{Rgba, RgbaImage};
+
+	#[test]
+	fn merge()
+	{
+		let mut dirty = Dirty::default();
+		dirty.mark((0, 0, 4, 4));
+		dirty.mark((10, 10, 2, 2));
+		dirty.mark((4, 2, 4, 4));
+		assert_eq!(dirty.rects(), [(10, 10, 2, 2), (0, 0, 8, 6)]);
+		assert!(dirty.contains(7, 5));
+		assert!(!dirty.contains(8, 5));
+		assert_eq!(dirty.area(), 52);
+	}
+
+	#[test]
+	fn diff_blocks()
+	{
+		let a = DynamicImage::ImageRgba8(RgbaImage::new(20, 10));
+		let mut b = a.to_rgba8();
+		b.put_pixel(17, 9, Rgba([1, 2, 3, 4]));
+		let dirty = Dirty::diff(&a, &DynamicImage::ImageRgba8(b), 8);
+		assert_eq!(dirty.rects(), [(16, 8, 4, 2)]);
+	}
+}
@@ -0,0 +1,93 @@
+use std::{
+	fmt::Write,
+	path::Path,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+use crate::store::Chunk;
+
+
+/// A chunk with the time it was sent at and the OFFSET it is relative to
+type Sent = (Duration, Chunk, (u32, u32));
+
+/// The pixels of the first pass in the order they were handed to the connections,
+/// so visualizers can replay how the image was painted
+#[derive(Debug)]
+pub struct DrawOrder
+{
+	canvas: (u32, u32),
+	start: Instant,
+	chunks: Mutex<Vec<Sent>>,
+}
+
+impl DrawOrder
+{
+	pub fn new(canvas: (u32, u32)) -> Self
+	{
+		Self { canvas, start: Instant::now(), chunks: Mutex::default() }
+	}
+
+	pub fn record(&self, chunk: &Chunk, offset: Option<(u32, u32)>)
+	{
+		self.chunks.lock().unwrap()
+			.push((self.start.elapsed(), chunk.clone(), offset.unwrap_or((0, 0))));
+	}
+
+	/// Number of pixels recorded so far
+	pub fn pixels(&self) -> usize
+	{
+		self.chunks.lock().unwrap().iter()
+			.map(|(_, chunk, _)| chunk.lines().count())
+			.sum()
+	}
+
+	/// Writes the canvas size and a `[ms, x, y, color]` array per pixel, in canvas coordinates
+	pub fn write(&self, path: &Path) -> anyhow::Result<()>
+	{
+		let mut out = String::new();
+		write!(out, "{{\"canvas\":[{},{}],\"pixels\":[", self.canvas.0, self.canvas.1)?;
+		let chunks = self.chunks.lock().unwrap();
+		let pxls = chunks.iter()
+			.flat_map(|(t, chunk, offset)| chunk.lines().map(move |px| (t, px, offset)));
+		for (i, (t, px, (ox, oy))) in pxls.enumerate() {
+			let (x, y) = crate::coords_of(px);
+			if i > 0 {
+				out.push(',');
+			}
+			write!(out, "\n[{},{},{},\"{}\"]", t.as_millis(), x + *ox as i64, y + *oy as i64, crate::color_of(px))?;
+		}
+		out.push_str("\n]}\n");
+		std::fs::write(path, out)
+			.with_context(|| format!("failed to write {}", path.display()))
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn export()
+	{
+		let order = DrawOrder::new((64, 48));
+		order.record(&Chunk::from("PX 1 2 ff0000\nPX 3 4 80\n".to_owned()), None);
+		order.record(&Chunk::from("PX 0 0 00ff00ff\n".to_owned()), Some((10, 20)));
+		assert_eq!(order.pixels(), 3);
+
+		let path = std::env::temp_dir().join(format!("pixelspray-draworder-{}", std::process::id()));
+		order.write(&path).unwrap();
+		let json = std::fs::read_to_string(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert!(json.starts_with("{\"canvas\":[64,48],\"pixels\":[\n["));
+		let pxls = json.lines().skip(1)
+			.filter_map(|line| line.trim_end_matches(',').strip_prefix('['))
+			.map(|line| line.split_once(',').unwrap().1)
+			.collect::<Vec<_>>();
+		assert_eq!(pxls, ["1,2,\"ff0000\"]", "3,4,\"80\"]", "10,20,\"00ff00ff\"]"]);
+	}
+}
@@ -0,0 +1,319 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use image::{DynamicImage, RgbaImage};
+use rand::Rng;
+
+use crate::transform::Transform;
+
+
+/// Time-varying modifications of a static image
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum Effect
+{
+	/// Random pixels light up
+	Sparkle,
+	/// Rows are shifted sideways in random bands
+	Glitch,
+	/// Opacity slowly pulses
+	Breathe,
+}
+
+/// Length of a breath in seconds
+const BREATHE_PERIOD: f32 = 4.0;
+
+impl Effect
+{
+	/// Applies the effect as it looks `t` seconds in
+	pub fn apply(self, image: &DynamicImage, t: f32) -> DynamicImage
+	{
+		let mut rng = rand::thread_rng();
+		let mut image = image.to_rgba8();
+		match self {
+			Effect::Sparkle => {
+				for px in image.pixels_mut() {
+					if rng.gen_bool(0.02) {
+						for c in px.0[..3].iter_mut() {
+							*c += ((0xff - *c) as f32 * 0.8) as u8;
+						}
+					}
+				}
+			},
+			Effect::Glitch => {
+				let (w, h) = image.dimensions();
+				let src = image.clone();
+				for _ in 0..rng.gen_range(1..=4) {
+					let y0 = rng.gen_range(0..h);
+					let y1 = (y0 + rng.gen_range(1..=h / 10 + 1)).min(h);
+					let dx = rng.gen_range(0..=w / 10);
+					shift_rows(&src, &mut image, y0..y1, dx);
+				}
+			},
+			Effect::Breathe => {
+				let f = 0.6 + 0.4 * (t * std::f32::consts::TAU / BREATHE_PERIOD).cos();
+				for px in image.pixels_mut() {
+					px.0[3] = (px.0[3] as f32 * f) as u8;
+				}
+			},
+		}
+		DynamicImage::ImageRgba8(image)
+	}
+}
+
+impl Transform for Effect
+{
+	fn apply(&mut self, image: DynamicImage, t: f32) -> anyhow::Result<DynamicImage>
+	{
+		Ok(Effect::apply(*self, &image, t))
+	}
+
+	fn animated(&self, _t: f32) -> bool
+	{
+		true
+	}
+}
+
+/// How the image appears at first
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum RevealKind
+{
+	/// Colors brighten from black
+	Fade,
+	/// Rows appear from top to bottom
+	Scanline,
+	/// Pixels appear along a spiral from the center
+	Spiral,
+	/// Pixels appear in random order
+	Random,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct Reveal
+{
+	pub kind: RevealKind,
+	pub duration: Duration,
+}
+
+/// Parses `kind:duration`, e.g. `fade:3s`
+pub fn parse_reveal(s: &str) -> Result<Reveal, String>
+{
+	let (kind, duration) = s.split_once(':')
+		.ok_or_else(|| format!("expected kind:duration, got '{}'", s))?;
+	let kind = RevealKind::from_str(kind, true)?;
+	let duration = humantime::parse_duration(duration)
+		.map_err(|err| format!("invalid duration '{}': {}", duration, err))?;
+	Ok(Reveal { kind, duration })
+}
+
+impl Reveal
+{
+	/// Whether the image is fully shown `t` seconds in
+	pub fn done(&self, t: f32) -> bool
+	{
+		t >= self.duration.as_secs_f32()
+	}
+
+	/// Hides what is not revealed yet `t` seconds in by making it transparent or dark
+	pub fn apply(&self, image: &DynamicImage, t: f32) -> DynamicImage
+	{
+		let p = (t / self.duration.as_secs_f32()).clamp(0.0, 1.0);
+		let mut image = image.to_rgba8();
+		let (w, h) = image.dimensions();
+		let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+		let radius = cx.hypot(cy).max(1.0);
+		for (x, y, px) in image.enumerate_pixels_mut() {
+			// when the pixel appears, from 0 to 1
+			let at = match self.kind {
+				RevealKind::Fade => {
+					// exponential, so dark pixels are sent first and brightened
+					let f = (2f32.powf(10.0 * p) - 1.0) / 1023.0;
+					for c in px.0[..3].iter_mut() {
+						*c = (*c as f32 * f) as u8;
+					}
+					continue;
+				},
+				RevealKind::Scanline => y as f32 / h as f32,
+				RevealKind::Spiral => {
+					let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+					let turn = (dy.atan2(dx) + std::f32::consts::PI) / std::f32::consts::TAU;
+					(dx.hypot(dy) / radius * SPIRAL_TURNS + turn) / (SPIRAL_TURNS + 1.0)
+				},
+				RevealKind::Random => {
+					let hash = (x.wrapping_mul(0x9e37_79b1) ^ y.wrapping_mul(0x85eb_ca77)).wrapping_mul(0xc2b2_ae3d);
+					(hash >> 8) as f32 / (1 << 24) as f32
+				},
+			};
+			if at >= p {
+				px.0[3] = 0;
+			}
+		}
+		DynamicImage::ImageRgba8(image)
+	}
+}
+
+impl Transform for Reveal
+{
+	fn apply(&mut self, image: DynamicImage, t: f32) -> anyhow::Result<DynamicImage>
+	{
+		if self.done(t) {
+			return Ok(image);
+		}
+		Ok(Reveal::apply(self, &image, t))
+	}
+
+	fn animated(&self, t: f32) -> bool
+	{
+		!self.done(t)
+	}
+}
+
+/// Dims and warms the image at night, following the local time of day
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct AutoDim
+{
+	pub sunset: chrono::NaiveTime,
+	pub sunrise: chrono::NaiveTime,
+	/// Brightness at night from 0 to 1
+	pub night: f32,
+	/// How long the change after sunset and sunrise takes
+	pub fade: Duration,
+}
+
+/// Parses `sunset=18:00,sunrise=06:00` with optional `night=0.3` and `fade=1h`
+pub fn parse_auto_dim(s: &str) -> Result<AutoDim, String>
+{
+	let time = |v: &str| chrono::NaiveTime::parse_from_str(v, "%H:%M")
+		.map_err(|err| format!("invalid time '{}': {}", v, err));
+	let (mut sunset, mut sunrise) = (None, None);
+	let mut dim = AutoDim { sunset: Default::default(), sunrise: Default::default(), night: 0.3, fade: Duration::from_secs(3600) };
+	for part in s.split(',') {
+		let (key, value) = part.split_once('=')
+			.ok_or_else(|| format!("expected key=value, got '{}'", part))?;
+		match key {
+			"sunset" => sunset = Some(time(value)?),
+			"sunrise" => sunrise = Some(time(value)?),
+			"night" => dim.night = value.parse::<f32>().ok().filter(|v| (0.0..=1.0).contains(v))
+				.ok_or_else(|| format!("invalid night brightness '{}', expected 0 to 1", value))?,
+			"fade" => dim.fade = humantime::parse_duration(value)
+				.map_err(|err| format!("invalid duration '{}': {}", value, err))?,
+			_ => return Err(format!("unknown key '{}'", key)),
+		}
+	}
+	dim.sunset = sunset.ok_or("missing sunset")?;
+	dim.sunrise = sunrise.ok_or("missing sunrise")?;
+	Ok(dim)
+}
+
+impl AutoDim
+{
+	/// Brightness at the time of day `now`, fading down after sunset and up after sunrise
+	pub fn level(&self, now: chrono::NaiveTime) -> f32
+	{
+		const DAY: i64 = 24 * 3600;
+		let since = |at: chrono::NaiveTime| (now - at).num_seconds().rem_euclid(DAY) as f32;
+		let night = (self.sunrise - self.sunset).num_seconds().rem_euclid(DAY) as f32;
+		let fade = self.fade.as_secs_f32().max(1.0);
+		let level = if since(self.sunset) < night {
+			self.night + (1.0 - self.night) * (1.0 - since(self.sunset) / fade).max(0.0)
+		} else {
+			1.0 - (1.0 - self.night) * (1.0 - since(self.sunrise) / fade).max(0.0)
+		};
+		// in steps, so the image does not change on every render
+		(level * 100.0).round() / 100.0
+	}
+
+	pub fn apply(&self, image: &DynamicImage, level: f32) -> DynamicImage
+	{
+		let mut image = image.to_rgba8();
+		// how far into the night, for a warmer tint
+		let warm = if self.night < 1.0 { (1.0 - level) / (1.0 - self.night) } else { 0.0 };
+		let scale = [level, level * (1.0 - 0.2 * warm), level * (1.0 - 0.5 * warm)];
+		for px in image.pixels_mut() {
+			for (c, f) in px.0[..3].iter_mut().zip(scale) {
+				*c = (*c as f32 * f).round() as u8;
+			}
+		}
+		DynamicImage::ImageRgba8(image)
+	}
+}
+
+impl Transform for AutoDim
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let level = self.level(chrono::Local::now().time());
+		if level >= 1.0 {
+			return Ok(image);
+		}
+		Ok(AutoDim::apply(self, &image, level))
+	}
+
+	fn animated(&self, _t: f32) -> bool
+	{
+		true
+	}
+}
+
+/// Windings of the spiral reveal
+const SPIRAL_TURNS: f32 = 3.0;
+
+/// Copies rows of `src` into `dst`, rotated right by `dx`
+fn shift_rows(src: &RgbaImage, dst: &mut RgbaImage, rows: std::ops::Range<u32>, dx: u32)
+{
+	let w = src.width();
+	for y in rows {
+		for x in 0..w {
+			dst.put_pixel((x + dx) % w, y, *src.get_pixel(x, y));
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use image::GenericImageView;
+
+	#[test]
+	fn reveal()
+	{
+		assert!(parse_reveal("fade").is_err());
+		assert!(parse_reveal("wipe:1s").is_err());
+		let reveal = parse_reveal("scanline:2s").unwrap();
+		assert_eq!(reveal, Reveal { kind: RevealKind::Scanline, duration: Duration::from_secs(2) });
+
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, image::Rgba([200, 100, 50, 0xff])));
+		let half = reveal.apply(&image, 1.0);
+		assert_eq!(half.get_pixel(0, 1).0[3], 0xff);
+		assert_eq!(half.get_pixel(0, 2).0[3], 0);
+		assert!(reveal.done(2.0));
+		assert_eq!(reveal.apply(&image, 2.0), image);
+
+		let fade = Reveal { kind: RevealKind::Fade, duration: Duration::from_secs(1) };
+		assert_eq!(fade.apply(&image, 0.0).get_pixel(0, 0).0, [0, 0, 0, 0xff]);
+		assert_eq!(fade.apply(&image, 1.0), image);
+	}
+
+	#[test]
+	fn auto_dim()
+	{
+		assert!(parse_auto_dim("sunset=18:00").is_err());
+		assert!(parse_auto_dim("sunset=18:00,sunrise=6am").is_err());
+		assert!(parse_auto_dim("sunset=18:00,sunrise=06:00,night=2").is_err());
+		let dim = parse_auto_dim("sunset=18:00,sunrise=06:00,night=0.2,fade=1h").unwrap();
+		assert_eq!(dim.night, 0.2);
+
+		let at = |h, m| chrono::NaiveTime::from_hms_opt(h, m, 0).unwrap();
+		assert_eq!(dim.level(at(12, 0)), 1.0);
+		assert_eq!(dim.level(at(18, 30)), 0.6);
+		assert_eq!(dim.level(at(23, 0)), 0.2);
+		assert_eq!(dim.level(at(3, 0)), 0.2);
+		assert_eq!(dim.level(at(6, 30)), 0.6);
+		assert_eq!(dim.level(at(7, 0)), 1.0);
+
+		// blue fades the most at night
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, image::Rgba([200, 200, 200, 0xff])));
+		assert_eq!(dim.apply(&image, 0.2).get_pixel(0, 0).0, [40, 32, 20, 0xff]);
+	}
+}
@@ -0,0 +1,115 @@
+use std::fmt::Write;
+
+use crate::Frame;
+
+
+/// Most connections recommended, beyond that servers tend to refuse more
+const MAX_CONNECTIONS: usize = 64;
+
+/// Parses a speed in bits per second like `100M`, `1G`, `512k` or `2.5Mbit`
+pub fn parse_speed(s: &str) -> Result<f64, String>
+{
+	let digits = s.trim_end_matches("bit").trim_end_matches("bps");
+	let (number, unit) = match digits.char_indices().last() {
+		Some((i, c)) if c.is_ascii_alphabetic() => (&digits[..i], Some(c)),
+		_ => (digits, None),
+	};
+	let scale = match unit.map(|c| c.to_ascii_lowercase()) {
+		None => 1e0,
+		Some('k') => 1e3,
+		Some('m') => 1e6,
+		Some('g') => 1e9,
+		Some(_) => return Err(format!("unknown unit in '{}', expected k, M or G", s)),
+	};
+	let n = number.parse::<f64>().map_err(|err| format!("invalid speed '{}': {}", s, err))?;
+	if !(n > 0.0 && n.is_finite()) {
+		return Err(format!("{} is not a positive speed", s));
+	}
+	Ok(n * scale)
+}
+
+/// What a full repaint of a frame costs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate
+{
+	pub pixels: u64,
+	pub bytes: u64,
+}
+
+impl Estimate
+{
+	pub fn new(lanes: &Frame) -> Self
+	{
+		let chunks = lanes.iter().flatten();
+		Self {
+			pixels: chunks.clone().map(|chunk| chunk.lines().count() as u64).sum(),
+			bytes: chunks.map(|chunk| chunk.len() as u64).sum(),
+		}
+	}
+
+	/// Repaints per second over a link of `link` bits per second
+	pub fn repaints(&self, link: f64) -> f64
+	{
+		link / 8.0 / self.bytes.max(1) as f64
+	}
+
+	/// Connections needed to fill the link at `connection` bits per second each
+	pub fn connections(&self, link: f64, connection: f64) -> usize
+	{
+		((link / connection).ceil() as usize).clamp(1, MAX_CONNECTIONS)
+	}
+
+	/// A report in the layout of `info`
+	pub fn report(&self, link: f64, connection: f64) -> String
+	{
+		let repaints = self.repaints(link);
+		let mut out = String::new();
+		writeln!(out, "Repaint:     {} pixels, {:.2} MB", self.pixels, self.bytes as f64 / 1e6).unwrap();
+		writeln!(out, "Link:        {:.1} Mbit/s, {:.2} repaints/s, {:.2?} each",
+			link / 1e6, repaints, std::time::Duration::from_secs_f64(1.0 / repaints)).unwrap();
+		write!(out, "Recommended: -n {} at {:.1} Mbit/s per connection", self.connections(link, connection), connection / 1e6).unwrap();
+		// a cycle should not take long to repair what others painted over
+		if repaints < 1.0 {
+			write!(out, ", --sample-rate {:.2} for a cycle per second", repaints).unwrap();
+		}
+		out.push('\n');
+		out
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::store::Chunk;
+
+	#[test]
+	fn speeds()
+	{
+		assert_eq!(parse_speed("100M"), Ok(100e6));
+		assert_eq!(parse_speed("2.5Mbit"), Ok(2.5e6));
+		assert_eq!(parse_speed("1G"), Ok(1e9));
+		assert_eq!(parse_speed("512kbps"), Ok(512e3));
+		assert_eq!(parse_speed("9600"), Ok(9600.0));
+		assert!(parse_speed("100X").is_err());
+		assert!(parse_speed("0M").is_err());
+		assert!(parse_speed("fast").is_err());
+	}
+
+	#[test]
+	fn report()
+	{
+		let chunk = Chunk::from("PX 1 2 ff0000\n".repeat(1000));
+		let estimate = Estimate::new(&vec![ vec![ chunk.clone(); 10 ], vec![ chunk ] ]);
+		assert_eq!(estimate, Estimate { pixels: 11_000, bytes: 154_000 });
+		assert_eq!(estimate.connections(100e6, 10e6), 10);
+		assert_eq!(estimate.connections(1e9, 1e6), MAX_CONNECTIONS);
+
+		let report = estimate.report(1e6, 1e6);
+		assert!(report.contains("11000 pixels, 0.15 MB"), "{}", report);
+		assert!(report.contains("0.81 repaints/s, 1.23s each"), "{}", report);
+		assert!(report.contains("-n 1 at 1.0 Mbit/s per connection, --sample-rate 0.81"), "{}", report);
+		assert!(!estimate.report(100e6, 10e6).contains("--sample-rate"));
+	}
+}
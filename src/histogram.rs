@@ -0,0 +1,128 @@
+use std::{
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use tokio::time;
+use tracing as log;
+
+
+/// Buckets per doubling of the duration
+const STEPS: f64 = 4.0;
+/// Enough buckets for durations up to about 18 minutes in nanoseconds
+const BUCKETS: usize = 160;
+
+/// Counts of durations in logarithmic buckets, about 19% apart
+#[derive(Debug)]
+pub struct Histogram
+{
+	buckets: [AtomicU64; BUCKETS],
+}
+
+impl Default for Histogram
+{
+	fn default() -> Self
+	{
+		Self { buckets: std::array::from_fn(|_| AtomicU64::new(0)) }
+	}
+}
+
+impl Histogram
+{
+	pub fn record(&self, d: Duration)
+	{
+		let ns = d.as_nanos().max(1) as f64;
+		let bucket = ((ns.log2() * STEPS) as usize).min(BUCKETS - 1);
+		self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Takes the counts recorded so far, starting over
+	pub fn take(&self) -> Counts
+	{
+		Counts(self.buckets.iter().map(|bucket| bucket.swap(0, Ordering::Relaxed)).collect())
+	}
+}
+
+/// Bucket counts taken from a histogram
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counts(Vec<u64>);
+
+impl Counts
+{
+	pub fn total(&self) -> u64
+	{
+		self.0.iter().sum()
+	}
+
+	/// Upper bound of the duration that a fraction `q` of the records is at most
+	pub fn quantile(&self, q: f64) -> Option<Duration>
+	{
+		let rank = (q * self.total() as f64).ceil().max(1.0) as u64;
+		let mut seen = 0;
+		let bucket = self.0.iter().position(|&n| {
+			seen += n;
+			seen >= rank
+		})?;
+		Some(Duration::from_nanos(2f64.powf((bucket + 1) as f64 / STEPS) as u64))
+	}
+
+	/// p50, p95 and p99, or `None` without records
+	fn summary(&self) -> Option<String>
+	{
+		Some(format!("p50 {:.2?}, p95 {:.2?}, p99 {:.2?}", self.quantile(0.5)?, self.quantile(0.95)?, self.quantile(0.99)?))
+	}
+}
+
+/// Timings of the painting, to make scheduling changes measurable
+#[derive(Debug, Default)]
+pub struct Timings
+{
+	/// How long sending a chunk took
+	pub chunks: Histogram,
+	/// How long a full pass over the image took
+	pub cycles: Histogram,
+}
+
+/// Logs the quantiles of the timings recorded since the last report every `interval`
+pub async fn report(timings: Arc<Timings>, interval: Duration)
+{
+	let mut ticker = time::interval(interval);
+	ticker.tick().await;
+	loop {
+		ticker.tick().await;
+		if let Some(chunks) = timings.chunks.take().summary() {
+			log::info!("chunk sends: {}", chunks);
+		}
+		if let Some(cycles) = timings.cycles.take().summary() {
+			log::info!("full repaints: {}", cycles);
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn quantiles()
+	{
+		let histogram = Histogram::default();
+		assert_eq!(histogram.take().quantile(0.5), None);
+		for ms in 1..=100 {
+			histogram.record(Duration::from_millis(ms));
+		}
+		let counts = histogram.take();
+		assert_eq!(counts.total(), 100);
+		// within a bucket of the exact values
+		for (q, ms) in [(0.5, 50.0), (0.95, 95.0), (0.99, 99.0)] {
+			let d = counts.quantile(q).unwrap().as_secs_f64() * 1e3;
+			assert!(d >= ms && d < ms * 1.2, "p{} is {}ms", q * 100.0, d);
+		}
+		assert_eq!(histogram.take().total(), 0);
+	}
+}
@@ -0,0 +1,178 @@
+use std::{
+	future::Future,
+	net::SocketAddr,
+	time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net,
+	time,
+};
+use tracing as log;
+
+use crate::{probe, profile};
+
+
+/// Number of connects to measure the latency with
+const CONNECT_SAMPLES: usize = 5;
+/// How long a connection has to survive to count as accepted
+const ACCEPT_WAIT: Duration = Duration::from_secs(1);
+
+/// Probes what a server supports and how fast it is, and prints a report
+pub async fn info(host: SocketAddr, max_connections: usize, duration: Duration) -> anyhow::Result<()>
+{
+	log::info!("connecting to {}...", host);
+	let mut latencies = Vec::with_capacity(CONNECT_SAMPLES);
+	for _ in 0..CONNECT_SAMPLES {
+		let start = Instant::now();
+		net::TcpStream::connect(host).await
+			.context("failed to connect")?;
+		latencies.push(start.elapsed());
+	}
+
+	let profiles = profile::builtin();
+	let stream = net::TcpStream::connect(host).await
+		.context("failed to connect")?;
+	let (_, probed) = probe::canvas(stream, &profiles, None, true).await;
+	let server = profile::detect(&profiles, &probed.help);
+
+	log::info!("finding the connection limit...");
+	let (connections, capped) = search(max_connections, |n| accepts(host, n)).await;
+
+	log::info!("measuring throughput for {:?}...", duration);
+	let (bytes, pixels) = throughput(host, duration).await?;
+
+	println!("Server:      {} ({})", host, server.map_or("unknown type", |profile| profile.name.as_str()));
+	println!("Canvas:      {}x{} from {}", probed.size.0, probed.size.1, probed.strategy);
+	println!("Connect:     min {:.2?}, avg {:.2?}",
+		latencies.iter().min().unwrap(),
+		latencies.iter().sum::<Duration>() / latencies.len() as u32);
+	println!("Connections: {}{} in parallel", if capped { "at least " } else { "" }, connections);
+	println!("Throughput:  {:.2} MB/s, {:.0} pixels/s on one connection",
+		bytes as f64 / duration.as_secs_f64() / 1e6,
+		pixels as f64 / duration.as_secs_f64());
+	if !probed.help.is_empty() {
+		println!("HELP:");
+		for line in probed.help.iter() {
+			println!("  {}", line);
+		}
+	}
+	Ok(())
+}
+
+/// Finds the most connections up to `max` that `accepts`, by doubling and then bisecting
+///
+/// Returns whether the search stopped at `max`.
+pub async fn search<F>(max: usize, mut accepts: impl FnMut(usize) -> F) -> (usize, bool)
+where
+	F: Future<Output = bool>,
+{
+	// one connection is known to work
+	let (mut good, mut bad) = (1, None);
+	while good < max {
+		let n = (good * 2).min(max);
+		if accepts(n).await {
+			good = n;
+		} else {
+			bad = Some(n);
+			break;
+		}
+	}
+	let Some(mut bad) = bad else {
+		return (good, true);
+	};
+	while bad - good > 1 {
+		let n = (good + bad) / 2;
+		if accepts(n).await {
+			good = n;
+		} else {
+			bad = n;
+		}
+	}
+	(good, false)
+}
+
+/// Whether the server keeps `n` connections open at once
+pub async fn accepts(host: SocketAddr, n: usize) -> bool
+{
+	let connects = (0..n).map(|_| time::timeout(ACCEPT_WAIT, net::TcpStream::connect(host)));
+	let streams = match futures::future::join_all(connects).await.into_iter().collect::<Result<Result<Vec<_>, _>, _>>() {
+		Ok(Ok(streams)) => streams,
+		_ => return false,
+	};
+	// rejected connections are closed right away
+	let alive = streams.into_iter().map(|mut stream| async move {
+		if stream.write_all(b"SIZE\n").await.is_err() {
+			return false;
+		}
+		let mut buf = [0; 64];
+		!matches!(time::timeout(ACCEPT_WAIT, stream.read(&mut buf)).await, Ok(Ok(0)) | Ok(Err(_)))
+	});
+	let alive = futures::future::join_all(alive).await;
+	log::debug!("{} connections: {}", n, if alive.iter().all(|&a| a) { "ok" } else { "rejected" });
+	alive.into_iter().all(|a| a)
+}
+
+/// Sends pixels over one connection for `duration`, returning the bytes and pixels sent
+///
+/// The pixel at the origin is set to the color it already has, to leave the canvas as is.
+async fn throughput(host: SocketAddr, duration: Duration) -> anyhow::Result<(u64, u64)>
+{
+	let [r, g, b] = probe::grab(host, &[(0, 0)]).await?
+		.get(&(0, 0)).copied()
+		.unwrap_or_default();
+	let line = format!("PX 0 0 {:02x}{:02x}{:02x}\n", r, g, b);
+	let buf = line.repeat((64 << 10) / line.len());
+
+	let mut stream = net::TcpStream::connect(host).await
+		.context("failed to connect")?;
+	let (rd, mut wr) = stream.split();
+	// keep the receive buffer of the server from filling up with replies
+	let drain = async {
+		let mut rd = rd;
+		let mut sink = [0; 4096];
+		while matches!(rd.read(&mut sink).await, Ok(n) if n > 0) {}
+	};
+	let send = async {
+		let mut bytes = 0;
+		let end = Instant::now() + duration;
+		while Instant::now() < end {
+			match time::timeout_at(end.into(), wr.write_all(buf.as_bytes())).await {
+				Ok(res) => res.context("failed to send pixels")?,
+				Err(_) => break,
+			}
+			bytes += buf.len() as u64;
+		}
+		anyhow::Ok(bytes)
+	};
+	let bytes = futures::future::select(Box::pin(send), Box::pin(drain)).await;
+	let bytes = match bytes {
+		futures::future::Either::Left((bytes, _)) => bytes?,
+		futures::future::Either::Right(_) => anyhow::bail!("server closed the connection"),
+	};
+	Ok((bytes, bytes / line.len() as u64))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[tokio::test]
+	async fn connection_limit()
+	{
+		let tried = std::cell::RefCell::new(Vec::new());
+		let res = search(100, |n| {
+			tried.borrow_mut().push(n);
+			futures::future::ready(n <= 37)
+		}).await;
+		assert_eq!(res, (37, false));
+		assert_eq!(tried.borrow()[..7], [2, 4, 8, 16, 32, 64, 48]);
+
+		assert_eq!(search(10, |_| futures::future::ready(true)).await, (10, true));
+		assert_eq!(search(10, |_| futures::future::ready(false)).await, (1, false));
+	}
+}
@@ -0,0 +1,70 @@
+use std::{io, net::SocketAddr};
+
+use tokio::net;
+
+
+/// Connects through the network device `interface`, whatever the routes say
+pub async fn connect(addr: SocketAddr, interface: &str) -> io::Result<net::TcpStream>
+{
+	let socket = if addr.is_ipv4() { net::TcpSocket::new_v4()? } else { net::TcpSocket::new_v6()? };
+	bind(&socket, addr, interface)?;
+	socket.connect(addr).await
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind(socket: &net::TcpSocket, _addr: SocketAddr, interface: &str) -> io::Result<()>
+{
+	socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(target_os = "macos")]
+fn bind(socket: &net::TcpSocket, addr: SocketAddr, interface: &str) -> io::Result<()>
+{
+	use std::os::unix::io::AsRawFd;
+
+	let name = std::ffi::CString::new(interface)?;
+	// SAFETY: the name is a valid C string for the duration of the call
+	let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+	if index == 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let (level, option) = match addr {
+		SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_BOUND_IF),
+		SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_BOUND_IF),
+	};
+	// SAFETY: the option value points to an int of the given size
+	let res = unsafe {
+		libc::setsockopt(socket.as_raw_fd(), level, option,
+			&index as *const _ as *const libc::c_void, std::mem::size_of_val(&index) as libc::socklen_t)
+	};
+	if res != 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn bind(_socket: &net::TcpSocket, _addr: SocketAddr, _interface: &str) -> io::Result<()>
+{
+	Err(io::Error::new(io::ErrorKind::Unsupported, "binding to a network device is only supported on Linux and macOS"))
+}
+
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests
+{
+	use super::*;
+
+	#[tokio::test]
+	async fn loopback()
+	{
+		let listener = net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		// binding to a device takes privileges on older kernels
+		match connect(addr, "lo").await {
+			Ok(stream) => assert_eq!(stream.peer_addr().unwrap(), addr),
+			Err(err) => assert_eq!(err.kind(), io::ErrorKind::PermissionDenied),
+		}
+		assert!(connect(addr, "no-such-device0").await.is_err());
+	}
+}
@@ -0,0 +1,69 @@
+use std::{fmt, sync::Arc};
+
+use image::RgbaImage;
+
+
+/// Regions of the canvas that must never be painted, like areas of the organizers
+///
+/// Marked on an image in canvas coordinates by opaque pixels that are not black,
+/// so both black and white maps and transparent overlays work.
+#[derive(Clone, PartialEq)]
+pub struct Keepout
+{
+	size: (u32, u32),
+	forbidden: Arc<Vec<bool>>,
+}
+
+impl Keepout
+{
+	pub fn new(map: &RgbaImage) -> Self
+	{
+		let forbidden = map.pixels()
+			.map(|px| px.0[3] >= 0x80 && px.0[..3].iter().any(|&c| c >= 0x80))
+			.collect();
+		Self { size: map.dimensions(), forbidden: Arc::new(forbidden) }
+	}
+
+	/// Whether the canvas pixel at `x`,`y` is off limits, which none outside of the map is
+	pub fn forbids(&self, x: u32, y: u32) -> bool
+	{
+		let (w, h) = self.size;
+		x < w && y < h && self.forbidden[(y * w + x) as usize]
+	}
+}
+
+impl fmt::Debug for Keepout
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		write!(f, "Keepout({}x{}, {} pixels)", self.size.0, self.size.1, self.forbidden.iter().filter(|&&px| px).count())
+	}
+}
+
+/// Loads the map at `path`
+pub fn parse(path: &str) -> Result<Keepout, String>
+{
+	let map = image::open(path)
+		.map_err(|err| format!("failed to read keepout map {}: {}", path, err))?;
+	Ok(Keepout::new(&map.into_rgba8()))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use image::Rgba;
+
+	#[test]
+	fn marks()
+	{
+		let colors = [[0xff, 0xff, 0xff, 0xff], [0, 0, 0, 0xff], [0xff, 0, 0, 0xff], [0xff, 0xff, 0xff, 0x10]];
+		let map = RgbaImage::from_fn(4, 1, |x, _| Rgba(colors[x as usize]));
+		let keepout = Keepout::new(&map);
+		assert_eq!((0..4).map(|x| keepout.forbids(x, 0)).collect::<Vec<_>>(), [true, false, true, false]);
+		assert!(!keepout.forbids(0, 1) && !keepout.forbids(4, 0));
+		assert_eq!(format!("{:?}", keepout), "Keepout(4x1, 2 pixels)");
+		assert!(parse("/nonexistent.png").is_err());
+	}
+}
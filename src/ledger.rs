@@ -0,0 +1,118 @@
+use std::{
+	collections::HashMap,
+	io::Write,
+	path::Path,
+	sync::Mutex,
+};
+
+use anyhow::Context;
+
+
+/// A chunk as it lands on the canvas
+#[derive(Debug, Clone, PartialEq)]
+struct Entry
+{
+	bytes: usize,
+	pixels: usize,
+	/// Covered region as `(x, y, w, h)` on the canvas
+	region: (i64, i64, i64, i64),
+	sends: u64,
+}
+
+impl Entry
+{
+	fn new(chunk: &str, (ox, oy): (u32, u32)) -> Self
+	{
+		let (mut x0, mut y0, mut x1, mut y1) = (i64::MAX, i64::MAX, i64::MIN, i64::MIN);
+		let mut pixels = 0;
+		for (x, y) in chunk.lines().map(crate::coords_of) {
+			let (x, y) = (x + ox as i64, y + oy as i64);
+			(x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+			pixels += 1;
+		}
+		let region = if pixels == 0 { (0, 0, 0, 0) } else { (x0, y0, x1 - x0 + 1, y1 - y0 + 1) };
+		Self { bytes: chunk.len(), pixels, region, sends: 0 }
+	}
+}
+
+/// CRC32 of the commands of a chunk and the OFFSET they are relative to
+type Key = (u32, (u32, u32));
+
+/// Sends per chunk, keyed by the CRC32 of its commands and its OFFSET,
+/// to tell which chunks a region missing on the canvas was in
+#[derive(Debug, Default)]
+pub struct Ledger
+{
+	entries: Mutex<HashMap<Key, Entry>>,
+}
+
+impl Ledger
+{
+	pub fn record(&self, chunk: &str, offset: Option<(u32, u32)>)
+	{
+		let offset = offset.unwrap_or((0, 0));
+		let crc = crc32fast::hash(chunk.as_bytes());
+		self.entries.lock().unwrap()
+			.entry((crc, offset))
+			.or_insert_with(|| Entry::new(chunk, offset))
+			.sends += 1;
+	}
+
+	pub fn summary(&self) -> String
+	{
+		let entries = self.entries.lock().unwrap();
+		let sends = entries.values().map(|entry| entry.sends).sum::<u64>();
+		let bytes = entries.values().map(|entry| entry.sends * entry.bytes as u64).sum::<u64>();
+		format!("{} chunks sent {} times, {:.1} MB in total", entries.len(), sends, bytes as f64 / 1e6)
+	}
+
+	/// Writes a line per chunk from the top left of the canvas to the bottom right
+	pub fn write(&self, path: &Path) -> anyhow::Result<()>
+	{
+		let mut entries = self.entries.lock().unwrap()
+			.iter()
+			.map(|(&(crc, _), entry)| (crc, entry.clone()))
+			.collect::<Vec<_>>();
+		entries.sort_by_key(|(crc, entry)| (entry.region.1, entry.region.0, *crc));
+
+		let mut out = Vec::new();
+		writeln!(out, "# crc32\tsends\tbytes\tpixels\tregion")?;
+		for (crc, entry) in entries {
+			let (x, y, w, h) = entry.region;
+			writeln!(out, "{:08x}\t{}\t{}\t{}\t{}x{}+{}+{}", crc, entry.sends, entry.bytes, entry.pixels, w, h, x, y)?;
+		}
+		std::fs::write(path, out)
+			.with_context(|| format!("failed to write {}", path.display()))
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn accounting()
+	{
+		let ledger = Ledger::default();
+		let chunk = "PX 1 2 ff\nPX 4 3 00\n";
+		ledger.record(chunk, None);
+		ledger.record(chunk, None);
+		ledger.record(chunk, Some((10, 0)));
+		ledger.record("PX 0 0 ff\n", None);
+		assert_eq!(ledger.summary(), "3 chunks sent 4 times, 0.0 MB in total");
+
+		let path = std::env::temp_dir().join(format!("pixelspray-ledger-{}", std::process::id()));
+		ledger.write(&path).unwrap();
+		let report = std::fs::read_to_string(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+		let crc = crc32fast::hash(chunk.as_bytes());
+		assert_eq!(report.lines().skip(1).map(|line| line.split_once('\t').unwrap().1).collect::<Vec<_>>(), [
+			"1\t10\t1\t1x1+0+0",
+			"2\t20\t2\t4x2+1+2",
+			"1\t20\t2\t4x2+11+2",
+		]);
+		assert!(report.contains(&format!("{:08x}\t2\t", crc)));
+	}
+}
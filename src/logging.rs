@@ -0,0 +1,157 @@
+use std::{
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use tracing_subscriber::{
+	filter::{EnvFilter, LevelFilter},
+	fmt,
+	layer::SubscriberExt,
+	util::SubscriberInitExt,
+	Layer,
+};
+
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum Format
+{
+	/// One JSON object per line
+	Json,
+	/// Multi-line human readable records
+	Pretty,
+}
+
+/// Log file that is rotated to `path.1`, `path.2`, ... once it exceeds `max_size` bytes
+pub struct RotatingFile
+{
+	path: PathBuf,
+	max_size: u64,
+	keep: usize,
+	file: fs::File,
+	size: u64,
+}
+
+impl RotatingFile
+{
+	pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Self>
+	{
+		let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+		let size = file.metadata()?.len();
+		Ok(Self { path: path.to_owned(), max_size, keep, file, size })
+	}
+
+	fn rotated(&self, n: usize) -> PathBuf
+	{
+		let mut path = self.path.clone().into_os_string();
+		path.push(format!(".{}", n));
+		path.into()
+	}
+
+	fn rotate(&mut self) -> io::Result<()>
+	{
+		self.file.flush()?;
+		if self.keep == 0 {
+			self.file.set_len(0)?;
+		} else {
+			for n in (1..self.keep).rev() {
+				let from = self.rotated(n);
+				if from.exists() {
+					fs::rename(&from, self.rotated(n + 1))?;
+				}
+			}
+			fs::rename(&self.path, self.rotated(1))?;
+			self.file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+		}
+		self.size = 0;
+		Ok(())
+	}
+}
+
+impl Write for RotatingFile
+{
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+	{
+		if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+			self.rotate()?;
+		}
+		let n = self.file.write(buf)?;
+		self.size += n as u64;
+		Ok(n)
+	}
+
+	fn flush(&mut self) -> io::Result<()>
+	{
+		self.file.flush()
+	}
+}
+
+/// Debug logs of the connections only with `workers`, as there are many of them
+fn filter(workers: bool) -> anyhow::Result<EnvFilter>
+{
+	Ok(EnvFilter::from_default_env()
+		.add_directive(LevelFilter::DEBUG.into())
+		.add_directive("pixelspray=debug".parse()?)
+		.add_directive(if workers { "pixelspray::worker=debug" } else { "pixelspray::worker=info" }.parse()?))
+}
+
+/// Logs to stderr and, if given, to a rotating file
+pub fn init(file: Option<&Path>, format: Format, max_size: u64, keep: usize, workers: bool) -> anyhow::Result<()>
+{
+	let file = match file {
+		Some(path) => {
+			let writer = Mutex::new(RotatingFile::open(path, max_size, keep)
+				.with_context(|| format!("failed to open log file {}", path.display()))?);
+			let layer = fmt::layer().with_ansi(false).with_writer(writer);
+			Some(match format {
+				Format::Json => layer.json().boxed(),
+				Format::Pretty => layer.pretty().boxed(),
+			})
+		},
+		None => None,
+	};
+
+	tracing_subscriber::registry()
+		.with(filter(workers)?)
+		.with(fmt::layer().compact().with_writer(std::io::stderr))
+		.with(file)
+		.init();
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn rotation()
+	{
+		let dir = std::env::temp_dir().join(format!("pixelspray-log-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("spray.log");
+
+		let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+		for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+			file.write_all(line.as_bytes()).unwrap();
+		}
+		file.flush().unwrap();
+
+		assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+		assert_eq!(fs::read_to_string(dir.join("spray.log.1")).unwrap(), "third\n");
+		assert_eq!(fs::read_to_string(dir.join("spray.log.2")).unwrap(), "second\n");
+		assert!(!dir.join("spray.log.3").exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn worker_logs()
+	{
+		assert!(filter(false).unwrap().to_string().contains("pixelspray::worker=info"));
+		assert!(filter(true).unwrap().to_string().contains("pixelspray::worker=debug"));
+	}
+}
@@ -1,60 +1,232 @@
+mod audit;
+mod buffer;
+mod capture;
+mod chaos;
+mod chunksize;
+mod clock;
+mod color;
+mod completions;
+mod config;
+mod conflict;
+mod contention;
+mod control;
+mod cycle;
+mod decay;
+mod dirty;
+mod draworder;
+mod effect;
+mod estimate;
+mod histogram;
+mod info;
+mod interface;
+mod keepout;
+mod ledger;
+mod logging;
+mod overlay;
+mod ownership;
+mod palette;
+mod pixel;
+mod placement;
+mod preview;
+mod probe;
+mod profile;
+mod progress;
+mod queue;
+mod ratelimit;
+mod remote;
+mod schedule;
+mod server;
+mod socks;
+mod source;
+mod store;
+mod supervisor;
+mod tee;
+mod timelapse;
+mod transform;
+mod trigger;
+mod tuning;
+
 use std::{
-	path::PathBuf,
+	fmt::Write,
 	str::FromStr,
-	sync::Arc, convert::TryInto,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
 };
 
 use anyhow::Context;
-use chrono::offset;
 use image::{Pixel, GenericImageView};
-use rand::seq::SliceRandom;
+use rand::{seq::SliceRandom, Rng};
 
-use clap::{Parser, ValueEnum};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 
 use futures::{
 	future::FutureExt,
 	stream::StreamExt,
-	sink::SinkExt,
 };
 use tokio::{*,
-	io::AsyncWriteExt,
+	io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt},
 };
-use tokio_util::codec::Decoder;
 
 use tracing as log;
+use tracing::Instrument;
+
+use store::Chunk;
+use transform::Transform;
 
 
 #[derive(Parser, Debug)]
 #[clap(about, version)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true, args_override_self = true)]
 struct Opt
 {
-	/// The host to connect to
-	#[arg()]
-	host: std::net::SocketAddr,
+	#[command(subcommand)]
+	command: Option<Command>,
+
+	/// The host to connect to (e.g. 127.0.0.1:1337), the connections take turns over all addresses of a name
+	#[arg(required_unless_present = "examples")]
+	host: Option<String>,
+
+	/// Address of the server in the other IP family, connections alternate between both
+	#[arg(long)]
+	dual_stack: Option<std::net::SocketAddr>,
+
+	/// Bind the connections to these network devices in turns, to add up the bandwidth of several links (e.g. eth0,eth1)
+	#[arg(long, value_delimiter = ',', conflicts_with = "socks5")]
+	interface: Vec<String>,
+
+	/// Connect through this SOCKS5 proxy, like Tor at 127.0.0.1:9050
+	#[arg(long)]
+	socks5: Option<std::net::SocketAddr>,
+
+	/// Log in to the proxy differently on every connection, so Tor builds a circuit for each
+	#[arg(long, requires = "socks5")]
+	isolate_circuits: bool,
+
+	/// Claim a region on servers with team support, e.g. 100x100+320x240:secret
+	#[arg(long, value_parser = parse_claim)]
+	claim: Option<Claim>,
 
 	/// Number of connections
 	#[arg(short = 'n', default_value_t = 8)]
 	num: usize,
 
-	/// Image to spray
-	#[arg(value_parser)]
-	image: PathBuf,
+	/// Find out how many connections the server accepts at once first and use no more than that
+	#[arg(long, conflicts_with = "socks5")]
+	preflight: bool,
+
+	/// Image to spray (path or http(s) URL)
+	#[arg(required_unless_present_any = ["source", "clock", "countdown", "examples"])]
+	image: Option<String>,
+
+	/// Live image source (e.g. mjpeg://camera/stream, raw:/dev/fb0:800x480:bgrx, fifo:/tmp/frames, screen:[x11|wayland], rtmp-listen://0.0.0.0:1935/live, ndi:NAME)
+	#[arg(long, conflicts_with = "image")]
+	source: Option<String>,
+
+	/// Show the time of day in white on black, sending only the digits that change
+	#[arg(long, conflicts_with_all = ["image", "source", "countdown"])]
+	clock: bool,
+
+	/// Count down to this local time (e.g. 2025-01-01T00:00:00), sending only the digits that change
+	#[arg(long, value_parser = clock::parse_time, conflicts_with_all = ["image", "source"])]
+	countdown: Option<chrono::DateTime<chrono::Local>>,
+
+	/// Show the images of the directory given as image one after the other, each for this long
+	#[arg(long, conflicts_with = "source")]
+	slideshow: Option<humantime::Duration>,
+
+	/// Rotate palette entries of an indexed PNG at this many entries per second (e.g. 32-47:10)
+	#[arg(long, value_parser = cycle::parse_range, conflicts_with_all = ["source", "slideshow"])]
+	palette_cycle: Vec<cycle::Range>,
+
+	/// Change slides with a transition (e.g. crossfade:2s)
+	#[arg(long, value_parser = transform::parse_transition, requires = "slideshow")]
+	transition: Option<transform::Transition>,
+
+	/// Limit the frame rate of live sources
+	#[arg(long, value_parser = parse_rate)]
+	max_fps: Option<f32>,
+
+	/// Only send pixels that changed since the previous frame
+	#[arg(long)]
+	delta: bool,
+
+	/// Skip pixels with every channel changed by less than this with --delta, which lag behind until they change more (e.g. 8 for noisy cameras)
+	#[arg(long, requires = "delta", value_parser = clap::value_parser!(u8).range(1..))]
+	delta_threshold: Option<u8>,
+
+	/// Reload the image periodically if it changed (e.g. 30s)
+	#[arg(long)]
+	refresh: Option<humantime::Duration>,
+
+	/// Resize image, in pixels or percent of the canvas, with _ for a side following the aspect ratio (e.g. 50%x50% or 800x_)
+	#[arg(short = 'r', value_parser = placement::parse_size)]
+	resize: Option<(Option<placement::Length>, Option<placement::Length>)>,
+
+	/// Fail instead of shrinking images larger than the canvas
+	#[arg(long)]
+	no_resize: bool,
+
+	/// Filter to scale the image with [default: lanczos, nearest for the low-power profile]
+	#[arg(long)]
+	scaling: Option<transform::Scaling>,
+
+	/// Scale heavily shrunk images to N times the size first, then average them down in linear light
+	#[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=8))]
+	supersample: u32,
+
+	/// Scale the sRGB levels as they are instead of in linear light, faster but darkening shrunk edges
+	#[arg(long)]
+	srgb_naive: bool,
+
+	/// Canvas size, skips asking the server
+	#[arg(long, value_parser = parse_size)]
+	canvas: Option<(u32, u32)>,
+
+	/// Server implementation to adapt to, or "auto" to detect it
+	#[arg(long)]
+	server_type: Option<String>,
+
+	/// File with additional server profiles
+	#[arg(long)]
+	profiles: Option<std::path::PathBuf>,
+
+	/// Offset of the image, with M for the middle and E for the end of the canvas (e.g. Ex0 or 10%x90%)
+	#[arg(short = 'o', value_parser = placement::parse_offset)]
+	offset: Option<placement::Offset>,
 
-	/// Resize image
-	#[arg(short = 'r')]
-	resize: Option<String>,
+	/// Put the image at this spot of the canvas
+	#[arg(long, conflicts_with = "offset")]
+	place: Option<placement::Anchor>,
 
-	/// Resize image
-	#[arg(short = 'o')]
-	offset: Option<String>,
+	/// Distance to keep from the edges the image is aligned to
+	#[arg(long, default_value_t = 0)]
+	margin: u32,
 
 	/// Filter to use
 	#[arg(short = 'f', default_value="rgba")]
 	filter: Filter,
 
-	/// Use a single color mask
-	#[arg(long = "filter-color", default_value_t=255)]
-	color: u8,
+	/// Color of the mask filter (e.g. #ff8000, rgb(255, 128, 0), orange or a grey level)
+	#[arg(long = "filter-color", default_value = "white")]
+	color: color::ColorArg,
+
+	/// Send only the outlines, with an optional threshold from 0 to 255
+	#[arg(long, num_args = 0..=1, default_missing_value = "64")]
+	edges: Option<u8>,
+
+	/// Color of the outlines instead of the image colors (e.g. red)
+	#[arg(long, requires = "edges")]
+	edge_color: Option<color::ColorArg>,
+
+	/// Remap the image for the canvas: polar, fisheye, mirror-x or custom-matrix:a,b,c,d,e,f
+	#[arg(long, value_parser = transform::parse_projection)]
+	map: Option<transform::Projection>,
+
+	/// Adjust the colors for color blind viewers or colored ambient light
+	#[arg(long)]
+	accessibility: Option<transform::Accessibility>,
 
 	/// Mirror image
 	#[arg(long)]
@@ -69,281 +241,3070 @@ struct Opt
 	#[arg(long = "no-offset")]
 	no_offset: bool,
 
-	/// Do not compact pixels
+	/// Corner the server counts its coordinates from
+	#[arg(long, default_value = "top-left")]
+	origin: Origin,
+
+	/// Number of the first row and column on the server
+	#[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u32).range(0..=1))]
+	index_base: u32,
+
+	/// Deprecated, same as --grey-tolerance 0 --alpha-threshold 1
 	#[arg(short = 'l', long)]
 	lossless: bool,
 
-	/// Do not compact pixels
+	/// How far a pixel may be from grey to be sent as grey with -c
+	#[arg(long, default_value = "channel")]
+	grey_metric: GreyMetric,
+
+	/// Largest distance by --grey-metric still sent as grey [default: 4 for channel, 2.3 for delta-e]
+	#[arg(long)]
+	grey_tolerance: Option<f32>,
+
+	/// Lowest alpha of the pixels sent [default: 16]
+	#[arg(long, value_parser = clap::value_parser!(u8).range(1..))]
+	alpha_threshold: Option<u8>,
+
+	/// Send fully transparent pixels too, for servers that do not blend
+	#[arg(long, conflicts_with = "alpha_threshold")]
+	keep_transparent: bool,
+
+	/// Send near grey pixels as grey
 	#[arg(short = 'c', long)]
-	same_ch_opt: bool
-}
+	same_ch_opt: bool,
 
-#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
-enum Filter
-{
-	Mask,
-	Grey,
-	RGBA,
-}
+	/// Order of the pixels in the stream
+	#[arg(long, default_value = "random")]
+	order: Order,
 
-fn main() -> Result<(), Box<dyn std::error::Error>>
-{
-	// Logging system init
-	tracing_subscriber::fmt()
-		.with_env_filter(
-			tracing_subscriber::EnvFilter::from_default_env()
-				.add_directive(tracing_subscriber::filter::LevelFilter::DEBUG.into())
-				.add_directive("pixelspray=debug".parse()?)
-				//.add_directive("hyper=trace".parse()?)
-		)
-		.compact()
-		.init();
+	/// Build every chunk from at least this many regions of the image, so a lost chunk leaves no clustered hole
+	#[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+	interleave: u32,
 
-	let opt = Opt::parse();
-	log::info!("pixelspray: {:?}", &opt);
+	/// Send every Nth pixel of each row and column first, then halve the spacing until all are in, so the whole image appears early
+	#[arg(long, value_parser = clap::value_parser!(u32).range(2..=256))]
+	soft_start: Option<u32>,
 
-	runtime::Builder::new_multi_thread()
-		.enable_all()
-		.build()?
-		.block_on(run(opt))
-}
+	/// Never paint where this canvas-sized image has opaque pixels that are not black
+	#[arg(long, value_parser = keepout::parse)]
+	keepout: Option<keepout::Keepout>,
 
-async fn run(opt: Opt) -> Result<(), Box<dyn std::error::Error>>
-{
-	let mut image = image::open(&opt.image)?;
-	if opt.mirror_v {
-		image = image::DynamicImage::ImageRgba8(image::imageops::flip_horizontal(&image));
-	}
-	if opt.mirror {
-		image = image::DynamicImage::ImageRgba8(image::imageops::flip_vertical(&image));
-	}
+	/// What to do with pixels outside of the canvas
+	#[arg(long, default_value = "clip")]
+	overflow: Overflow,
 
-	log::info!("connecting to {}...", opt.host);
+	/// Give each connection its own image tile and OFFSET
+	#[arg(long)]
+	tiles: bool,
 
-	let stream = net::TcpStream::connect(opt.host).await?;
-	let codec = tokio_util::codec::LinesCodec::new();
-	let mut stream = codec.framed(stream);
+	/// Spray a grid of NxM copies of the image, with an optional gap (e.g. 3x2:10)
+	#[arg(long, value_parser = parse_duplicate, conflicts_with = "tiles")]
+	duplicate: Option<(u32, u32, u32)>,
 
-	stream.send("SIZE".to_owned()).await?;
-	let res = stream.next().await
-	                .and_then(|res| res.ok());
-	let (sw,sh) = match res {
-		Some(s) => {
-			log::debug!("SIZE: {}", s);
-			let mut i = s.split_ascii_whitespace()
-						 .skip(1)
-			             .map(|s| u32::from_str(s).unwrap());
+	/// Send opaque grey pixels in the short 2-digit form, if the server takes it by its profile or by painting pixel 0,0 for a moment with `test`
+	#[arg(long, default_value = "auto")]
+	grey_wire: GreyWire,
 
-			let w = i.next().unwrap();
-			let h = i.next().unwrap();
-			(w, h)
-		},
-		None => (1024, 768),
-	};
-	let mut stream = stream.into_inner();
-	stream.shutdown().await.ok();
-	std::mem::drop(stream);
+	/// Shrink the commands: the grey form where the server takes it by --grey-wire, and no alpha on near opaque pixels
+	#[arg(long)]
+	wire_optimize: bool,
 
-	let (w,h) = image.dimensions();
+	/// Spray onto a built-in server bound to the host address and verify the result
+	#[arg(long)]
+	self_test: bool,
 
-	if let Some(resize) = opt.resize.as_ref() {
-		let mut i = resize.split('x').map(|s| u32::from_str(s).unwrap());
-		let w = i.next().unwrap();
-		let h = i.next().unwrap();
-		image = image.resize(w, h, image::imageops::FilterType::Lanczos3);
-	} else if  w > sw || h > sh {
-		image = image.resize(sw, sh, image::imageops::FilterType::Lanczos3);
-	}
+	/// Measure connection latency with SIZE every interval and favor fast connections
+	#[arg(long, value_parser = parse_period)]
+	ping: Option<humantime::Duration>,
 
-	let (w,h) = image.dimensions();
+	/// Send semi-transparent pixels only every Nth cycle, scaled by their alpha
+	#[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+	alpha_period: u32,
 
-	let (xoff,yoff) = if let Some(offset) = opt.offset.as_ref() {
-		let (xstr,ystr) = offset.split_once('x').unwrap();
-		//log::debug!("offsetp: {} x {}", xstr, ystr);
-		let x = match xstr {
-			"E" => sw-w,
-			"M" => (sw-w)/2,
-			_ => u32::from_str(xstr).unwrap(),	
-		};
-		let y = match ystr {
-			"E" => sh-h,
-			"M" => (sh-h)/2,
-			_ => u32::from_str(ystr).unwrap(),
-		};
-		(x,y)
-	} else {
-		(0,0)
-	};
+	/// Disguise the traffic: send decoy reads, skip random chunks each cycle and reconnect every interval
+	#[arg(long)]
+	stealth: Option<humantime::Duration>,
 
-	//image = image.resize(256, 256, image::FilterType::Nearest);
-	//image = image.grayscale();
+	/// Limit colors to those in this file, one RRGGBB per line, dithering over time
+	#[arg(long)]
+	target_palette: Option<std::path::PathBuf>,
 
-	log::info!("screen: {}x{} image: {}x{} offset: {}x{}", sw, sh, w, h, xoff, yoff);
+	/// Dither to black and white over the area, for e-ink and other 1-bit displays
+	#[arg(long)]
+	mono: bool,
 
-	let mut pxls = image.pixels()
-		.filter(|pixel|
-		{
-			let (_x, _y, color) = pixel;
-			let [_r,_g,_b,a]: [u8; 4] = color.channels()[..].try_into().unwrap();
-			if opt.lossless { a != 0 } else { a > 0xf }
-		})
-		.map(|(mut x, mut y, color)| {
+	/// Send the whole image once per interval and nothing in between, to match the refresh of a slow display (e.g. 30s)
+	#[arg(long, value_parser = parse_period)]
+	refresh_interval: Option<humantime::Duration>,
 
-			let [mut r,g,b,a]: [u8; 4] = color.to_rgba().channels()[..].try_into().unwrap();
-			let mut ch = color.channels().len();
+	/// Read the covered region back first and blend semi-transparent pixels with it, not with what the server does
+	#[arg(long, conflicts_with = "duplicate")]
+	composite: bool,
 
-			if opt.no_offset {
-				x += xoff;
-				y += yoff;
-			}
+	/// Read the covered region back first and paint it over again when exiting
+	#[arg(long)]
+	restore_on_exit: bool,
 
-			if ch > 3 && a == 0xff {
-				ch = 3;
-			}
+	/// Read back this fraction of the sent pixels and report how many are still ours
+	#[arg(long, value_parser = parse_fraction)]
+	verify: Option<f64>,
 
-			let mut filter = opt.filter;
-			if opt.same_ch_opt {
-				if filter != Filter::Mask {
-					if opt.lossless {
-						if r == g && g == b {
-							filter = Filter::Grey;
-						}
-					} else {
-						let rg = (r as i32 - g as i32).abs();
-						let gb  = (g as i32 - b as i32).abs();
-						let br  = (b as i32 - r as i32).abs();
-						if *[rg, gb, br].iter().max().unwrap() <= 4 {
-							r = ((r as usize + g as usize + b as usize) / 3) as u8;
-							filter = Filter::Grey;
-						}
-					}
-				}
-			}
+	/// Warn when more than this fraction of the verified pixels is overwritten for several reports in a row
+	#[arg(long, value_parser = parse_fraction, requires = "verify")]
+	conflict: Option<f64>,
 
-			match filter
-			{
-				Filter::Mask => format!("PX {} {} {:02X}\n", x, y, opt.color),
-				Filter::Grey => format!("PX {} {} {:02X}\n", x, y, r),
-				Filter::RGBA if ch == 3 => format!("PX {} {} {:02X}{:02X}{:02X}\n", x, y, r, g, b),
-				Filter::RGBA => format!("PX {} {} {:02X}{:02X}{:02X}{:02X}\n", x, y, r, g, b, a),
-			}
-		})
-		.collect::<Vec<_>>();
+	/// Report where others paint over the image on a grid of this many regions (e.g. 4x4)
+	#[arg(long, value_parser = parse_size, requires = "verify")]
+	contention: Option<(u32, u32)>,
 
-	pxls.shuffle(&mut rand::thread_rng());
+	/// Move the image to the most quiet free area of the canvas on a conflict
+	#[arg(long, requires = "conflict")]
+	retreat: bool,
 
-	let chunk_len = 1420; //(pxls.len() + pxls.len() % opt.num) / opt.num;
+	/// Use an extra connection for read-backs and SIZE re-probes, so the workers only write
+	#[arg(long)]
+	control: bool,
 
-	println!("Pixels: {}", pxls.len());
-	let chunks = pxls.into_iter()
-		.fold(vec![ String::with_capacity(chunk_len) ], |mut buf, px|
-		{
-			let mut chunk = buf.last_mut().unwrap();
-			if chunk.len() + px.len() > chunk_len {
-				buf.push(String::with_capacity(chunk_len));
-				chunk = buf.last_mut().unwrap();
-			}
-			chunk.push_str(&px);
-			buf
-		})
-		.into_iter().map(Arc::new)
-		.collect::<Vec<_>>();
+	/// Take commands like `swap-image PATH [--fade 1s]` on a Unix socket at this path
+	#[arg(long)]
+	control_socket: Option<std::path::PathBuf>,
 
-	let offset = (!opt.no_offset).then_some((xoff, yoff));
+	/// React to `stop`, `start` and image names from an MQTT topic (mqtt://broker/topic) or HTTP requests to an address (http://0.0.0.0:8080)
+	#[arg(long, value_parser = trigger::parse)]
+	trigger: Option<trigger::Trigger>,
 
-	println!("Chunks: {} a {}", chunks.len(), chunk_len);
-	println!("Offset: {}", offset.map(|(x,y)| format!("{x}x{y}")).unwrap_or_default());
+	/// Images --trigger can switch to by name (e.g. open=open.png)
+	#[arg(long, value_parser = trigger::parse_image, requires = "trigger")]
+	trigger_image: Vec<(String, std::path::PathBuf)>,
 
-	let mut tasks = futures::stream::FuturesUnordered::new();
-	let mut channels = std::collections::HashMap::new();
-	for id in 0..opt.num {
-		let (tx, task) = client(id, opt.host, offset);
-		channels.insert(id, tx);
-		tasks.push(task);
-	}
+	/// Send only a random fraction of the pixels each cycle, opaque ones more likely (e.g. 0.1)
+	#[arg(long, value_parser = parse_fraction)]
+	sample_rate: Option<f64>,
 
-	let state = Arc::new(sync::Mutex::new(channels));
-	let channels = state.clone();
-	spawn(async move {
-		let mut chunk_iter = chunks.into_iter().cycle();
-		loop {
-			let mut channels = channels.lock().await;
-/*			let sends = channels.values_mut()
-				.zip(chunk_iter.by_ref())
-				.map(|(tx, chunk)| tx.send(chunk.clone()));
+	/// Send only as much as needed to keep this share of the verified pixels, in percent or as fraction (e.g. 80%)
+	#[arg(long, value_parser = parse_share, requires = "verify", conflicts_with = "sample_rate")]
+	target_ownership: Option<f64>,
 
-			futures::future::select_all(sends).await;
-*/
-			let mut broken = Vec::new();
-			for ((&id, tx), chunk) in channels.iter_mut()
-				.zip(chunk_iter.by_ref())
-			{
-				if let Err(_err) = tx.send(chunk.clone()).await {
-					broken.push(id);
-				}
-			}
-			for id in broken {
-				channels.remove(&id);
-			}
-		}
-	});
+	/// Resend bright pixels more often on canvases fading with this half-life (e.g. 30s)
+	#[arg(long)]
+	decay_compensation: Option<humantime::Duration>,
 
-	let channels = state.clone();
-	loop {
-		futures::select! {
-			_ = signal::ctrl_c().fuse() => {
-				break;
-			},
-			id = tasks.next() => {
-				log::debug!("meh {:?}", id);
-				if let Some(Err(err)) = id {
-					continue;
-				}
-				match id {
-					None => break,
-					Some(Err(err)) => break,
-					Some(Ok(Ok(_))) => break,
-					Some(Ok(Err(err))) => break,
-					Some(Ok(Ok(id))) => {
-						log::info!("{}: respawning...", id);
-						let (tx, task) = client(id, opt.host, offset);
-						channels.lock().await.entry(id).and_modify(|v| *v = tx);
-						tasks.push(task);	
-					},
-				}
-			},
-		};
-	}
-	log::info!("stopping...");
-	Ok(())
+	/// Show the targeted canvas region with the image over it in a window, or next to it in the terminal without a display, read back every interval
+	#[arg(long, num_args = 0..=1, default_missing_value = "1s")]
+	preview: Option<humantime::Duration>,
+
+	/// Read the whole canvas back regularly and save it as numbered PNGs into this directory
+	#[arg(long)]
+	timelapse: Option<std::path::PathBuf>,
+
+	/// How often to save a timelapse frame
+	#[arg(long, default_value = "30s", requires = "timelapse")]
+	timelapse_interval: humantime::Duration,
+
+	/// Replay the commands of every frame locally and save what the server should show as numbered PNGs into this directory
+	#[arg(long)]
+	audit: Option<std::path::PathBuf>,
+
+	/// Keep the chunks in memory-mapped files in this directory, so the kernel can page them out
+	#[arg(long)]
+	mmap_chunks: Option<std::path::PathBuf>,
+
+	/// Give up a connection failing more often than this within the time (restarts/window)
+	#[arg(long, value_parser = supervisor::parse_budget, default_value = "5/60s")]
+	restart_budget: supervisor::Budget,
+
+	/// Testing: kill a connection before a batch with this chance
+	#[arg(long, hide = true, value_parser = parse_fraction, default_value = "0")]
+	chaos_drop_rate: f64,
+
+	/// Testing: garble a byte of a chunk with this chance
+	#[arg(long, hide = true, value_parser = parse_fraction, default_value = "0")]
+	chaos_corrupt_rate: f64,
+
+	/// Testing: kill every connection after about this time
+	#[arg(long, hide = true)]
+	chaos_reconnect_interval: Option<humantime::Duration>,
+
+	/// Testing: seed of the faults, so a run can be repeated
+	#[arg(long, hide = true, default_value_t = 0)]
+	chaos_seed: u64,
+
+	/// Replace every connection with a new one after this time, staggered (e.g. 60s)
+	#[arg(long, value_parser = parse_period)]
+	rotate_connections: Option<humantime::Duration>,
+
+	/// Chunks queued per connection, more join up to larger writes [default: 4]
+	#[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+	queue_depth: Option<u32>,
+
+	/// What to do with chunks for a full queue, animations may rather drop stale ones
+	#[arg(long, default_value = "block")]
+	drop_policy: queue::Policy,
+
+	/// Most queued chunks handed to the system in one vectored write
+	#[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(1..=1024))]
+	max_batch: u32,
+
+	/// Count the sends of every chunk and write them with the region each covers to this file when exiting
+	#[arg(long)]
+	chunk_report: Option<std::path::PathBuf>,
+
+	/// Write the pixels of the first pass in the order they were sent, each with its time, to this JSON file when exiting
+	#[arg(long)]
+	export_draw_order: Option<std::path::PathBuf>,
+
+	/// Count what the servers send back, like OK or echoed pixels, and log it regularly
+	#[arg(long)]
+	count_inbound: bool,
+
+	/// Copy the sent commands to this file, or to stdout with -
+	#[arg(long)]
+	tee: Option<std::path::PathBuf>,
+
+	/// Shuffle the pixels anew every N cycles
+	#[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+	reshuffle_every: Option<u32>,
+
+	/// Blend new images of live or refreshed sources in over this time (e.g. 500ms)
+	#[arg(long)]
+	interpolate: Option<humantime::Duration>,
+
+	/// Animate the image with an effect
+	#[arg(long)]
+	effect: Option<effect::Effect>,
+
+	/// How often to render the effect
+	#[arg(long, default_value = "100ms", value_parser = parse_period)]
+	effect_interval: humantime::Duration,
+
+	/// Draw the image the first time with an animation (e.g. fade:3s, scanline, spiral, random)
+	#[arg(long, value_parser = effect::parse_reveal)]
+	reveal: Option<effect::Reveal>,
+
+	/// Dim and warm the image at night for projectors (e.g. sunset=18:00,sunrise=06:00,night=0.3,fade=1h)
+	#[arg(long, value_parser = effect::parse_auto_dim)]
+	auto_dim: Option<effect::AutoDim>,
+
+	/// Draw a HUD into the top left corner of the frames, rendered every --effect-interval (e.g. fps,timestamp)
+	#[arg(long, value_delimiter = ',')]
+	overlay: Vec<overlay::Hud>,
+
+	/// Preset for the kind of machine pixelspray runs on, the options given still win
+	#[arg(long)]
+	profile: Option<Preset>,
+
+	/// Number of runtime worker threads (default: one per core)
+	#[arg(long)]
+	worker_threads: Option<usize>,
+
+	/// Maximum number of threads for blocking work like image encoding
+	#[arg(long)]
+	blocking_threads: Option<usize>,
+
+	/// Pin the runtime threads to these cores (e.g. 2,3,4,5)
+	#[arg(long, value_delimiter = ',')]
+	pin_cores: Vec<usize>,
+
+	/// Log scheduler statistics every interval
+	#[arg(long)]
+	runtime_stats: Option<humantime::Duration>,
+
+	/// Do not keep and log the quantiles of the send timings
+	#[arg(long)]
+	no_timings: bool,
+
+	/// Read options from this file first, one per line, and again on SIGHUP
+	#[arg(long)]
+	config: Option<std::path::PathBuf>,
+
+	/// Also write logs to this file
+	#[arg(long)]
+	log_file: Option<std::path::PathBuf>,
+
+	/// Format of the log file
+	#[arg(long, default_value = "json")]
+	log_format: logging::Format,
+
+	/// Rotate the log file once it grows beyond this many bytes
+	#[arg(long, default_value_t = 16 << 20)]
+	log_max_size: u64,
+
+	/// Number of rotated log files to keep
+	#[arg(long, default_value_t = 3)]
+	log_keep: usize,
+
+	/// Log what each connection does at DEBUG level, like the write sizes and server notices
+	#[arg(long)]
+	log_workers: bool,
+
+	/// Print what a full repaint costs once encoded and exit, or go on with `--estimate=continue`
+	#[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "exit")]
+	estimate: Option<AfterEstimate>,
+
+	/// Speed of the link for --estimate and the throughput --contention expects (e.g. 100M, 1G)
+	#[arg(long, value_parser = estimate::parse_speed, default_value = "100M")]
+	link_speed: f64,
+
+	/// Throughput of one connection for --estimate, as `info` measures it
+	#[arg(long, value_parser = estimate::parse_speed, default_value = "10M")]
+	connection_speed: f64,
+
+	/// Print common invocations and exit
+	#[arg(long, exclusive = true)]
+	examples: bool,
 }
 
-fn client(id: usize, host_addr: std::net::SocketAddr, offset: Option<(u32, u32)>) -> (sync::mpsc::Sender<Arc<String>>, task::JoinHandle<anyhow::Result<usize>>) {
-	let (tx, mut rx) = sync::mpsc::channel::<Arc<String>>(4);
+/// Recipes printed by --examples
+const EXAMPLES: &str = "\
+Spray an image with 8 connections:
+    pixelspray 127.0.0.1:1337 image.png
 
-	let task = spawn(async move {
-		let mut stream = net::TcpStream::connect(host_addr).await
-			.context("failed to connect")?;
+Resize it and place it in the bottom right corner, only sending changes:
+    pixelspray 127.0.0.1:1337 image.png -r 200x200 --place bottom-right --delta
 
-		log::info!("{}: connected...", id);
-		if let Err(err) = stream.set_nodelay(true) {
-			log::warn!("{}: failed to set no delay: {}", id, err);
-		}
+Keep a webcam on the canvas at 10 frames per second:
+    pixelspray 127.0.0.1:1337 --source mjpeg://camera/stream --max-fps 10
 
-		if let Some(offset) = offset {
-			let offset = format!("OFFSET {} {}\n", offset.0, offset.1);
-			stream.write_all(offset.as_bytes()).await
-				.context("failed to send offset")?;
-		}
+Show a directory of images, each for 30 seconds:
+    pixelspray 127.0.0.1:1337 slides/ --slideshow 30s
 
-		while let Some(chunk) = rx.recv().await {
-			//log::debug!("sending {} bytes: {}...", chunk.len(), chunk.split_at(16).0);
-			stream.write_all(chunk.as_bytes()).await
-				.context("failed to send chunk")?;
-		}
+Try it out on a local server first:
+    pixelspray serve --snapshot canvas.png &
+    pixelspray 127.0.0.1:1337 image.png
 
-		Ok(id)
-	});
+Find out what a server supports:
+    pixelspray info 127.0.0.1:1337
 
-	(tx, task)
+Install the bash completions:
+    pixelspray completions bash > ~/.local/share/bash-completion/completions/pixelspray
+";
+
+#[derive(Subcommand, Debug)]
+enum Command
+{
+	/// Run a local pixelflut server to preview images on
+	Serve(ServeOpt),
+	/// Report what a server supports and how fast it is
+	Info(InfoOpt),
+	/// Print the completion script for a shell
+	Completions(CompletionsOpt),
+}
+
+#[derive(Args, Debug)]
+struct ServeOpt
+{
+	/// The address to listen on
+	#[arg(default_value = "127.0.0.1:1337")]
+	listen: std::net::SocketAddr,
+
+	/// Canvas size
+	#[arg(long, default_value = "1024x768", value_parser = parse_size)]
+	size: (u32, u32),
+
+	/// Save the canvas as PNG to this path
+	#[arg(long)]
+	snapshot: Option<std::path::PathBuf>,
+
+	/// How often to save the canvas
+	#[arg(long, default_value = "5s")]
+	interval: humantime::Duration,
+}
+
+#[derive(Args, Debug)]
+struct CompletionsOpt
+{
+	/// The shell to complete in
+	#[arg(value_enum)]
+	shell: completions::Shell,
+}
+
+#[derive(Args, Debug)]
+struct InfoOpt
+{
+	/// The host to examine
+	host: std::net::SocketAddr,
+
+	/// Stop looking for the connection limit at this many
+	#[arg(long, default_value_t = 256)]
+	max_connections: usize,
+
+	/// How long to measure the throughput
+	#[arg(long, default_value = "3s")]
+	duration: humantime::Duration,
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String>
+{
+	let (w, h) = s.split_once('x')
+		.ok_or_else(|| format!("expected WxH, got '{}'", s))?;
+	let w = u32::from_str(w).map_err(|err| format!("invalid width '{}': {}", w, err))?;
+	let h = u32::from_str(h).map_err(|err| format!("invalid height '{}': {}", h, err))?;
+	Ok((w, h))
+}
+
+fn parse_duplicate(s: &str) -> Result<(u32, u32, u32), String>
+{
+	let (grid, gap) = match s.split_once(':') {
+		Some((grid, gap)) => (grid, u32::from_str(gap).map_err(|err| format!("invalid gap '{}': {}", gap, err))?),
+		None => (s, 0),
+	};
+	let (cols, rows) = parse_size(grid)?;
+	if cols == 0 || rows == 0 {
+		return Err("need at least one copy".to_owned());
+	}
+	Ok((cols, rows, gap))
+}
+
+/// Region reservation on servers with team support
+#[derive(Debug, Clone, PartialEq)]
+struct Claim
+{
+	at: (u32, u32),
+	size: (u32, u32),
+	token: String,
+}
+
+impl std::fmt::Display for Claim
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+	{
+		writeln!(f, "REGION {} {} {} {} {}", self.at.0, self.at.1, self.size.0, self.size.1, self.token)
+	}
+}
+
+/// Parses `XxY+WxH:token`
+fn parse_claim(s: &str) -> Result<Claim, String>
+{
+	let (region, token) = s.split_once(':')
+		.ok_or_else(|| format!("expected XxY+WxH:token, got '{}'", s))?;
+	let (at, size) = region.split_once('+')
+		.ok_or_else(|| format!("expected XxY+WxH, got '{}'", region))?;
+	if token.is_empty() || token.contains(char::is_whitespace) {
+		return Err(format!("invalid token '{}'", token));
+	}
+	Ok(Claim {
+		at: parse_size(at)?,
+		size: parse_size(size)?,
+		token: token.to_owned(),
+	})
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String>
+{
+	let f = f64::from_str(s).map_err(|err| format!("invalid number '{}': {}", s, err))?;
+	if !(0.0..=1.0).contains(&f) {
+		return Err(format!("{} is not between 0 and 1", f));
+	}
+	Ok(f)
 }
+
+/// Parses a rate, which has to be above 0 to wait between frames
+fn parse_rate(s: &str) -> Result<f32, String>
+{
+	let f = f32::from_str(s).map_err(|err| format!("invalid number '{}': {}", s, err))?;
+	if !(f.is_finite() && f > 0.0) {
+		return Err(format!("{} is not a positive number", s));
+	}
+	Ok(f)
+}
+
+/// Parses the interval of something repeated, which can not be 0
+fn parse_period(s: &str) -> Result<humantime::Duration, String>
+{
+	let period = humantime::Duration::from_str(s).map_err(|err| format!("invalid duration '{}': {}", s, err))?;
+	if period.is_zero() {
+		return Err("the interval has to be longer than 0".to_owned());
+	}
+	Ok(period)
+}
+
+/// Parses a percentage like `80%` or a fraction
+fn parse_share(s: &str) -> Result<f64, String>
+{
+	let Some(percent) = s.strip_suffix('%') else {
+		return parse_fraction(s);
+	};
+	let f = f64::from_str(percent).map_err(|err| format!("invalid percentage '{}': {}", s, err))?;
+	if !(0.0..=100.0).contains(&f) {
+		return Err(format!("{} is not between 0% and 100%", s));
+	}
+	Ok(f / 100.0)
+}
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+enum Filter
+{
+	Mask,
+	Grey,
+	Rgba,
+}
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+enum GreyMetric
+{
+	/// Largest difference between two channels
+	Channel,
+	/// CIE76 color difference to the grey of the same lightness
+	DeltaE,
+}
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+enum Order
+{
+	/// Shuffled, so the image appears evenly
+	Random,
+	/// Grouped by color, so consecutive commands compress well
+	Color,
+	/// From the border of the image inwards, to claim the area early
+	Inward,
+	/// From the center of the image outwards
+	Outward,
+}
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+enum Preset
+{
+	/// For small boards like a Raspberry Pi: nearest-neighbor scaling, no send timings and few threads
+	LowPower,
+	/// For e-ink bridges: black and white, and a full frame every 30s
+	Eink,
+}
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+enum AfterEstimate
+{
+	Exit,
+	Continue,
+}
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+enum GreyWire
+{
+	/// Decide by the profile of the server
+	Auto,
+	/// Find out by painting pixel 0,0 grey for a moment and reading it back
+	Test,
+	On,
+	Off,
+}
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+enum Origin
+{
+	TopLeft,
+	/// With the Y axis pointing up
+	BottomLeft,
+}
+
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+enum Overflow
+{
+	/// Drop pixels outside of the canvas
+	Clip,
+	/// Wrap pixels around the canvas edges
+	Wrap,
+	/// Abort if the image does not fit on the canvas
+	Error,
+}
+
+impl Opt
+{
+	/// Parses the command line after the arguments from a config file, so the command line wins
+	fn with_config(config: Vec<std::ffi::OsString>) -> Result<Self, clap::Error>
+	{
+		let mut args = std::env::args_os();
+		Self::try_parse_from(args.next().into_iter().chain(config).chain(args))
+			.map(Self::preset)
+	}
+
+	/// Fills in the options the profile decides that were not given
+	fn preset(mut self) -> Self
+	{
+		match self.profile {
+			Some(Preset::LowPower) => {
+				self.scaling.get_or_insert(transform::Scaling::Nearest);
+				self.worker_threads.get_or_insert(LOW_POWER_THREADS);
+				self.blocking_threads.get_or_insert(LOW_POWER_THREADS);
+				self.no_timings = true;
+			},
+			Some(Preset::Eink) => {
+				self.mono = true;
+				self.refresh_interval.get_or_insert(EINK_REFRESH.into());
+			},
+			None => {},
+		}
+		self
+	}
+
+	fn min_alpha(&self) -> u8
+	{
+		match self.alpha_threshold {
+			_ if self.keep_transparent => 0,
+			Some(threshold) => threshold,
+			None if self.lossless => 1,
+			None => 0x10,
+		}
+	}
+
+	fn grey_tolerance(&self) -> f32
+	{
+		match (self.grey_tolerance, self.grey_metric) {
+			(Some(tolerance), _) => tolerance,
+			(None, _) if self.lossless => 0.0,
+			(None, GreyMetric::Channel) => 4.0,
+			// about the smallest difference noticeable
+			(None, GreyMetric::DeltaE) => 2.3,
+		}
+	}
+
+	/// Canvas coordinates as the server counts them
+	fn server_coords(&self, (x, y): (u32, u32), (_, sh): (u32, u32)) -> (u32, u32)
+	{
+		let y = match self.origin {
+			Origin::TopLeft => y,
+			Origin::BottomLeft => sh - 1 - y,
+		};
+		(x + self.index_base, y + self.index_base)
+	}
+
+	/// Canvas coordinates of the ones the server counts, if they are on it
+	fn canvas_coords(&self, (x, y): (u32, u32), (_, sh): (u32, u32)) -> Option<(u32, u32)>
+	{
+		let (x, y) = (x.checked_sub(self.index_base)?, y.checked_sub(self.index_base)?);
+		let y = match self.origin {
+			Origin::TopLeft => y,
+			Origin::BottomLeft => sh.checked_sub(1)?.checked_sub(y)?,
+		};
+		Some((x, y))
+	}
+
+	fn resampling(&self) -> transform::Resampling
+	{
+		transform::Resampling {
+			scaling: self.scaling.unwrap_or_default(),
+			supersample: self.supersample,
+			linear: !self.srgb_naive,
+		}
+	}
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>>
+{
+	let opt = match config::find(std::env::args_os()) {
+		Some(path) => Opt::with_config(config::args(&path)?).unwrap_or_else(|err| err.exit()),
+		None => Opt::parse().preset(),
+	};
+	if let Some(Command::Completions(completions)) = &opt.command {
+		let mut cmd = Opt::command();
+		cmd.build();
+		print!("{}", completions::generate(&cmd, completions.shell));
+		return Ok(());
+	}
+	if opt.examples {
+		print!("{}", EXAMPLES);
+		return Ok(());
+	}
+	logging::init(opt.log_file.as_deref(), opt.log_format, opt.log_max_size, opt.log_keep, opt.log_workers)?;
+	log::info!("pixelspray: {:?}", &opt);
+
+	let (rt, stats) = tuning::build(&tuning::Tuning {
+		worker_threads: opt.worker_threads,
+		blocking_threads: opt.blocking_threads,
+		cores: opt.pin_cores.clone(),
+	})?;
+	if let Some(interval) = opt.runtime_stats {
+		rt.spawn(tuning::report(stats, interval.into()));
+	}
+
+	match opt.command {
+		Some(Command::Serve(serve)) => {
+			rt.block_on(server::serve(serve.listen, serve.size, serve.snapshot, serve.interval.into()))?;
+			Ok(())
+		},
+		Some(Command::Info(info)) => {
+			rt.block_on(info::info(info.host, info.max_connections, info.duration.into()))?;
+			Ok(())
+		},
+		Some(Command::Completions(_)) => unreachable!("handled before"),
+		None => rt.block_on(run(opt)),
+	}
+}
+
+async fn run(mut opt: Opt) -> Result<(), Box<dyn std::error::Error>>
+{
+	let mut profiles = match opt.profiles.as_ref() {
+		Some(path) => profile::load(path)?,
+		None => Vec::new(),
+	};
+	// user profiles take precedence
+	profiles.extend(profile::builtin());
+	let detect = opt.server_type.as_deref() == Some("auto");
+	let mut profile = match opt.server_type.as_deref() {
+		Some("auto") | None => None,
+		Some(name) => Some(profile::find(&profiles, name)
+			.with_context(|| format!("unknown server type {}", name))?
+			.clone()),
+	};
+
+	if opt.lossless {
+		log::warn!("--lossless is deprecated, use --grey-tolerance 0 --alpha-threshold 1 instead");
+	}
+
+	let mut addrs = resolve(opt.host.as_deref().context("no host given")?).await?;
+	// the built-in server listens on one address only
+	if opt.self_test {
+		addrs.truncate(1);
+	}
+	if addrs.len() > 1 {
+		log::info!("{} resolves to {} addresses, spreading the connections over them", opt.host.as_deref().unwrap_or_default(), addrs.len());
+	}
+	let host = addrs[0];
+	let source = source::open(&opt)?;
+	// decoded up front, so triggers switch right away
+	let trigger_images = opt.trigger_image.iter()
+		.map(|(name, path)| {
+			let image = image::open(path).with_context(|| format!("failed to read {}", path.display()))?;
+			Ok((name.clone(), image))
+		})
+		.collect::<anyhow::Result<std::collections::HashMap<_, _>>>()?;
+
+	let (images_tx, mut images) = sync::mpsc::channel(1);
+	let mut feeder = spawn(source.feed(images_tx.clone(), opt.refresh.map(Into::into), opt.max_fps));
+	// the sender kept for reloads never closes the channel, so a failing source has to end the wait
+	let image = futures::select! {
+		image = images.recv().fuse() => image,
+		fed = (&mut feeder).fuse() => {
+			fed??;
+			// it may have ended right after its only image
+			images.try_recv().ok()
+		},
+	};
+	let image = image.ok_or("source ended without an image")?;
+
+	let self_test = if opt.self_test {
+		let server = server::Server::bind(host, opt.canvas.unwrap_or(probe::DEFAULT_SIZE)).await?;
+		log::info!("self-test: server listening on {}", server.local_addr()?);
+		let state = server.state();
+		spawn(server.run());
+		Some(state)
+	} else {
+		None
+	};
+
+	// only the connections painting go through the proxy, read-backs connect directly
+	let proxy = opt.socks5.map(|addr| socks::Proxy { addr, isolate: opt.isolate_circuits });
+	match proxy {
+		Some(proxy) => log::info!("connecting to {} through {}...", host, proxy.addr),
+		None => log::info!("connecting to {}...", host),
+	}
+
+	let (mut probed, (sw,sh)) = match opt.canvas {
+		Some(canvas) => {
+			if detect {
+				log::warn!("server type can not be detected with a given canvas size");
+			}
+			(None, canvas)
+		},
+		None => {
+			// the probe connection becomes the first worker
+			let stream = match proxy {
+				Some(proxy) => proxy.connect(host).await?,
+				None => net::TcpStream::connect(host).await?,
+			};
+			let (stream, res) = probe::canvas(stream, &profiles, profile.as_ref(), detect).await;
+			let (sw,sh) = res.size;
+			if res.strategy == probe::Strategy::Default {
+				log::warn!("no canvas size reported, assuming {}x{}", sw, sh);
+			} else {
+				log::info!("canvas size {}x{} from {}", sw, sh, res.strategy);
+			}
+			if detect {
+				profile = profile::detect(&profiles, &res.help).cloned();
+				match profile.as_ref() {
+					Some(profile) => log::info!("detected {} server", profile.name),
+					None => log::warn!("unknown server type"),
+				}
+			}
+			(Some(stream), (sw, sh))
+		},
+	};
+
+	if let Some(profile) = profile.as_ref() {
+		opt.no_offset |= !profile.offset;
+		if profile.grey && opt.grey_wire == GreyWire::Auto {
+			opt.grey_wire = GreyWire::On;
+		}
+		if let Some(max) = profile.max_connections.filter(|&max| opt.num > max) {
+			log::warn!("{} allows only {} connections", profile.name, max);
+			opt.num = max;
+		}
+		if profile.binary {
+			log::info!("{} supports binary pixels, sending text anyway", profile.name);
+		}
+	}
+	if opt.preflight && opt.num > 1 {
+		log::info!("finding the connection limit...");
+		// the probe connection stays open, which makes the limit err on the safe side
+		let (max, capped) = info::search(opt.num, |n| info::accepts(host, n)).await;
+		if !capped {
+			log::warn!("the server accepts only {} connections at once, using that many instead of {}", max, opt.num);
+			opt.num = max;
+		}
+	}
+	// the built-in server of the self-test takes it
+	if opt.self_test && opt.grey_wire == GreyWire::Auto {
+		opt.grey_wire = GreyWire::On;
+	}
+	// estimates count the long form rather than connecting only for the test
+	if opt.grey_wire == GreyWire::Test && opt.estimate == Some(AfterEstimate::Exit) {
+		opt.grey_wire = GreyWire::Off;
+	}
+	// painting on a canvas only to find out takes being asked for
+	if opt.grey_wire == GreyWire::Test {
+		opt.grey_wire = match probe::grey(host).await {
+			Ok(true) => GreyWire::On,
+			Ok(false) => GreyWire::Off,
+			Err(err) => {
+				log::warn!("failed to test the grey form: {:#}", err);
+				GreyWire::Off
+			},
+		};
+		log::info!("grey form of PX: {}", if opt.grey_wire == GreyWire::On { "supported" } else { "not supported" });
+	}
+	let opt = Arc::new(opt);
+
+	let canvas = (sw, sh);
+	let image = transform::preprocess(&opt, canvas).build().apply(image, 0.0)?;
+	let (w,h) = image.dimensions();
+	let footprint = match opt.duplicate {
+		Some((cols, rows, gap)) => (cols * (w + gap) - gap, rows * (h + gap) - gap),
+		None => (w, h),
+	};
+	let (xoff,yoff) = placement(&opt, footprint, canvas)?;
+
+	let on_canvas = |x: u32, y: u32| match opt.overflow {
+		Overflow::Wrap => Some(((xoff + x) % sw, (yoff + y) % sh)),
+		_ => (xoff + x < sw && yoff + y < sh).then_some((xoff + x, yoff + y)),
+	};
+	let read_back = if opt.restore_on_exit || opt.composite {
+		let coords = (0..footprint.1)
+			.flat_map(|y| (0..footprint.0).map(move |x| (x, y)))
+			.filter_map(|(x, y)| on_canvas(x, y))
+			.collect::<Vec<_>>();
+		log::info!("reading back {} pixels under the image...", coords.len());
+		Some(probe::grab(host, &coords).await?)
+	} else {
+		None
+	};
+	let background = read_back.as_ref().filter(|_| opt.composite).map(|pixels| {
+		Arc::new(image::RgbaImage::from_fn(w, h, |x, y| match on_canvas(x, y).and_then(|at| pixels.get(&at)) {
+			Some(&[r, g, b]) => image::Rgba([r, g, b, 0xff]),
+			None => image::Rgba([0; 4]),
+		}))
+	});
+	let image = match background.clone() {
+		Some(background) => transform::Composite(background).apply(image, 0.0)?,
+		None => image,
+	};
+	let original = read_back.filter(|_| opt.restore_on_exit);
+
+	//image = image.resize(256, 256, image::FilterType::Nearest);
+	//image = image.grayscale();
+
+	log::info!("screen: {}x{} image: {}x{} offset: {}x{}", sw, sh, w, h, xoff, yoff);
+
+	// wrapped or flipped coordinates can not be expressed relative to an OFFSET
+	let no_offset = opt.no_offset || opt.overflow == Overflow::Wrap || opt.origin == Origin::BottomLeft;
+	let offset = (!no_offset).then_some((xoff, yoff));
+
+	if opt.retreat && no_offset {
+		return Err("--retreat requires OFFSET, which is disabled by --no-offset, --overflow wrap or --origin bottom-left".into());
+	}
+
+	let pieces = if opt.tiles {
+		if no_offset {
+			return Err("tiles require OFFSET, which is disabled by --no-offset, --overflow wrap or --origin bottom-left".into());
+		}
+		tiles(opt.num, (w, h))
+	} else if let Some((cols, rows, gap)) = opt.duplicate {
+		if ((cols * rows) as usize) > opt.num {
+			return Err(format!("{} copies need at least as many connections", cols * rows).into());
+		}
+		duplicates((cols, rows, gap), (w, h))
+	} else {
+		Vec::new()
+	};
+	let mut offsets = (0..opt.num)
+		.map(|id| match pieces.get(id % pieces.len().max(1)) {
+			Some(piece) if !no_offset => Some((xoff + piece.at.0, yoff + piece.at.1)),
+			_ => offset,
+		})
+		.collect::<Vec<_>>();
+
+	let area = if pieces.is_empty() {
+		(w * h) as u64
+	} else {
+		pieces.iter().map(|piece| (piece.crop.2 * piece.crop.3) as u64).sum()
+	};
+	let encoding = progress::Progress::new("Encoding", area);
+	let bar = encoding.show();
+	let lanes = encoding.scope(|| frame(&opt, &image, None, canvas, (xoff, yoff), no_offset, &pieces));
+	// shared pieces are not encoded again
+	encoding.finish();
+	if let Some(bar) = bar {
+		bar.join().ok();
+	}
+
+	let mut summary = format!("Chunks: {} a {}\nOffset: {}",
+		lanes.iter().map(Vec::len).sum::<usize>(), CHUNK_LEN,
+		offset.map(|(x,y)| format!("{x}x{y}")).unwrap_or_default());
+	if opt.estimate.is_some() {
+		summary = format!("{}\n{}", summary, estimate::Estimate::new(&lanes).report(opt.link_speed, opt.connection_speed).trim_end());
+	}
+	// stdout may be taken by the copied commands
+	if opt.tee.as_deref() == Some(std::path::Path::new("-")) {
+		eprintln!("{}", summary);
+	} else {
+		println!("{}", summary);
+	}
+	if opt.estimate == Some(AfterEstimate::Exit) {
+		return Ok(());
+	}
+
+	let lanes = Arc::new(lanes);
+	// keep the initial dimensions so the placement stays valid
+	let prepare = Arc::new(std::sync::Mutex::new(transform::prepare(&opt, canvas, (w, h), background.clone())));
+	let prepare_reload = prepare.clone();
+	// bytes handed to the connections, for the HUD
+	let sent_bytes = Arc::new(AtomicU64::new(0));
+	let mut render = transform::render(&opt, sent_bytes.clone())?;
+	let first = if render.is_empty() {
+		lanes.clone()
+	} else {
+		let shown = render.apply(image.clone(), 0.0)?;
+		Arc::new(frame(&opt, &shown, None, canvas, (xoff, yoff), no_offset, &pieces))
+	};
+	let render = Arc::new(std::sync::Mutex::new(render));
+	let painting = progress::Progress::new("Painting", first.iter().map(Vec::len).sum::<usize>() as u64);
+	let frames = Arc::new(buffer::DoubleBuffer::new(first));
+
+	let (shown_tx, shown_rx) = sync::watch::channel(Arc::new(image.clone()));
+	if let Some(interval) = opt.preview {
+		spawn(preview::run(host, canvas, (xoff, yoff), shown_rx, interval.into()));
+	}
+	if let Some(dir) = opt.timelapse.clone() {
+		let interval = opt.timelapse_interval.into();
+		spawn(async move {
+			if let Err(err) = timelapse::run(host, canvas, dir, interval).await {
+				log::warn!("timelapse stopped: {:#}", err);
+			}
+		});
+	}
+	if let Some(dir) = opt.audit.clone() {
+		let offsets = if pieces.is_empty() { vec![ offset ] } else { offsets.clone() };
+		let frames = frames.clone();
+		spawn(async move {
+			if let Err(err) = audit::run(dir, canvas, frames, offsets).await {
+				log::warn!("audit stopped: {:#}", err);
+			}
+		});
+	}
+
+	let (remote_tx, remote) = sync::mpsc::channel(1);
+	let (paused_tx, mut paused) = sync::watch::channel(false);
+	let mut remote = (opt.control_socket.is_some() || opt.trigger.is_some()).then_some(remote);
+	if let Some(path) = opt.control_socket.clone() {
+		let remote_tx = remote_tx.clone();
+		spawn(async move {
+			if let Err(err) = remote::run(&path, remote_tx).await {
+				log::warn!("control socket failed: {:#}", err);
+			}
+		});
+	}
+	if let Some(trigger) = opt.trigger.clone() {
+		let actions = trigger::Actions { images: trigger_images, swaps: remote_tx, paused: paused_tx };
+		spawn(async move {
+			if let Err(err) = trigger::run(trigger, actions).await {
+				log::warn!("trigger failed: {:#}", err);
+			}
+		});
+	}
+	let (swaps_tx, mut swaps) = sync::mpsc::channel::<remote::Swap>(1);
+
+	let opt_enc = opt.clone();
+	let frames_tx = frames.clone();
+	spawn(async move {
+		let opt = opt_enc;
+		let pieces = Arc::new(pieces);
+		// the crossfade to a swapped image
+		let fading = Arc::new(std::sync::Mutex::new(None::<transform::Crossfade>));
+		let start = time::Instant::now();
+		let mut images = Some(images);
+		let interval = time::Duration::from(opt.effect_interval);
+		let mut ticker = render.lock().unwrap().animated(0.0)
+			.then(|| time::interval(interval));
+		// the latest image and how it was sent after effects
+		let mut current = Arc::new(image);
+		let mut prev = current.clone();
+		loop {
+			let next = async {
+				match images.as_mut() {
+					Some(images) => images.recv().await,
+					None => futures::future::pending().await,
+				}
+			};
+			let tick = async {
+				match ticker.as_mut() {
+					Some(ticker) => ticker.tick().await,
+					None => futures::future::pending().await,
+				}
+			};
+			let swap = async {
+				match swaps.recv().await {
+					Some(swap) => swap,
+					None => futures::future::pending().await,
+				}
+			};
+			// the fade of a swapped image, if there was one
+			let (image, closed, swapped) = futures::select! {
+				image = next.fuse() => {
+					let closed = image.is_none();
+					(image, closed, None)
+				},
+				_ = tick.fuse() => (None, false, None),
+				swap = swap.fuse() => (Some(swap.image), false, Some(swap.fade)),
+			};
+			if closed {
+				// effects keep rendering the last image
+				images = None;
+				if ticker.is_none() && opt.control_socket.is_none() && opt.trigger.is_none() {
+					break;
+				}
+				continue;
+			}
+			if swapped.is_some() {
+				// what the source sent before it was stopped is outdated
+				while images.as_mut().is_some_and(|images| images.try_recv().is_ok()) {}
+			}
+
+			let t = start.elapsed().as_secs_f32();
+			let opt = opt.clone();
+			let last = current.clone();
+			let base = prev.clone();
+			let pieces = pieces.clone();
+			let prepare = prepare.clone();
+			let render = render.clone();
+			let fading = fading.clone();
+			// full frames of a single piece can be patched where the image changed
+			let (_, published) = frames_tx.load();
+			let patchable = !opt.delta && pieces.is_empty() && opt.alpha_period == 1 && opt.overflow != Overflow::Wrap;
+			let frame = task::spawn_blocking(move || {
+				let ticked = image.is_none();
+				let image = match image {
+					Some(image) => Arc::new(prepare.lock().unwrap().apply(image, t)?),
+					None => last.clone(),
+				};
+				let mut fading = fading.lock().unwrap();
+				if let Some(fade) = swapped {
+					*fading = fade.map(transform::Crossfade::new);
+					if let Some(crossfade) = fading.as_mut() {
+						// from the image before the swap
+						crossfade.apply((*last).clone(), t)?;
+					}
+				}
+				let faded = match fading.as_mut() {
+					Some(crossfade) => Arc::new(crossfade.apply((*image).clone(), t)?),
+					None => image.clone(),
+				};
+				if fading.as_ref().is_some_and(|crossfade| !crossfade.animated(t)) {
+					*fading = None;
+				}
+				let mut render = render.lock().unwrap();
+				let shown = if render.is_empty() {
+					faded
+				} else {
+					Arc::new(render.apply((*faded).clone(), t)?)
+				};
+				let animated = render.animated(t) || fading.is_some();
+				// what is on the canvas stays where it is close enough
+				let shown = match opt.delta_threshold {
+					Some(threshold) if opt.delta => Arc::new(settle(&base, &shown, threshold)),
+					_ => shown,
+				};
+				let dirty = patchable
+					.then(|| dirty::Dirty::diff(&base, &shown, DIRTY_BLOCK))
+					.filter(|dirty| dirty.area() * 2 < (w * h) as u64);
+				let lanes = match dirty {
+					// renders of the same image that look as before need no new frame
+					_ if ticked && *shown == *base => vec![ Vec::new() ],
+					Some(dirty) if dirty.is_empty() => vec![ Vec::new() ],
+					Some(dirty) => vec![ patch(&opt, &shown, &published[0], &dirty, canvas, (xoff, yoff), no_offset) ],
+					// a repeated image is a keep-alive of the source, which repaints all of it
+					None => frame(&opt, &shown, opt.delta.then_some(&*base).filter(|base| **base != *shown), canvas, (xoff, yoff), no_offset, &pieces),
+				};
+				anyhow::Ok((image, shown, lanes, animated))
+			}).await;
+			let (image, shown, lanes, animated) = match frame {
+				Ok(Ok(frame)) => frame,
+				Ok(Err(err)) => {
+					log::warn!("failed to prepare image: {:#}", err);
+					continue;
+				},
+				Err(err) => {
+					log::warn!("failed to encode image: {}", err);
+					continue;
+				},
+			};
+			current = image;
+			prev = shown;
+			shown_tx.send_replace(prev.clone());
+			// e.g. no more ticks once the reveal is over, until a new image is faded in
+			if !animated {
+				ticker = None;
+			} else if ticker.is_none() {
+				ticker = Some(time::interval(interval));
+			}
+
+			if lanes.iter().all(Vec::is_empty) {
+				continue;
+			}
+			log::debug!("new frame: {} chunks", lanes.iter().map(Vec::len).sum::<usize>());
+			frames_tx.publish(Arc::new(lanes));
+		}
+	});
+
+	let (rtt_tx, mut rtt_rx) = sync::mpsc::unbounded_channel();
+	let ping = opt.ping.map(|interval| Ping {
+		interval: interval.into(),
+		rtt: rtt_tx,
+	});
+
+	let stealth = opt.stealth.map(|rotate| Stealth {
+		rotate: rotate.into(),
+		canvas,
+	});
+
+	let (matches_tx, mut matches) = sync::mpsc::unbounded_channel();
+	let control = if opt.control {
+		let (samples_tx, samples) = sync::mpsc::unbounded_channel();
+		let matches = matches_tx.clone();
+		spawn(async move {
+			if let Err(err) = control::run(host, canvas, control::REPROBE, samples, matches).await {
+				log::warn!("control connection failed: {:#}", err);
+			}
+		});
+		Some(samples_tx)
+	} else {
+		None
+	};
+	let verify = opt.verify.map(|rate| Verify {
+		rate,
+		matches: matches_tx,
+		control,
+	});
+	let (conflicts_tx, conflicts) = sync::mpsc::unbounded_channel();
+	let mut conflicts = opt.retreat.then_some(conflicts);
+	let (sample_rate_tx, sample_rate) = sync::watch::channel(opt.sample_rate);
+	let sample_rate_tx = Arc::new(sample_rate_tx);
+	if verify.is_some() {
+		let mut conflict = opt.conflict.map(conflict::Conflict::new);
+		let mut target = opt.target_ownership.map(ownership::Target::new);
+		let mut contention = opt.contention.map(|grid| contention::Contention::new(canvas, grid));
+		let link_speed = opt.link_speed;
+		let sent = sent_bytes.clone();
+		let sample_rate_tx = sample_rate_tx.clone();
+		spawn(async move {
+			let mut ticker = time::interval(VERIFY_REPORT);
+			ticker.tick().await;
+			let (mut ours, mut total) = (0u64, 0u64);
+			let mut last_sent = 0;
+			loop {
+				futures::select! {
+					m = matches.recv().fuse() => {
+						let Some((at, m)) = m else { break };
+						ours += m as u64;
+						total += 1;
+						if let Some(contention) = contention.as_mut() {
+							contention.record(at, m);
+						}
+					},
+					_ = ticker.tick().fuse() => {
+						if total > 0 {
+							log::info!("ownership: {:.1}% of {} sampled pixels", 100.0 * ours as f64 / total as f64, total);
+						}
+						if conflict.as_mut().is_some_and(|conflict| conflict.report(ours, total)) {
+							log::warn!("another client keeps painting over the image");
+							conflicts_tx.send(()).ok();
+						}
+						if let Some(rate) = target.as_mut().and_then(|target| target.report(ours, total)) {
+							log::debug!("sending {:.0}% of the pixels for the target ownership", 100.0 * rate);
+							sample_rate_tx.send_replace((rate < 1.0).then_some(rate));
+						}
+						if let Some(contention) = contention.as_mut() {
+							let now = sent.load(Ordering::Relaxed);
+							let delivery = (now - last_sent) as f64 * 8.0 / VERIFY_REPORT.as_secs_f64() / link_speed;
+							last_sent = now;
+							if delivery < contention::SATURATED {
+								log::warn!("sending at {:.0}% of --link-speed, the canvas or link is saturated", 100.0 * delivery);
+							}
+							let regions = contention.report(delivery);
+							if !regions.is_empty() {
+								log::info!("contention: {}", contention::summary(&regions));
+							}
+						}
+						(ours, total) = (0, 0);
+					},
+				}
+			}
+		});
+	}
+
+	let limit = Arc::new(ratelimit::Aimd::default());
+	spawn(ratelimit::report(limit.clone(), THROUGHPUT_REPORT));
+	let timings = (!opt.no_timings).then(|| Arc::new(histogram::Timings::default()));
+	if let Some(timings) = timings.clone() {
+		spawn(histogram::report(timings, TIMINGS_REPORT));
+	}
+	let inbound = opt.count_inbound.then(|| Arc::new([AtomicU64::new(0), AtomicU64::new(0)]));
+	if let Some(inbound) = inbound.clone() {
+		spawn(async move {
+			let mut ticker = time::interval(THROUGHPUT_REPORT);
+			ticker.tick().await;
+			loop {
+				ticker.tick().await;
+				let [lines, bytes] = [0, 1].map(|i| inbound[i].swap(0, Ordering::Relaxed));
+				log::info!("inbound: {} lines, {:.1} kB/s", lines, bytes as f64 / THROUGHPUT_REPORT.as_secs_f64() / 1e3);
+			}
+		});
+	}
+
+	let ledger = opt.chunk_report.is_some().then(|| Arc::new(ledger::Ledger::default()));
+
+	let client_opt = ClientOpt {
+		ping,
+		stealth,
+		verify,
+		claim: opt.claim.as_ref().map(ToString::to_string),
+		limit,
+		timings: timings.clone(),
+		tee: opt.tee.as_deref().map(tee::Tee::open).transpose()?,
+		queue_depth: opt.queue_depth.map(|depth| depth as usize),
+		drop_policy: opt.drop_policy,
+		max_batch: opt.max_batch as usize,
+		proxy,
+		inbound,
+		ledger: ledger.clone(),
+		chaos: (opt.chaos_drop_rate > 0.0 || opt.chaos_corrupt_rate > 0.0 || opt.chaos_reconnect_interval.is_some())
+			.then(|| chaos::Chaos::new(opt.chaos_drop_rate, opt.chaos_corrupt_rate, opt.chaos_reconnect_interval.map(Into::into), opt.chaos_seed)),
+		index_base: opt.index_base,
+		interfaces: opt.interface.clone(),
+		connects: Arc::default(),
+	};
+
+	// the connections take the addresses in turns, the first one uses the probed host
+	let mut hosts = addrs;
+	if let Some(other) = opt.dual_stack {
+		if other.is_ipv4() == host.is_ipv4() {
+			log::warn!("{} and {} are of the same IP family", host, other);
+		}
+		hosts.push(other);
+	}
+	let hosts = Arc::<[std::net::SocketAddr]>::from(hosts);
+	let host_of = {
+		let hosts = hosts.clone();
+		move |id: usize| hosts[id % hosts.len()]
+	};
+	// bytes handed to the connections of each address
+	let host_bytes = Arc::new(hosts.iter().map(|_| AtomicU64::new(0)).collect::<Vec<_>>());
+	if hosts.len() > 1 {
+		let host_bytes = host_bytes.clone();
+		spawn(async move {
+			let mut ticker = time::interval(THROUGHPUT_REPORT);
+			ticker.tick().await;
+			let mut last = vec![0; hosts.len()];
+			loop {
+				ticker.tick().await;
+				let mut line = String::new();
+				for ((addr, bytes), last) in hosts.iter().zip(host_bytes.iter()).zip(last.iter_mut()) {
+					let now = bytes.load(Ordering::Relaxed);
+					let rate = (now - *last) as f64 / THROUGHPUT_REPORT.as_secs_f64() / 1e6;
+					write!(line, "{}{} {:.2} MB/s", if line.is_empty() { "" } else { ", " }, addr, rate).unwrap();
+					*last = now;
+				}
+				log::info!("throughput: {}", line);
+			}
+		});
+	}
+
+	// the probe went out on the default route
+	if !opt.interface.is_empty() {
+		probed = None;
+	}
+	let mut tasks = futures::stream::FuturesUnordered::new();
+	let mut channels = std::collections::HashMap::new();
+	for (id, &offset) in offsets.iter().enumerate() {
+		let (tx, task) = client(id, host_of(id), probed.take(), offset, client_opt.clone());
+		channels.insert(id, tx);
+		tasks.push(supervisor::Task::new(id, task));
+	}
+
+	let state = Arc::new(sync::Mutex::new(channels));
+	let channels = state.clone();
+	let reshuffle_every = opt.reshuffle_every;
+	let order = opt.order;
+	let interleave = opt.interleave;
+	let skip = if opt.stealth.is_some() { STEALTH_SKIP } else { 0.0 };
+	let delta = opt.delta;
+	let half_life = opt.decay_compensation.map(time::Duration::from);
+	// the bright pixels to resend in between of each lane
+	let refreshes = move |lanes: Arc<Frame>| async move {
+		match half_life {
+			Some(half_life) => task::spawn_blocking(move || lanes.iter()
+					.map(|chunks| decay::Refresh::new(chunks, half_life))
+					.collect())
+				.await
+				.unwrap_or_default(),
+			None => Vec::new(),
+		}
+	};
+	let sent = host_bytes.clone();
+	let sent_total = sent_bytes.clone();
+	let draw_order = opt.export_draw_order.is_some().then(|| Arc::new(draworder::DrawOrder::new(canvas)));
+	let recorder = draw_order.clone();
+	let first_offsets = offsets.clone();
+	let mut pace = opt.refresh_interval.map(|every| {
+		// the first pass goes out right away
+		let mut pace = time::interval_at(time::Instant::now() + *every, every.into());
+		pace.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+		pace
+	});
+	painting.show();
+	let distributor = spawn(async move {
+		// until the first frame was sent once
+		let mut painting = Some(painting);
+		let (mut generation, mut lanes) = frames.load();
+		let mut refresh = refreshes(lanes.clone()).await;
+		let mut next = vec![0; lanes.len()];
+		let mut cycles = 0;
+		let mut cycle_start = time::Instant::now();
+		let mut sched = schedule::Scheduler::default();
+		// whether a cycle is over and the next waits for its turn
+		let mut cycled = false;
+		loop {
+			if let Some(pace) = pace.as_mut().filter(|_| std::mem::take(&mut cycled)) {
+				pace.tick().await;
+			}
+			if *paused.borrow() {
+				log::info!("paused");
+				paused.wait_for(|&paused| !paused).await.ok();
+				log::info!("resumed");
+			}
+			if lanes.iter().all(Vec::is_empty) {
+				frames.changed(generation).await;
+				(generation, lanes) = frames.load();
+				refresh = refreshes(lanes.clone()).await;
+				next = vec![0; lanes.len()];
+				continue;
+			}
+
+			while let Ok((id, rtt)) = rtt_rx.try_recv() {
+				log::trace!("{}: rtt {:?}", id, rtt);
+				sched.report(id, rtt);
+			}
+
+			let mut channels = channels.lock().await;
+/*			let sends = channels.values_mut()
+				.zip(chunk_iter.by_ref())
+				.map(|(tx, chunk)| tx.send(chunk.clone()));
+
+			futures::future::select_all(sends).await;
+*/
+			let ids = channels.keys().copied().collect::<Vec<_>>();
+			let mut broken = Vec::new();
+			for id in sched.round(&ids) {
+				// switch to a new frame at the next chunk, going on where the cycle was,
+				// but deltas build on each other and have to be sent in full
+				// paced frames are sent whole as well
+				if (next[0] == 0 || !(delta || pace.is_some())) && frames.generation() != generation {
+					(generation, lanes) = frames.load();
+					refresh = refreshes(lanes.clone()).await;
+					next = lanes.iter()
+						.enumerate()
+						.map(|(lane, chunks)| next.get(lane).map_or(0, |n| n % chunks.len().max(1)))
+						.collect();
+					cycles = 0;
+					cycle_start = time::Instant::now();
+					if let Some(painting) = painting.take() {
+						painting.finish();
+					}
+				} else if next[0] == 0 && reshuffle_every.is_some_and(|n| cycles >= n) {
+					let frame = lanes.clone();
+					if let Ok(frame) = task::spawn_blocking(move || reshuffle(&frame, order, interleave)).await {
+						lanes = Arc::new(frame);
+						next = vec![0; lanes.len()];
+					}
+					cycles = 0;
+				}
+				let lane = if lanes.len() == 1 { 0 } else { id % lanes.len() };
+				// fading pixels that are due go first
+				let resend = refresh.get_mut(lane).and_then(|refresh| refresh.next(std::time::Instant::now()));
+				let fresh = resend.is_none();
+				let chunk = match resend {
+					Some(chunk) => chunk,
+					None => {
+						let chunks = &lanes[lane];
+						if chunks.is_empty() {
+							continue;
+						}
+						let chunk = chunks[next[lane]].clone();
+						next[lane] = (next[lane] + 1) % chunks.len();
+						if lane == 0 && next[0] == 0 {
+							cycles += 1;
+							if let Some(timings) = timings.as_ref() {
+								timings.cycles.record(cycle_start.elapsed());
+							}
+							cycle_start = time::Instant::now();
+							cycled = true;
+						}
+						chunk
+					},
+				};
+				if skip > 0.0 && rand::thread_rng().gen_bool(skip) {
+					continue;
+				}
+				// a different subset every cycle
+				let chunk = match *sample_rate.borrow() {
+					Some(rate) => Chunk::from(sample(&chunk, rate, &mut rand::thread_rng())),
+					None => chunk,
+				};
+				if chunk.is_empty() {
+					continue;
+				}
+				sent[id % sent.len()].fetch_add(chunk.len() as u64, Ordering::Relaxed);
+				sent_total.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+				if let Some(p) = painting.as_ref().filter(|_| fresh) {
+					if let Some(recorder) = recorder.as_ref() {
+						recorder.record(&chunk, first_offsets[id]);
+					}
+					p.inc(1);
+					if p.is_finished() {
+						painting = None;
+					}
+				}
+
+				if let Err(_err) = channels[&id].send(chunk).await {
+					broken.push(id);
+				}
+				if cycled && pace.is_some() {
+					break;
+				}
+			}
+			for id in broken {
+				channels.remove(&id);
+				sched.forget(id);
+			}
+		}
+	});
+
+	// the self-test is done once a full pass arrived at the server
+	let pixels = lanes.iter().flatten()
+		.map(|chunk| chunk.lines().count() as u64)
+		.sum::<u64>();
+	let self_test_done = async {
+		match self_test.as_ref() {
+			Some(state) => {
+				// count only what arrives once the reveal is over
+				if let Some(reveal) = opt.reveal {
+					time::sleep(reveal.duration + 2 * *opt.effect_interval).await;
+					state.pixels.store(0, std::sync::atomic::Ordering::Relaxed);
+				}
+				while state.pixels.load(std::sync::atomic::Ordering::Relaxed) < pixels {
+					time::sleep(time::Duration::from_millis(100)).await;
+				}
+				time::sleep(time::Duration::from_millis(500)).await;
+			},
+			None => futures::future::pending().await,
+		}
+	}.fuse();
+	futures::pin_mut!(self_test_done);
+
+	let channels = state.clone();
+	// one connection after the other, so they are not all replaced at once
+	let mut rotation = opt.rotate_connections
+		// staggered over the connections, but not faster than they can connect
+		.map(|every| (*every / offsets.len() as u32).max(time::Duration::from_millis(1)))
+		.map(|period| time::interval_at(time::Instant::now() + period, period));
+	let mut rotated = 0;
+	// replaced workers still draining their chunks, which are not respawned once done
+	let mut retired = std::collections::HashMap::<usize, usize>::new();
+	// SIGHUP keeps its default of ending the process without a config to reload
+	let mut hangup = opt.config.is_some().then(config::Hangup::new).transpose()?;
+	let mut at = (xoff, yoff);
+	let mut supervisor = supervisor::Supervisor::new(opt.restart_budget);
+	// failed workers waiting to be restarted
+	let mut restarts = futures::stream::FuturesUnordered::new();
+	let mut gave_up = false;
+	loop {
+		let rotate = async {
+			match rotation.as_mut() {
+				Some(rotation) => rotation.tick().await,
+				None => futures::future::pending().await,
+			}
+		};
+		let reload = async {
+			match hangup.as_mut() {
+				Some(hangup) => hangup.recv().await,
+				None => futures::future::pending().await,
+			}
+		};
+		let swapped = async {
+			match remote.as_mut() {
+				Some(remote) => remote.recv().await,
+				None => futures::future::pending().await,
+			}
+		};
+		let conflicted = async {
+			match conflicts.as_mut() {
+				Some(conflicts) => conflicts.recv().await,
+				None => futures::future::pending().await,
+			}
+		};
+		futures::select! {
+			_ = signal::ctrl_c().fuse() => {
+				break;
+			},
+			_ = reload.fuse() => {
+				let Some(path) = opt.config.as_ref() else { continue };
+				log::info!("reloading {}...", path.display());
+				// everything is checked before anything is applied
+				let reload = async {
+					let new = Opt::with_config(config::args(path)?)?;
+					let prepare = transform::prepare(&new, canvas, (w, h), background.clone());
+					let placed = placement(&new, footprint, canvas)?;
+					// the new source has to deliver before the old one is let go
+					let (tx, mut rx) = sync::mpsc::channel(1);
+					let feed = spawn(source::open(&new)?.feed(tx, new.refresh.map(Into::into), new.max_fps));
+					let first = match time::timeout(RELOAD_TIMEOUT, rx.recv()).await {
+						Ok(Some(image)) => image,
+						Ok(None) => {
+							feed.await??;
+							anyhow::bail!("source ended without an image");
+						},
+						Err(_) => {
+							feed.abort();
+							anyhow::bail!("source sent no image within {:?}", RELOAD_TIMEOUT);
+						},
+					};
+					anyhow::Ok((new, prepare, placed, first, rx))
+				}.await;
+				let (new, prepare, placed, first, mut rx) = match reload {
+					Ok(reload) => reload,
+					Err(err) => {
+						log::warn!("keeping the old config: {:#}", err);
+						continue;
+					},
+				};
+				*prepare_reload.lock().unwrap() = prepare;
+				feeder.abort();
+				let images_tx = images_tx.clone();
+				feeder = spawn(async move {
+					let mut image = Some(first);
+					while let Some(next) = image.take() {
+						if images_tx.send(next).await.is_err() {
+							break;
+						}
+						image = rx.recv().await;
+					}
+					anyhow::Ok(())
+				});
+				// the target ownership keeps control of it
+				if opt.target_ownership.is_none() {
+					sample_rate_tx.send_replace(new.sample_rate);
+				}
+				if placed != at {
+					if offset.is_none() {
+						log::warn!("the offset can only change with OFFSET enabled, restart to apply it");
+					} else {
+						log::info!("moving to {}x{}", placed.0, placed.1);
+						for (id, offset) in offsets.iter_mut().enumerate() {
+							*offset = offset.map(|(x, y)| (x - at.0 + placed.0, y - at.1 + placed.1));
+							// the pool stays, but every connection needs the new OFFSET
+							let (tx, task) = client(id, host_of(id), None, *offset, client_opt.clone());
+							if channels.lock().await.insert(id, tx).is_some() {
+								*retired.entry(id).or_default() += 1;
+							}
+							tasks.push(supervisor::Task::new(id, task));
+						}
+						at = placed;
+					}
+				}
+				log::info!("config reloaded, options other than the image, offset and sample rate need a restart");
+			},
+			swap = swapped.fuse() => {
+				let Some(swap) = swap else {
+					remote = None;
+					continue;
+				};
+				// the swapped image stays until the config is reloaded
+				feeder.abort();
+				swaps_tx.send(swap).await.ok();
+			},
+			_ = conflicted.fuse() => {
+				let (coords, step) = conflict::grid(canvas);
+				let pixels = match probe::grab(host, &coords).await {
+					Ok(pixels) => pixels,
+					Err(err) => {
+						log::warn!("failed to read the canvas back, staying: {:#}", err);
+						continue;
+					},
+				};
+				let Ok(Some(placed)) = task::spawn_blocking(move || conflict::free_area(&pixels, step, canvas, footprint, at)).await else {
+					log::warn!("no free area to retreat to, staying");
+					continue;
+				};
+				log::info!("retreating from {}x{} to {}x{}", at.0, at.1, placed.0, placed.1);
+				for (id, offset) in offsets.iter_mut().enumerate() {
+					*offset = offset.map(|(x, y)| (x - at.0 + placed.0, y - at.1 + placed.1));
+					let (tx, task) = client(id, host_of(id), None, *offset, client_opt.clone());
+					if channels.lock().await.insert(id, tx).is_some() {
+						*retired.entry(id).or_default() += 1;
+					}
+					tasks.push(supervisor::Task::new(id, task));
+				}
+				at = placed;
+			},
+			_ = rotate.fuse() => {
+				let id = rotated % offsets.len();
+				rotated += 1;
+				log::debug!(worker = id, "rotating connection");
+				// the new connection takes over the channel first, so no chunk is lost
+				let (tx, task) = client(id, host_of(id), None, offsets[id], client_opt.clone());
+				if channels.lock().await.insert(id, tx).is_some() {
+					*retired.entry(id).or_default() += 1;
+				}
+				tasks.push(supervisor::Task::new(id, task));
+			},
+			_ = self_test_done => {
+				break;
+			},
+			(id, res) = tasks.select_next_some() => {
+				if retired.get(&id).is_some_and(|&n| n > 0) {
+					*retired.get_mut(&id).unwrap() -= 1;
+					continue;
+				}
+				match res {
+					Ok(_) => {
+						log::info!(worker = id, "respawning...");
+						let (tx, task) = client(id, host_of(id), None, offsets[id], client_opt.clone());
+						channels.lock().await.insert(id, tx);
+						tasks.push(supervisor::Task::new(id, task));
+					},
+					Err(err) => {
+						log::warn!(worker = id, "worker failed: {:#}", err);
+						if let Some(delay) = supervisor.failed(id, format!("{:#}", err), std::time::Instant::now()) {
+							log::info!(worker = id, "restarting in {:?}...", delay);
+							restarts.push(time::sleep(delay).map(move |_| id));
+							continue;
+						}
+						let budget = opt.restart_budget;
+						log::error!(worker = id, "giving up after {} restarts within {:?}", budget.restarts, budget.window);
+						if supervisor.given_up() == offsets.len() {
+							gave_up = true;
+							break;
+						}
+					},
+				}
+			},
+			id = restarts.select_next_some() => {
+				let (tx, task) = client(id, host_of(id), None, offsets[id], client_opt.clone());
+				channels.lock().await.insert(id, tx);
+				tasks.push(supervisor::Task::new(id, task));
+			},
+		};
+	}
+	log::info!("stopping...");
+	for line in supervisor.summary() {
+		if gave_up {
+			log::error!("{}", line);
+		} else {
+			log::info!("{}", line);
+		}
+	}
+	if let (Some(ledger), Some(path)) = (ledger.as_ref(), opt.chunk_report.as_ref()) {
+		log::info!("{}, written to {}", ledger.summary(), path.display());
+		ledger.write(path)?;
+	}
+	if let (Some(draw_order), Some(path)) = (draw_order.as_ref(), opt.export_draw_order.as_ref()) {
+		log::info!("draw order of {} pixels written to {}", draw_order.pixels(), path.display());
+		draw_order.write(path)?;
+	}
+
+	if let Some(original) = original {
+		distributor.abort();
+		for task in tasks.iter() {
+			task.abort();
+		}
+		// chunks still buffered for the closed connections arrive first
+		time::sleep(time::Duration::from_millis(500)).await;
+		log::info!("restoring {} pixels...", original.len());
+		restore(host, &original).await?;
+	}
+
+	if let Some(state) = self_test.as_ref() {
+		// replay the first frame to get what the canvas should look like
+		let expected = server::State::new(canvas);
+		let offsets = if lanes.len() == 1 { vec![ offset ] } else { offsets };
+		audit::replay(&expected, &lanes, &offsets);
+		let expected = expected.canvas.into_inner().unwrap();
+		let canvas = state.canvas.lock().unwrap();
+		let wrong = expected.pixels().zip(canvas.pixels())
+			.filter(|(a, b)| a != b)
+			.count();
+
+		println!("Self-test: {} of {} pixels wrong", wrong, pixels);
+		if wrong > 0 {
+			return Err("self-test failed".into());
+		}
+	}
+	if gave_up {
+		return Err("the server keeps rejecting the connections".into());
+	}
+	Ok(())
+}
+
+/// Resolves the host to its addresses, in order and without duplicates
+async fn resolve(host: &str) -> anyhow::Result<Vec<std::net::SocketAddr>>
+{
+	let mut addrs = Vec::new();
+	for addr in net::lookup_host(host).await.with_context(|| format!("failed to resolve {}", host))? {
+		if !addrs.contains(&addr) {
+			addrs.push(addr);
+		}
+	}
+	anyhow::ensure!(!addrs.is_empty(), "{} has no address", host);
+	Ok(addrs)
+}
+
+/// Computes the image offset on the canvas
+fn placement(opt: &Opt, (w, h): (u32, u32), (sw, sh): (u32, u32)) -> anyhow::Result<(u32, u32)>
+{
+	let offset = opt.place.map(placement::Offset::from).or(opt.offset).unwrap_or_default();
+	let (xoff,yoff) = placement::place(offset, opt.margin, (w, h), (sw, sh));
+
+	if opt.overflow == Overflow::Error && (xoff + w > sw || yoff + h > sh) {
+		anyhow::bail!("image {}x{} at offset {}x{} exceeds canvas {}x{}", w, h, xoff, yoff, sw, sh);
+	}
+	Ok((xoff, yoff))
+}
+
+/// Part of the image sprayed by its own connections
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Piece
+{
+	/// Region of the image as `(x, y, w, h)`
+	crop: (u32, u32, u32, u32),
+	/// Where it goes, relative to the image offset
+	at: (u32, u32),
+}
+
+/// Splits the image into one tile per connection
+fn tiles(num: usize, (w, h): (u32, u32)) -> Vec<Piece>
+{
+	// the most square grid with exactly `num` tiles
+	let rows = (1..=num)
+		.filter(|d| num.is_multiple_of(*d) && d * d <= num)
+		.max()
+		.unwrap_or(1) as u32;
+	let cols = num as u32 / rows;
+
+	(0..rows)
+		.flat_map(|r| (0..cols).map(move |c| (r, c)))
+		.map(|(r, c)| {
+			let (x0, x1) = (w * c / cols, w * (c + 1) / cols);
+			let (y0, y1) = (h * r / rows, h * (r + 1) / rows);
+			Piece { crop: (x0, y0, x1 - x0, y1 - y0), at: (x0, y0) }
+		})
+		.collect()
+}
+
+/// Places copies of the whole image in a grid
+fn duplicates((cols, rows, gap): (u32, u32, u32), (w, h): (u32, u32)) -> Vec<Piece>
+{
+	(0..rows)
+		.flat_map(|r| (0..cols).map(move |c| (r, c)))
+		.map(|(r, c)| Piece { crop: (0, 0, w, h), at: (c * (w + gap), r * (h + gap)) })
+		.collect()
+}
+
+/// Chunks per lane; a single lane is shared by all connections,
+/// otherwise each connection sprays the lane of its piece
+type Frame = Vec<Vec<Chunk>>;
+
+/// The image with the pixels that differ from `base` by less than `threshold` in every channel taken from it
+fn settle(base: &image::DynamicImage, image: &image::DynamicImage, threshold: u8) -> image::DynamicImage
+{
+	if base.dimensions() != image.dimensions() {
+		return image.clone();
+	}
+	let mut image = image.to_rgba8();
+	for (x, y, px) in image.enumerate_pixels_mut() {
+		let old = base.get_pixel(x, y);
+		if old.0.iter().zip(px.0).all(|(&a, b)| a.abs_diff(b) < threshold) {
+			*px = old;
+		}
+	}
+	image::DynamicImage::ImageRgba8(image)
+}
+
+/// Encodes the image as a whole or piece by piece
+fn frame(opt: &Opt, image: &image::DynamicImage, base: Option<&image::DynamicImage>, (sw, sh): (u32, u32), (xoff, yoff): (u32, u32), no_offset: bool, pieces: &[Piece]) -> Frame
+{
+	let lanes = if pieces.is_empty() {
+		vec![ encode(opt, image, base, (sw, sh), (xoff, yoff), no_offset) ]
+	} else {
+		pieced(opt, image, base, (sw, sh), (xoff, yoff), no_offset, pieces)
+	};
+	match &opt.mmap_chunks {
+		Some(dir) => store::spill(&lanes, dir).unwrap_or_else(|err| {
+			log::warn!("keeping chunks in memory: {:#}", err);
+			lanes
+		}),
+		None => lanes,
+	}
+}
+
+/// Encodes the pieces, a lane each
+fn pieced(opt: &Opt, image: &image::DynamicImage, base: Option<&image::DynamicImage>, (sw, sh): (u32, u32), (xoff, yoff): (u32, u32), no_offset: bool, pieces: &[Piece]) -> Frame
+{
+	// pieces relative to an OFFSET share their chunks unless they get clipped
+	let mut shared = std::collections::HashMap::new();
+	pieces.iter()
+		.map(|piece| {
+			let (x, y, w, h) = piece.crop;
+			let (px, py) = (xoff + piece.at.0, yoff + piece.at.1);
+			let shareable = !no_offset && px + w <= sw && py + h <= sh;
+			if let Some(chunks) = shared.get(&piece.crop).filter(|_| shareable) {
+				return Vec::clone(chunks);
+			}
+
+			let part = image.crop_imm(x, y, w, h);
+			let base = base.map(|base| base.crop_imm(x, y, w, h));
+			let chunks = encode(opt, &part, base.as_ref(), (sw, sh), (px, py), no_offset);
+			if shareable {
+				shared.insert(piece.crop, chunks.clone());
+			}
+			chunks
+		})
+		.collect()
+}
+
+/// Re-encodes the `dirty` regions of `image`, keeping the chunks outside of them
+fn patch(opt: &Opt, image: &image::DynamicImage, chunks: &[Chunk], dirty: &dirty::Dirty, canvas: (u32, u32), (xoff, yoff): (u32, u32), no_offset: bool) -> Vec<Chunk>
+{
+	let stale = |line: &str| {
+		let mut args = line.split_ascii_whitespace().skip(1);
+		let at = (|| Some((u32::from_str(args.next()?).ok()?, u32::from_str(args.next()?).ok()?)))();
+		let at = match at {
+			Some(at) if no_offset => opt.canvas_coords(at, canvas)
+				.and_then(|(x, y)| x.checked_sub(xoff).zip(y.checked_sub(yoff))),
+			at => at,
+		};
+		at.is_some_and(|(x, y)| dirty.contains(x, y))
+	};
+
+	let mut kept = Vec::with_capacity(chunks.len());
+	let mut left = Vec::new();
+	for chunk in chunks {
+		if !chunk.lines().any(stale) {
+			kept.push(chunk.clone());
+			continue;
+		}
+		left.extend(chunk.split_inclusive('\n').filter(|line| !stale(line)).map(str::to_owned));
+	}
+
+	// the base differs from the image only in the dirty regions, so only they are encoded
+	let mut base = image.to_rgba8();
+	for &(x, y, w, h) in dirty.rects() {
+		for y in y..y + h {
+			for x in x..x + w {
+				base.get_pixel_mut(x, y).0[0] ^= 1;
+			}
+		}
+	}
+	let base = image::DynamicImage::ImageRgba8(base);
+	kept.extend(chunk(left.into_iter()));
+	kept.extend(encode(opt, image, Some(&base), canvas, (xoff, yoff), no_offset));
+	kept
+}
+
+/// Size of the blocks compared to find changed regions of new images
+const DIRTY_BLOCK: u32 = 32;
+
+const CHUNK_LEN: usize = chunksize::MIN;
+
+/// Turns the image into shuffled chunks of PX commands
+///
+/// Pixels which are the same in `base` are skipped.
+fn encode(opt: &Opt, image: &image::DynamicImage, base: Option<&image::DynamicImage>, (sw, sh): (u32, u32), (xoff, yoff): (u32, u32), no_offset: bool) -> Vec<Chunk>
+{
+	let progress = progress::current();
+	let opts = pixel::Opts {
+		mask: opt.color.0,
+		same_ch_opt: opt.same_ch_opt,
+		grey_metric: opt.grey_metric,
+		grey_tolerance: opt.grey_tolerance(),
+		min_alpha: opt.min_alpha(),
+		// only decided once the server is known
+		grey_wire: opt.grey_wire == GreyWire::On,
+		strip_alpha: opt.wire_optimize,
+	};
+	// all commands go into one buffer instead of a string each
+	let mut wire = String::new();
+	let pxls = image.pixels()
+		.inspect(|_| if let Some(progress) = progress.as_ref() {
+			progress.inc(1);
+		})
+		.filter(|(_x, _y, color)| opts.visible(color.0[3]))
+		.filter(|(x, y, color)| base.is_none_or(|base| base.get_pixel(*x, *y) != *color))
+		.filter_map(|(x, y, color)| {
+			let (cx, cy) = match opt.overflow {
+				Overflow::Wrap => ((x + xoff) % sw, (y + yoff) % sh),
+				_ if x + xoff >= sw || y + yoff >= sh => return None,
+				_ => (x + xoff, y + yoff),
+			};
+			if opt.keepout.as_ref().is_some_and(|keepout| keepout.forbids(cx, cy)) {
+				return None;
+			}
+			Some(if no_offset {
+				let (cx, cy) = opt.server_coords((cx, cy), (sw, sh));
+				(cx, cy, color)
+			} else {
+				(x, y, color)
+			})
+		})
+		.map(|(x, y, color)| {
+			let start = wire.len();
+			writeln!(wire, "{}", pixel::encode_pixel(opt.filter, &opts, x, y, color.0)).unwrap();
+			let a = color.0[3];
+			(start..wire.len(), a)
+		})
+		.collect::<Vec<_>>();
+	let pxls = pxls.into_iter()
+		.map(|(range, a)| (&wire[range], a))
+		.collect();
+
+	let pxls = arrange(pxls, opt.order, opt.interleave, |(px, _a)| *px);
+	let pxls = match opt.soft_start {
+		Some(block) => progressive(pxls, block, |(px, _a)| *px),
+		None => pxls,
+	};
+
+	log::debug!("pixels: {}", pxls.len());
+	if opt.alpha_period == 1 {
+		return chunk(pxls.into_iter().map(|(px, _a)| px));
+	}
+
+	// one cycle per period, the opaque chunks are shared between them
+	let n = opt.alpha_period;
+	let (opaque, semi): (Vec<_>, Vec<_>) = pxls.into_iter()
+		.partition(|(_px, a)| *a == 0xff);
+	let opaque = chunk(opaque.into_iter().map(|(px, _a)| px));
+	// sends per cycle proportional to alpha, spread evenly over the cycle
+	let semi = semi.into_iter()
+		.map(|(px, a)| (px, ((n * a as u32 + 0x7f) / 0xff).max(1)))
+		.collect::<Vec<_>>();
+
+	(0..n)
+		.flat_map(|k| {
+			let semi = semi.iter()
+				.filter(|(_px, sends)| k * sends % n < *sends)
+				.map(|(px, _sends)| *px);
+			opaque.iter().cloned().chain(chunk(semi))
+		})
+		.collect()
+}
+
+/// Packs PX commands into chunks of up to `CHUNK_LEN` bytes
+fn chunk(pxls: impl Iterator<Item = impl AsRef<str>>) -> Vec<Chunk>
+{
+	pxls
+		.fold(vec![ String::with_capacity(CHUNK_LEN) ], |mut buf, px|
+		{
+			let px = px.as_ref();
+			let mut chunk = buf.last_mut().unwrap();
+			if chunk.len() + px.len() > CHUNK_LEN {
+				buf.push(String::with_capacity(CHUNK_LEN));
+				chunk = buf.last_mut().unwrap();
+			}
+			chunk.push_str(px);
+			buf
+		})
+		.into_iter()
+		.filter(|chunk| !chunk.is_empty())
+		.map(Chunk::from)
+		.collect::<Vec<_>>()
+}
+
+/// Shuffles the pixels, then sorts them by color or ring for the other orders
+///
+/// With `interleave` above 1 the pixels are taken in turns from at least as many
+/// regions of the image, keeping the order within each region.
+fn arrange<T>(mut pxls: Vec<T>, order: Order, interleave: u32, px: impl Fn(&T) -> &str) -> Vec<T>
+{
+	pxls.shuffle(&mut rand::thread_rng());
+	let at = |p: &T| coords_of(px(p));
+	let (mut min, mut max) = ((i64::MAX, i64::MAX), (i64::MIN, i64::MIN));
+	if order != Order::Random || interleave > 1 {
+		for (x, y) in pxls.iter().map(at) {
+			min = (min.0.min(x), min.1.min(y));
+			max = (max.0.max(x), max.1.max(y));
+		}
+	}
+	match order {
+		Order::Random => {},
+		Order::Color => pxls.sort_by(|a, b| color_of(px(a)).cmp(color_of(px(b)))),
+		Order::Inward | Order::Outward => {
+			// rings around the bounding box
+			let ring = |p: &T| {
+				let (x, y) = at(p);
+				match order {
+					Order::Inward => (x - min.0).min(max.0 - x).min(y - min.1).min(max.1 - y),
+					_ => (2 * x - min.0 - max.0).abs().max((2 * y - min.1 - max.1).abs()),
+				}
+			};
+			pxls.sort_by_cached_key(ring);
+		},
+	}
+
+	if interleave <= 1 || pxls.is_empty() {
+		return pxls;
+	}
+	// a grid of about square cells over the bounding box
+	let cols = (interleave as f64).sqrt().ceil() as i64;
+	let rows = (interleave as i64 + cols - 1) / cols;
+	let (w, h) = (max.0 - min.0 + 1, max.1 - min.1 + 1);
+	let mut taken = vec![0usize; (cols * rows) as usize];
+	let turns = pxls.iter()
+		.map(|p| {
+			let (x, y) = at(p);
+			let region = ((y - min.1) * rows / h * cols + (x - min.0) * cols / w) as usize;
+			taken[region] += 1;
+			(taken[region], region)
+		})
+		.collect::<Vec<_>>();
+	let mut sorted = (0..pxls.len()).collect::<Vec<_>>();
+	sorted.sort_by_key(|&i| turns[i]);
+	let mut pxls = pxls.into_iter().map(Some).collect::<Vec<_>>();
+	sorted.into_iter().filter_map(|i| pxls[i].take()).collect()
+}
+
+/// Puts the pixels on a grid of `block` first, then those on grids of half the spacing
+/// until all are in, keeping their order within each grid
+fn progressive<T>(mut pxls: Vec<T>, block: u32, px: impl Fn(&T) -> &str) -> Vec<T>
+{
+	let origin = pxls.iter()
+		.map(|p| coords_of(px(p)))
+		.fold((i64::MAX, i64::MAX), |(x0, y0), (x, y)| (x0.min(x), y0.min(y)));
+	let spacing = |p: &T| {
+		let (x, y) = coords_of(px(p));
+		std::iter::successors(Some(block as i64), |&s| (s > 1).then_some(s / 2))
+			.find(|&s| (x - origin.0) % s == 0 && (y - origin.1) % s == 0)
+			.unwrap_or(1)
+	};
+	pxls.sort_by_cached_key(|p| std::cmp::Reverse(spacing(p)));
+	pxls
+}
+
+/// The coordinates of a `PX x y color` command
+fn coords_of(px: &str) -> (i64, i64)
+{
+	let mut args = px.split_ascii_whitespace().skip(1);
+	(|| Some((i64::from_str(args.next()?).ok()?, i64::from_str(args.next()?).ok()?)))()
+		.unwrap_or_default()
+}
+
+/// The color of a `PX x y color` command
+fn color_of(px: &str) -> &str
+{
+	px.trim_end().rsplit(' ').next().unwrap_or_default()
+}
+
+/// Permutes the pixels of each lane across chunk boundaries
+fn reshuffle(frame: &Frame, order: Order, interleave: u32) -> Frame
+{
+	frame.iter()
+		.map(|chunks| {
+			let pxls = chunks.iter()
+				.flat_map(|chunk| chunk.split_inclusive('\n'))
+				.collect::<Vec<_>>();
+			let pxls = arrange(pxls, order, interleave, |px| *px);
+			chunk(pxls.into_iter())
+		})
+		.collect()
+}
+
+/// Keeps a random `rate` of the pixels of a chunk, weighted by their opacity
+fn sample(chunk: &str, rate: f64, rng: &mut impl Rng) -> String
+{
+	chunk.split_inclusive('\n')
+		.filter(|px| {
+			let alpha = px.split_ascii_whitespace().nth(3)
+				.and_then(server::parse_color)
+				.map_or(0xff, |c| c.0[3]);
+			rng.gen_bool(rate * alpha as f64 / 255.0)
+		})
+		.collect()
+}
+
+/// Paints the pixels back over a new connection
+async fn restore(host: std::net::SocketAddr, pixels: &std::collections::HashMap<(u32, u32), [u8; 3]>) -> anyhow::Result<()>
+{
+	let mut stream = net::TcpStream::connect(host).await
+		.context("failed to connect")?;
+	let pxls = pixels.iter()
+		.map(|((x, y), [r, g, b])| format!("PX {} {} {:02X}{:02X}{:02X}\n", x, y, r, g, b))
+		.collect::<String>();
+	stream.write_all(pxls.as_bytes()).await
+		.context("failed to restore pixels")?;
+	stream.shutdown().await?;
+	Ok(())
+}
+
+/// Latency probing of the connections
+#[derive(Clone, Debug)]
+struct Ping
+{
+	interval: time::Duration,
+	/// Where to report the measured round trip times to
+	rtt: sync::mpsc::UnboundedSender<(usize, time::Duration)>,
+}
+
+/// Read-back of sent pixels
+#[derive(Clone, Debug)]
+struct Verify
+{
+	/// Fraction of pixels to read back
+	rate: f64,
+	/// Where to report whether a pixel at absolute coordinates still has our color
+	matches: sync::mpsc::UnboundedSender<((u32, u32), bool)>,
+	/// Hands the samples to the control connection instead of reading them back on the worker
+	control: Option<sync::mpsc::UnboundedSender<control::Sample>>,
+}
+
+/// How often the throughput per IP family is logged
+const THROUGHPUT_REPORT: time::Duration = time::Duration::from_secs(5);
+
+/// How often the quantiles of the send timings are logged
+const TIMINGS_REPORT: time::Duration = time::Duration::from_secs(10);
+
+/// Runtime and blocking threads of the low-power profile
+const LOW_POWER_THREADS: usize = 2;
+
+/// How often the e-ink profile sends the whole image
+const EINK_REFRESH: time::Duration = time::Duration::from_secs(30);
+
+/// How long a reloaded source may take for its first image
+const RELOAD_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// How often the verified ownership is logged
+const VERIFY_REPORT: time::Duration = time::Duration::from_secs(5);
+
+/// Fraction of chunks left out per cycle in stealth mode
+const STEALTH_SKIP: f64 = 0.2;
+/// Read-back decoys sent along with every chunk in stealth mode
+const STEALTH_DECOYS: usize = 4;
+
+/// Traffic disguise of the connections
+#[derive(Clone, Debug)]
+struct Stealth
+{
+	/// How long a connection lives on average before it is replaced
+	rotate: time::Duration,
+	canvas: (u32, u32),
+}
+
+/// Optional behavior shared by all workers
+#[derive(Clone, Debug, Default)]
+struct ClientOpt
+{
+	ping: Option<Ping>,
+	stealth: Option<Stealth>,
+	verify: Option<Verify>,
+	/// Claim command sent on every connect
+	claim: Option<String>,
+	/// Send rate adapted to the throttle notices of the server
+	limit: Arc<ratelimit::Aimd>,
+	timings: Option<Arc<histogram::Timings>>,
+	tee: Option<tee::Tee>,
+	/// Chunks queued per connection, if not the default
+	queue_depth: Option<usize>,
+	drop_policy: queue::Policy,
+	/// Most chunks per write
+	max_batch: usize,
+	proxy: Option<socks::Proxy>,
+	/// Lines and bytes received
+	inbound: Option<Arc<[AtomicU64; 2]>>,
+	ledger: Option<Arc<ledger::Ledger>>,
+	/// Faults injected for testing
+	chaos: Option<chaos::Chaos>,
+	/// Added to the OFFSET for servers counting from 1
+	index_base: u32,
+	/// Network devices taken in turns
+	interfaces: Vec<String>,
+	/// Connections made by each worker so far
+	connects: Arc<std::sync::Mutex<std::collections::HashMap<usize, u64>>>,
+}
+
+/// Log target of the workers, see --log-workers
+const WORKER: &str = "pixelspray::worker";
+
+/// Longest line read from a server, longer ones are taken in pieces
+const MAX_LINE: u64 = 4096;
+
+/// Reads the next line, or `None` at the end, putting up with invalid UTF-8
+async fn next_line(rd: &mut (impl io::AsyncBufRead + Unpin), buf: &mut Vec<u8>) -> std::io::Result<Option<String>>
+{
+	buf.clear();
+	if (&mut *rd).take(MAX_LINE).read_until(b'\n', buf).await? == 0 {
+		return Ok(None);
+	}
+	Ok(Some(String::from_utf8_lossy(buf).trim_end().to_owned()))
+}
+
+/// Writes the chunks with as few system calls as the socket takes them in
+async fn write_chunks(wr: &mut (impl io::AsyncWrite + Unpin), chunks: &[Chunk]) -> std::io::Result<()>
+{
+	let mut slices = chunks.iter()
+		.map(|chunk| std::io::IoSlice::new(chunk.as_bytes()))
+		.collect::<Vec<_>>();
+	let mut slices = &mut slices[..];
+	while !slices.is_empty() {
+		let n = wr.write_vectored(slices).await?;
+		if n == 0 {
+			return Err(std::io::ErrorKind::WriteZero.into());
+		}
+		std::io::IoSlice::advance_slices(&mut slices, n);
+	}
+	Ok(())
+}
+
+/// Spawns a worker, connecting to `host_addr` unless a `stream` is already established
+///
+/// In stealth mode the worker finishes without error once its time is up, to be respawned.
+fn client(id: usize, host_addr: std::net::SocketAddr, stream: Option<net::TcpStream>, offset: Option<(u32, u32)>, client_opt: ClientOpt) -> (queue::Sender<Chunk>, task::JoinHandle<anyhow::Result<usize>>) {
+	let ClientOpt { ping, stealth, verify, claim, limit, timings, tee, queue_depth, drop_policy, max_batch, proxy, inbound, ledger, chaos, index_base, interfaces, connects } = client_opt;
+	let (tx, mut rx) = queue::channel::<Chunk>(queue_depth.unwrap_or(queue::DEPTH), drop_policy);
+	let reconnects = {
+		let mut connects = connects.lock().unwrap();
+		let n = connects.entry(id).or_default();
+		*n += 1;
+		*n - 1
+	};
+	// the bytes are recorded once the connection is over
+	let span = log::info_span!(target: WORKER, "worker", id, peer = %host_addr, reconnects, bytes = log::field::Empty);
+
+	let task = spawn(async move {
+		let stream = match stream {
+			Some(stream) => stream,
+			None => match (proxy, interfaces.get(id % interfaces.len().max(1))) {
+				(Some(proxy), _) => proxy.connect(host_addr).await?,
+				(None, Some(interface)) => interface::connect(host_addr, interface).await
+					.with_context(|| format!("failed to connect through {}", interface))?,
+				(None, None) => net::TcpStream::connect(host_addr).await
+					.context("failed to connect")?,
+			},
+		};
+
+		log::info!(target: WORKER, "connected...");
+		// everything after this is in the coordinates of the server
+		let offset = offset.map(|(x, y)| (x + index_base, y + index_base));
+		if let Err(err) = stream.set_nodelay(true) {
+			log::warn!(target: WORKER, "failed to set no delay: {}", err);
+		}
+		let (rd, mut stream) = stream.into_split();
+
+		if let Some(claim) = claim {
+			stream.write_all(claim.as_bytes()).await
+				.context("failed to send claim")?;
+		}
+		if let Some(offset) = offset {
+			let offset = format!("OFFSET {} {}\n", offset.0, offset.1);
+			stream.write_all(offset.as_bytes()).await
+				.context("failed to send offset")?;
+		}
+
+		// pings queue up behind the chunks, so the replies also reflect congestion
+		let (sent_tx, mut sent) = sync::mpsc::unbounded_channel::<time::Instant>();
+		let mut ticker = ping.as_ref().map(|ping| time::interval(ping.interval));
+		// read-backs in order of sending, with the expected color unless it is a decoy
+		let (reads_tx, mut reads) = sync::mpsc::unbounded_channel::<(String, Option<((u32, u32), image::Rgba<u8>)>)>();
+		let rate = verify.as_ref().map_or(0.0, |verify| verify.rate);
+		let control = verify.as_ref().and_then(|verify| verify.control.clone());
+		// whether read-backs are answered on this connection
+		let local = rate > 0.0 && control.is_none();
+		// replies and server notices like rate limits, everything else is drained
+		// so servers answering every PX do not stall the connection
+		let limiter = limit.clone();
+		let pong = spawn(async move {
+			let mut rd = io::BufReader::new(rd);
+			let mut buf = Vec::new();
+			while let Ok(Some(line)) = next_line(&mut rd, &mut buf).await {
+				if let Some(inbound) = inbound.as_ref() {
+					inbound[0].fetch_add(1, Ordering::Relaxed);
+					inbound[1].fetch_add(buf.len() as u64, Ordering::Relaxed);
+				}
+				if ratelimit::is_throttle(&line) {
+					log::debug!(target: WORKER, "server: {}", line);
+					limiter.throttled();
+					continue;
+				}
+				if let Some(reply) = line.strip_prefix("PX ") {
+					let Some(verify) = verify.as_ref() else { continue };
+					let mut args = reply.split_ascii_whitespace();
+					let (Some(x), Some(y), Some(color)) = (args.next(), args.next(), args.next()) else { continue };
+					let at = format!("{} {}", x, y);
+					// servers may skip reads, so catch up to the reply
+					while let Ok((read, expected)) = reads.try_recv() {
+						if read != at {
+							continue;
+						}
+						if let Some((abs, expected)) = expected {
+							let color = server::parse_color(color.get(..6).unwrap_or(color));
+							verify.matches.send((abs, color.is_some_and(|c| c.0[..3] == expected.0[..3]))).ok();
+						}
+						break;
+					}
+					continue;
+				}
+				let Some(ping) = ping.as_ref().filter(|_| line.starts_with("SIZE")) else {
+					continue;
+				};
+				if let Some(at) = sent.recv().await {
+					ping.rtt.send((id, at.elapsed())).ok();
+				}
+			}
+		}.in_current_span());
+
+		// staggered, so the connections are not all replaced at once
+		let lifetime = stealth.as_ref()
+			.map(|stealth| stealth.rotate.mul_f64(rand::thread_rng().gen_range(0.5..1.5)));
+		let expired = async {
+			match lifetime {
+				Some(lifetime) => time::sleep(lifetime).await,
+				None => futures::future::pending().await,
+			}
+		}.fuse();
+		futures::pin_mut!(expired);
+		let mut faults = chaos.as_ref().map(chaos::Chaos::connection);
+		let lifetime = faults.as_ref().and_then(|faults| faults.lifetime);
+		let killed = async {
+			match lifetime {
+				Some(lifetime) => time::sleep(lifetime).await,
+				None => futures::future::pending().await,
+			}
+		}.fuse();
+		futures::pin_mut!(killed);
+		let decoys = stealth.as_ref().map(|stealth| {
+			let (ox, oy) = offset.unwrap_or((0, 0));
+			(stealth.canvas.0.saturating_sub(ox).max(1), stealth.canvas.1.saturating_sub(oy).max(1))
+		});
+
+		let mut size = chunksize::ChunkSize::default();
+		let mut bytes = 0u64;
+		let res = async {
+			loop {
+				let tick = async {
+					match ticker.as_mut() {
+						Some(ticker) => ticker.tick().await,
+						None => futures::future::pending().await,
+					}
+				};
+				futures::select! {
+					chunk = rx.recv().fuse() => {
+						let Some(chunk) = chunk else { break };
+						// queued chunks go out together, up to the write size
+						let mut len = chunk.len();
+						let mut batch = vec![ chunk ];
+						while len < size.get() && batch.len() < max_batch {
+							let Some(next) = rx.try_recv() else { break };
+							len += next.len();
+							batch.push(next);
+						}
+						if let Some(ledger) = ledger.as_ref() {
+							for chunk in batch.iter() {
+								ledger.record(chunk, offset);
+							}
+						}
+						if let Some(faults) = faults.as_mut() {
+							if faults.kill() {
+								anyhow::bail!("chaos: connection dropped");
+							}
+							for chunk in batch.iter_mut() {
+								if let Some(garbled) = faults.corrupt(chunk) {
+									*chunk = garbled;
+								}
+							}
+						}
+						//log::debug!("sending {} bytes: {}...", chunk.len(), chunk.split_at(16).0);
+						limit.acquire(len).await;
+						let start = time::Instant::now();
+						write_chunks(&mut stream, &batch).await
+							.context("failed to send chunk")?;
+						bytes += len as u64;
+						let elapsed = start.elapsed();
+						if let Some(timings) = timings.as_ref() {
+							timings.chunks.record(elapsed);
+						}
+						if let Some(size) = size.record(len, elapsed) {
+							log::debug!(target: WORKER, "writing {} bytes at once", size);
+						}
+						if let Some(tee) = tee.as_ref() {
+							for chunk in batch.iter() {
+								tee.send(offset, chunk.clone()).await;
+							}
+						}
+						let mut reads = String::new();
+						if let Some((w, h)) = decoys {
+							for _ in 0..STEALTH_DECOYS {
+								let mut rng = rand::thread_rng();
+								let at = format!("{} {}", rng.gen_range(0..w), rng.gen_range(0..h));
+								reads += &format!("PX {}\n", at);
+								if local {
+									reads_tx.send((at, None)).ok();
+								}
+							}
+						}
+						if rate > 0.0 {
+							let mut rng = rand::thread_rng();
+							// only opaque pixels have a known color on the canvas
+							let samples = batch.iter()
+								.flat_map(|chunk| chunk.lines())
+								.filter_map(|line| {
+									let mut args = line.split_ascii_whitespace().skip(1);
+									let (x, y, color) = (args.next()?, args.next()?, args.next()?);
+									let at = (u32::from_str(x).ok()?, u32::from_str(y).ok()?);
+									Some((at, server::parse_color(color).filter(|c| c.0[3] == 0xff)?))
+								})
+								.filter(|_| rng.gen_bool(rate));
+							for ((x, y), color) in samples {
+								let (ox, oy) = offset.unwrap_or((0, 0));
+								match control.as_ref() {
+									Some(control) => {
+										control.send(((x + ox, y + oy), color.to_rgb().0)).ok();
+									},
+									None => {
+										let at = format!("{} {}", x, y);
+										reads += &format!("PX {}\n", at);
+										reads_tx.send((at, Some(((x + ox, y + oy), color)))).ok();
+									},
+								}
+							}
+						}
+						if !reads.is_empty() {
+							stream.write_all(reads.as_bytes()).await
+								.context("failed to send reads")?;
+						}
+					},
+					_ = expired => {
+						log::debug!(target: WORKER, "rotating connection");
+						break;
+					},
+					_ = killed => {
+						anyhow::bail!("chaos: connection killed");
+					},
+					_ = tick.fuse() => {
+						sent_tx.send(time::Instant::now()).ok();
+						stream.write_all(b"SIZE\n").await
+							.context("failed to send ping")?;
+					},
+				}
+			}
+			anyhow::Ok(())
+		}.await;
+
+		// the reader keeps the connection open otherwise
+		pong.abort();
+		log::Span::current().record("bytes", bytes);
+		match res.as_ref() {
+			Ok(()) => log::debug!(target: WORKER, "disconnected"),
+			Err(err) => log::debug!(target: WORKER, "disconnected: {:#}", err),
+		}
+		res.map(|_| id)
+	}.instrument(span));
+
+	(tx, task)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use std::{prelude::v1::test, sync::atomic::Ordering};
+	use image::{DynamicImage, Rgba, RgbaImage};
+
+	const CANVAS: (u32, u32) = (16, 16);
+
+	fn opt(args: &[&str]) -> Opt
+	{
+		Opt::parse_from(["pixelspray", "127.0.0.1:0", "test.png"].iter().chain(args))
+	}
+
+	#[tokio::test]
+	async fn source_fails_first()
+	{
+		let missing = std::env::temp_dir().join(format!("pixelspray-missing-{}.png", std::process::id()));
+		let opt = Opt::parse_from(["pixelspray", "127.0.0.1:1", missing.to_str().unwrap()]);
+		let res = time::timeout(time::Duration::from_secs(5), run(opt)).await
+			.expect("the run hangs on a failing source");
+		assert!(res.unwrap_err().to_string().contains("failed to"));
+	}
+
+	fn image() -> DynamicImage
+	{
+		DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, y| {
+			let a = if (x, y) == (0, 0) { 0 } else { 0xff };
+			Rgba([x as u8 * 0x40, y as u8 * 0x40, 0x80, a])
+		}))
+	}
+
+	/// Sprays the frame over one connection per lane into a fresh server
+	async fn spray(lanes: Frame, offsets: &[Option<(u32, u32)>]) -> Arc<server::State>
+	{
+		let server = server::Server::bind("127.0.0.1:0".parse().unwrap(), CANVAS).await.unwrap();
+		let addr = server.local_addr().unwrap();
+		let state = server.state();
+		spawn(server.run());
+
+		let pixels = lanes.iter().flatten()
+			.map(|chunk| chunk.lines().count() as u64)
+			.sum::<u64>();
+		for (id, chunks) in lanes.into_iter().enumerate() {
+			let (tx, task) = client(id, addr, None, offsets[id], ClientOpt::default());
+			for chunk in chunks {
+				tx.send(chunk).await.unwrap();
+			}
+			drop(tx);
+			assert_eq!(task.await.unwrap().unwrap(), id);
+		}
+
+		while state.pixels.load(Ordering::Relaxed) < pixels {
+			time::sleep(time::Duration::from_millis(10)).await;
+		}
+		state
+	}
+
+	fn painted(state: &server::State) -> Vec<(u32, u32, Rgba<u8>)>
+	{
+		state.canvas.lock().unwrap()
+			.enumerate_pixels()
+			.filter(|(_, _, px)| px.0[3] != 0)
+			.map(|(x, y, px)| (x, y, *px))
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn rgba_with_offset()
+	{
+		let opt = opt(&[]);
+		let lanes = frame(&opt, &image(), None, CANVAS, (3, 2), false, &[]);
+		let state = spray(lanes, &[Some((3, 2))]).await;
+
+		let painted = painted(&state);
+		assert_eq!(painted.len(), 15);
+		for (x, y, px) in painted {
+			assert_eq!(px, *image().as_rgba8().unwrap().get_pixel(x - 3, y - 2));
+		}
+	}
+
+	#[tokio::test]
+	async fn mask_without_offset()
+	{
+		let opt = opt(&["-f", "mask", "--filter-color", "127", "--no-offset"]);
+		let lanes = frame(&opt, &image(), None, CANVAS, (12, 12), true, &[]);
+		let state = spray(lanes, &[None]).await;
+
+		let painted = painted(&state);
+		assert_eq!(painted.len(), 15);
+		assert!(painted.iter().all(|&(x, y, px)| x >= 12 && y >= 12 && px == Rgba([0x7f, 0x7f, 0x7f, 0xff])));
+	}
+
+	#[tokio::test]
+	async fn clip_overflow()
+	{
+		let opt = opt(&["-f", "grey"]);
+		let lanes = frame(&opt, &image(), None, CANVAS, (14, 14), false, &[]);
+		let state = spray(lanes, &[Some((14, 14))]).await;
+
+		let painted = painted(&state);
+		assert_eq!(painted.len(), 3);
+		assert!(painted.iter().all(|&(x, _, px)| px.0[0] == (x as u8 - 14) * 0x40 && px.0[0] == px.0[1]));
+	}
+
+	#[test]
+	fn grey_wire()
+	{
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([0x40, 0x42, 0x41, 0xff])));
+		let px = |args: &[&str]| encode(&opt(args), &image, None, CANVAS, (0, 0), false)[0].to_string();
+		// without a profile telling so, the default does not assume it
+		assert_eq!(px(&["-f", "grey"]), "PX 0 0 404040\n");
+		assert_eq!(px(&["-f", "grey", "--grey-wire", "on"]), "PX 0 0 40\n");
+		// close enough to grey under -c
+		assert_eq!(px(&["-c"]), "PX 0 0 414141\n");
+		assert_eq!(px(&["-c", "--grey-wire", "on"]), "PX 0 0 41\n");
+		assert_eq!(px(&["--grey-wire", "on"]), "PX 0 0 404241\n");
+		// not without knowing the server takes it
+		assert_eq!(px(&["-f", "grey", "--wire-optimize"]), "PX 0 0 404040\n");
+		assert_eq!(px(&["-f", "grey", "--wire-optimize", "--grey-wire", "on"]), "PX 0 0 40\n");
+	}
+
+	/// Expected commands for every filter and flag over a fixture of the tricky pixels
+	#[test]
+	fn golden()
+	{
+		let pixels = [
+			[0xff, 0, 0, 0xff], [0x40, 0x40, 0x40, 0xff], [0x40, 0x42, 0x41, 0xff],
+			[0x20, 0x40, 0x80, 0x80], [0x10, 0x20, 0x30, 0x0a], [0, 0, 0, 0],
+		];
+		let fixture = DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 2, |x, y| Rgba(pixels[(y * 3 + x) as usize])));
+		let commands = |args: &[&str]| {
+			let mut lines = encode(&opt(args), &fixture, None, CANVAS, (0, 0), false).iter()
+				.flat_map(|chunk| chunk.lines().map(str::to_owned).collect::<Vec<_>>())
+				.collect::<Vec<_>>();
+			lines.sort();
+			lines.join(",")
+		};
+		let golden = [
+			(&[][..], "PX 0 0 FF0000,PX 0 1 20408080,PX 1 0 404040,PX 2 0 404241"),
+			(&["-l"], "PX 0 0 FF0000,PX 0 1 20408080,PX 1 0 404040,PX 1 1 1020300A,PX 2 0 404241"),
+			(&["-f", "grey"], "PX 0 0 FFFFFF,PX 0 1 202020,PX 1 0 404040,PX 2 0 404040"),
+			(&["-f", "grey", "--grey-wire", "on"], "PX 0 0 FF,PX 0 1 20,PX 1 0 40,PX 2 0 40"),
+			(&["-f", "mask"], "PX 0 0 FFFFFF,PX 0 1 FFFFFF,PX 1 0 FFFFFF,PX 2 0 FFFFFF"),
+			(&["-f", "mask", "--grey-wire", "on"], "PX 0 0 FF,PX 0 1 FF,PX 1 0 FF,PX 2 0 FF"),
+			(&["-f", "mask", "--filter-color", "#ff800080"], "PX 0 0 FF800080,PX 0 1 FF800080,PX 1 0 FF800080,PX 2 0 FF800080"),
+			(&["-c"], "PX 0 0 FF0000,PX 0 1 20408080,PX 1 0 404040,PX 2 0 414141"),
+			(&["-c", "-l"], "PX 0 0 FF0000,PX 0 1 20408080,PX 1 0 404040,PX 1 1 1020300A,PX 2 0 404241"),
+			(&["-c", "--grey-wire", "on"], "PX 0 0 FF0000,PX 0 1 20408080,PX 1 0 40,PX 2 0 41"),
+			(&["--grey-wire", "on"], "PX 0 0 FF0000,PX 0 1 20408080,PX 1 0 40,PX 2 0 404241"),
+		];
+		for (args, expected) in golden.iter() {
+			assert_eq!(commands(args), *expected, "{:?}", args);
+		}
+	}
+
+	#[tokio::test]
+	async fn drains_replies()
+	{
+		let mut data = b"OK\r\nPX 1 2 ff\xff\n".to_vec();
+		data.extend(vec![b'x'; MAX_LINE as usize + 10]);
+		let mut rd = &data[..];
+		let mut buf = Vec::new();
+		assert_eq!(next_line(&mut rd, &mut buf).await.unwrap().as_deref(), Some("OK"));
+		assert_eq!(next_line(&mut rd, &mut buf).await.unwrap().as_deref(), Some("PX 1 2 ff\u{fffd}"));
+		assert_eq!(next_line(&mut rd, &mut buf).await.unwrap().map(|line| line.len()), Some(MAX_LINE as usize));
+		assert_eq!(next_line(&mut rd, &mut buf).await.unwrap().map(|line| line.len()), Some(10));
+		assert_eq!(next_line(&mut rd, &mut buf).await.unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn vectored_batch()
+	{
+		let chunks = ["PX 0 0 ff\n", "", "PX 1 0 00\nPX 2 0 80\n"].map(|chunk| Chunk::from(chunk.to_owned()));
+		// takes only part of the buffers per write
+		let (mut wr, mut rd) = io::duplex(4);
+		let (res, out) = futures::join!(write_chunks(&mut wr, &chunks), async {
+			let mut out = vec![0; 30];
+			rd.read_exact(&mut out).await.map(|_| out)
+		});
+		res.unwrap();
+		assert_eq!(String::from_utf8(out.unwrap()).unwrap(), "PX 0 0 ff\nPX 1 0 00\nPX 2 0 80\n");
+	}
+
+	#[tokio::test]
+	async fn resolve_host()
+	{
+		assert_eq!(resolve("127.0.0.1:1337").await.unwrap(), ["127.0.0.1:1337".parse().unwrap()]);
+		let addrs = resolve("localhost:1337").await.unwrap();
+		assert!(!addrs.is_empty() && addrs.iter().all(|addr| addr.ip().is_loopback() && addr.port() == 1337));
+		assert!(resolve("127.0.0.1").await.is_err());
+	}
+
+	#[test]
+	fn low_power()
+	{
+		let opt = opt(&["--profile", "low-power", "--worker-threads", "4"]).preset();
+		assert_eq!(opt.scaling, Some(transform::Scaling::Nearest));
+		assert_eq!((opt.worker_threads, opt.blocking_threads), (Some(4), Some(LOW_POWER_THREADS)));
+		assert!(opt.no_timings);
+		assert_eq!(self::opt(&[]).preset().scaling, None);
+	}
+
+	#[test]
+	fn examples()
+	{
+		assert!(Opt::try_parse_from(["pixelspray", "--examples"]).unwrap().examples);
+		assert!(Opt::try_parse_from(["pixelspray", "--examples", "-n", "2"]).is_err());
+		let opt = Opt::try_parse_from(["pixelspray", "completions", "zsh"]).unwrap();
+		assert!(matches!(opt.command, Some(Command::Completions(CompletionsOpt { shell: completions::Shell::Zsh }))));
+	}
+
+	#[tokio::test]
+	async fn tiles_per_connection()
+	{
+		let opt = opt(&["--tiles", "-n", "4"]);
+		let tiles = tiles(4, (4, 4));
+		let crops = tiles.iter().map(|tile| tile.crop).collect::<Vec<_>>();
+		assert_eq!(crops, [(0, 0, 2, 2), (2, 0, 2, 2), (0, 2, 2, 2), (2, 2, 2, 2)]);
+
+		let lanes = frame(&opt, &image(), None, CANVAS, (8, 8), false, &tiles);
+		let offsets = tiles.iter()
+			.map(|tile| Some((8 + tile.at.0, 8 + tile.at.1)))
+			.collect::<Vec<_>>();
+		let state = spray(lanes, &offsets).await;
+
+		let painted = painted(&state);
+		assert_eq!(painted.len(), 15);
+		for (x, y, px) in painted {
+			assert_eq!(px, *image().as_rgba8().unwrap().get_pixel(x - 8, y - 8));
+		}
+	}
+
+	#[test]
+	fn alpha_period()
+	{
+		let opt = opt(&["--alpha-period", "4"]);
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 1, |x, _| Rgba([0, 0, 0, [0xff, 0x80, 0x10][x as usize]])));
+		let chunks = encode(&opt, &image, None, CANVAS, (0, 0), false);
+
+		let sends = |x| chunks.iter()
+			.flat_map(|chunk| chunk.lines())
+			.filter(|line| line.starts_with(&format!("PX {} 0 ", x)))
+			.count();
+		assert_eq!(sends(0), 4);
+		assert_eq!(sends(1), 2);
+		assert_eq!(sends(2), 1);
+	}
+
+	#[test]
+	fn alpha_threshold()
+	{
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 1, |x, _| Rgba([0, 0, 0, [0xff, 0x80, 0x10, 0][x as usize]])));
+		let sent = |args: &[&str]| encode(&opt(args), &image, None, CANVAS, (0, 0), false).iter()
+			.map(|chunk| chunk.lines().count())
+			.sum::<usize>();
+		assert_eq!(sent(&[]), 3);
+		assert_eq!(sent(&["--alpha-threshold", "128"]), 2);
+		assert_eq!(sent(&["--lossless", "--alpha-threshold", "255"]), 1);
+		assert_eq!(sent(&["--keep-transparent"]), 4);
+		assert!(Opt::try_parse_from(["pixelspray", "--alpha-threshold", "0"]).is_err());
+
+		// the deprecated --lossless only changes the defaults
+		assert_eq!(opt(&[]).grey_tolerance(), 4.0);
+		assert_eq!(opt(&["--lossless"]).grey_tolerance(), 0.0);
+		assert_eq!(opt(&["--lossless", "--grey-tolerance", "8"]).grey_tolerance(), 8.0);
+		assert_eq!(opt(&["--grey-metric", "delta-e"]).grey_tolerance(), 2.3);
+	}
+
+	#[tokio::test]
+	async fn duplicate_grid()
+	{
+		let opt = opt(&["--duplicate", "2x2:2", "-n", "4"]);
+		let copies = duplicates(opt.duplicate.unwrap(), (4, 4));
+		let lanes = frame(&opt, &image(), None, CANVAS, (1, 1), false, &copies);
+		assert_eq!(lanes[0][0].as_ptr(), lanes[3][0].as_ptr());
+
+		let offsets = copies.iter()
+			.map(|copy| Some((1 + copy.at.0, 1 + copy.at.1)))
+			.collect::<Vec<_>>();
+		let state = spray(lanes, &offsets).await;
+
+		let painted = painted(&state);
+		assert_eq!(painted.len(), 4 * 15);
+		for (x, y, px) in painted {
+			let (x, y) = ((x - 1) % 6, (y - 1) % 6);
+			assert_eq!(px, *image().as_rgba8().unwrap().get_pixel(x, y));
+		}
+	}
+
+	#[test]
+	fn reshuffle_keeps_pixels()
+	{
+		let pxls = (0..500).map(|i| format!("PX {} 0 FFFFFF\n", i)).collect::<Vec<_>>();
+		let lanes = vec![chunk(pxls.iter().cloned())];
+		let shuffled = reshuffle(&lanes, Order::Random, 1);
+
+		let lines = |frame: &Frame| {
+			let mut lines = frame[0].iter()
+				.flat_map(|chunk| chunk.lines().map(str::to_owned))
+				.collect::<Vec<_>>();
+			lines.sort();
+			lines
+		};
+		assert_ne!(shuffled, lanes);
+		assert_eq!(lines(&shuffled), lines(&lanes));
+		assert!(shuffled[0].iter().all(|chunk| chunk.len() <= CHUNK_LEN));
+	}
+
+	#[test]
+	fn interleave_regions()
+	{
+		let pxls = (0..400).map(|i| format!("PX {} {} FFFFFF\n", i % 20, i / 20)).collect::<Vec<_>>();
+		let quadrant = |px: &String| {
+			let (x, y) = coords_of(px);
+			(x / 10, y / 10)
+		};
+		let arranged = arrange(pxls.clone(), Order::Inward, 4, |px| px.as_str());
+		assert_eq!(arranged.len(), pxls.len());
+		for turn in arranged.chunks(4) {
+			let mut quadrants = turn.iter().map(quadrant).collect::<Vec<_>>();
+			quadrants.sort();
+			assert_eq!(quadrants, [(0, 0), (0, 1), (1, 0), (1, 1)]);
+		}
+		// still from the border inwards within each region
+		assert!(arranged[..4].iter().all(|px| matches!(coords_of(px), (0 | 19, _) | (_, 0 | 19))));
+	}
+
+	#[test]
+	fn sample_rate()
+	{
+		use rand::SeedableRng;
+
+		let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+		let opaque = (0..1000).map(|i| format!("PX {} 0 FFFFFF\n", i)).collect::<String>();
+		let half = (0..1000).map(|i| format!("PX {} 1 FFFFFF80\n", i)).collect::<String>();
+		assert_eq!(sample(&opaque, 1.0, &mut rng), opaque);
+		assert!((50..150).contains(&sample(&opaque, 0.1, &mut rng).lines().count()));
+		assert!((400..600).contains(&sample(&half, 1.0, &mut rng).lines().count()));
+	}
+
+	#[test]
+	fn share()
+	{
+		assert_eq!(parse_share("80%"), Ok(0.8));
+		assert_eq!(parse_share("0.25"), Ok(0.25));
+		assert!(parse_share("120%").is_err());
+		assert!(parse_share("x%").is_err());
+		assert_eq!(opt(&["--verify", "0.1", "--target-ownership", "50%"]).target_ownership, Some(0.5));
+	}
+
+	#[test]
+	fn rate()
+	{
+		assert_eq!(parse_rate("2.5"), Ok(2.5));
+		assert!(parse_rate("0").is_err());
+		assert!(parse_rate("-1").is_err());
+		assert!(parse_rate("inf").is_err());
+		assert!(parse_rate("NaN").is_err());
+		assert!(Opt::try_parse_from(["pixelspray", "--max-fps", "0"]).is_err());
+		assert_eq!(opt(&["--max-fps", "30"]).max_fps, Some(30.0));
+	}
+
+	#[test]
+	fn period()
+	{
+		assert_eq!(parse_period("250ms").map(Into::into), Ok(time::Duration::from_millis(250)));
+		assert!(parse_period("0s").is_err());
+		assert!(parse_period("soon").is_err());
+		assert!(Opt::try_parse_from(["pixelspray", "--effect-interval", "0s"]).is_err());
+		assert!(Opt::try_parse_from(["pixelspray", "--rotate-connections", "0s"]).is_err());
+		assert!(Opt::try_parse_from(["pixelspray", "--ping", "0s"]).is_err());
+		assert!(Opt::try_parse_from(["pixelspray", "--refresh-interval", "0s"]).is_err());
+	}
+
+	#[test]
+	fn claim()
+	{
+		let claim = parse_claim("100x50+320x240:team-red").unwrap();
+		assert_eq!(claim.to_string(), "REGION 100 50 320 240 team-red\n");
+		assert!(parse_claim("100x50+320x240").is_err());
+		assert!(parse_claim("100x50:token").is_err());
+		assert!(parse_claim("100x50+320x240:two words").is_err());
+	}
+
+	#[test]
+	fn color_order()
+	{
+		let opt = opt(&["--order", "color"]);
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| match (x + y) % 3 {
+			0 => Rgba([0xff, 0, 0, 0xff]),
+			1 => Rgba([0, 0xff, 0, 0xff]),
+			_ => Rgba([0, 0, 0xff, 0xff]),
+		}));
+		let chunks = encode(&opt, &image, None, CANVAS, (0, 0), false);
+		let colors = chunks.iter()
+			.flat_map(|chunk| chunk.lines())
+			.map(|line| line.rsplit(' ').next().unwrap())
+			.collect::<Vec<_>>();
+		assert_eq!(colors.len(), 256);
+		assert_eq!(colors.windows(2).filter(|w| w[0] != w[1]).count(), 2);
+	}
+
+	#[test]
+	fn keepout()
+	{
+		let path = std::env::temp_dir().join(format!("pixelspray-keepout-{}.png", std::process::id()));
+		// the left half of the canvas, which the image at 6x0 overlaps by two columns
+		RgbaImage::from_fn(CANVAS.0, CANVAS.1, |x, _| Rgba(if x < 8 { [0xff; 4] } else { [0, 0, 0, 0xff] })).save(&path).unwrap();
+		let opt = opt(&["--keepout", path.to_str().unwrap()]);
+		std::fs::remove_file(&path).unwrap();
+
+		for no_offset in [false, true] {
+			let xs = encode(&opt, &image(), None, CANVAS, (6, 0), no_offset).iter()
+				.flat_map(|chunk| chunk.lines().map(|px| coords_of(px).0).collect::<Vec<_>>())
+				.map(|x| if no_offset { x } else { x + 6 })
+				.collect::<Vec<_>>();
+			assert_eq!(xs.len(), 8);
+			assert!(xs.iter().all(|&x| x >= 8));
+		}
+	}
+
+	#[test]
+	fn origin()
+	{
+		let opt = opt(&["--origin", "bottom-left", "--index-base", "1"]);
+		let lines = encode(&opt, &image(), None, CANVAS, (2, 2), true).iter()
+			.flat_map(|chunk| chunk.lines().map(str::to_owned).collect::<Vec<_>>())
+			.collect::<Vec<_>>();
+		// (1, 0) of the image is at (3, 2) on the canvas, counted from 1 at the bottom
+		assert!(lines.contains(&"PX 4 14 400080".to_owned()), "{:?}", lines);
+		assert!(lines.iter().map(|px| coords_of(px)).all(|(x, y)| (3..=6).contains(&x) && (11..=14).contains(&y)));
+
+		assert_eq!(opt.canvas_coords((4, 14), CANVAS), Some((3, 2)));
+		assert_eq!(opt.canvas_coords((0, 14), CANVAS), None);
+		assert_eq!(opt.canvas_coords((1, 17), CANVAS), None);
+		for at in [(0, 0), (15, 15), (3, 7)] {
+			assert_eq!(opt.canvas_coords(opt.server_coords(at, CANVAS), CANVAS), Some(at));
+		}
+	}
+
+	#[test]
+	fn soft_start()
+	{
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([1, 2, 3, 0xff])));
+		let opt = opt(&["--soft-start", "4"]);
+		let coords = encode(&opt, &image, None, CANVAS, (2, 2), true).iter()
+			.flat_map(|chunk| chunk.lines().map(coords_of).collect::<Vec<_>>())
+			.collect::<Vec<_>>();
+		assert_eq!(coords.len(), 64);
+		// the corners of the 4x4 blocks, then of the 2x2 ones, then the rest
+		let mut grid = coords[..4].to_vec();
+		grid.sort();
+		assert_eq!(grid, [(2, 2), (2, 6), (6, 2), (6, 6)]);
+		assert!(coords[4..16].iter().all(|&(x, y)| x % 2 == 0 && y % 2 == 0));
+		assert!(coords[16..].iter().all(|&(x, y)| x % 2 == 1 || y % 2 == 1));
+	}
+
+	#[test]
+	fn delta_threshold()
+	{
+		let base = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 1, Rgba([0x80, 0x80, 0x80, 0xff])));
+		let colors = [[0x83, 0x7d, 0x80, 0xff], [0x88, 0x80, 0x80, 0xff], [0x80, 0x80, 0x80, 0xfa], [0x80, 0x80, 0x84, 0xff]];
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 1, |x, _| Rgba(colors[x as usize])));
+		let settled = settle(&base, &image, 8);
+		// only the pixel changed by 8 or more is sent
+		let coords = encode(&opt(&["--delta", "--delta-threshold", "8"]), &settled, Some(&base), CANVAS, (0, 0), false).iter()
+			.flat_map(|chunk| chunk.lines().map(coords_of).collect::<Vec<_>>())
+			.collect::<Vec<_>>();
+		assert_eq!(coords, [(1, 0)]);
+		// the lag does not build up, a slow drift is sent once it reaches the threshold
+		let drift = |r| DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 1, Rgba([r, 0x80, 0x80, 0xff])));
+		let settled = settle(&settled, &drift(0x84), 8);
+		assert_eq!(settled.get_pixel(0, 0).0, [0x80, 0x80, 0x80, 0xff]);
+		let settled = settle(&settled, &drift(0x88), 8);
+		assert_eq!(settled.get_pixel(0, 0).0, [0x88, 0x80, 0x80, 0xff]);
+		assert!(Opt::try_parse_from(["pixelspray", "host", "image.png", "--delta-threshold", "8"]).is_err());
+	}
+
+	#[test]
+	fn ring_orders()
+	{
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 6, Rgba([0xff, 0, 0, 0xff])));
+		let first = |order| {
+			let chunks = encode(&opt(&["--order", order]), &image, None, CANVAS, (0, 0), false);
+			chunks.iter()
+				.flat_map(|chunk| chunk.lines())
+				.map(|line| {
+					let mut args = line.split(' ').skip(1).map(|v| u32::from_str(v).unwrap());
+					(args.next().unwrap(), args.next().unwrap())
+				})
+				.collect::<Vec<_>>()
+		};
+
+		let inward = first("inward");
+		assert_eq!(inward.len(), 48);
+		// the 24 pixels of the border come first
+		assert!(inward[..24].iter().all(|&(x, y)| x == 0 || y == 0 || x == 7 || y == 5));
+		assert!(inward[24..].iter().all(|&(x, y)| x != 0 && y != 0 && x != 7 && y != 5));
+
+		let outward = first("outward");
+		assert!(outward[..2].iter().all(|&(x, y)| (3..=4).contains(&x) && (2..=3).contains(&y)));
+		assert!(outward[44..].iter().all(|&(x, _)| x == 0 || x == 7));
+	}
+
+	#[test]
+	fn patch_dirty()
+	{
+		let opt = opt(&["--no-offset"]);
+		let before = image();
+		let mut after = before.to_rgba8();
+		after.put_pixel(3, 3, Rgba([1, 2, 3, 0xff]));
+		let after = DynamicImage::ImageRgba8(after);
+
+		let chunks = encode(&opt, &before, None, CANVAS, (2, 2), true);
+		let dirty = dirty::Dirty::diff(&before, &after, 2);
+		assert_eq!(dirty.rects(), [(2, 2, 2, 2)]);
+		let patched = patch(&opt, &after, &chunks, &dirty, CANVAS, (2, 2), true);
+
+		let replay = |chunks: &[Chunk]| {
+			let state = server::State::new(CANVAS);
+			let mut lines = chunks.iter().flat_map(|chunk| chunk.lines().map(str::to_owned)).collect::<Vec<_>>();
+			lines.sort();
+			for line in lines.iter() {
+				state.command(line, &mut (0, 0));
+			}
+			(lines, state.canvas.into_inner().unwrap())
+		};
+		assert_eq!(replay(&patched), replay(&encode(&opt, &after, None, CANVAS, (2, 2), true)));
+	}
+
+	fn any_image() -> impl proptest::strategy::Strategy<Value = DynamicImage>
+	{
+		use proptest::prelude::*;
+		(1u32..8, 1u32..8)
+			.prop_flat_map(|(w, h)| proptest::collection::vec(any::<u8>(), (w * h * 4) as usize)
+				.prop_map(move |data| DynamicImage::ImageRgba8(RgbaImage::from_raw(w, h, data).unwrap())))
+	}
+
+	proptest::proptest! {
+		#![proptest_config(proptest::prelude::ProptestConfig::with_cases(128))]
+
+		/// Whatever the image and options, only valid commands for pixels on the canvas come out
+		#[test]
+		fn encodes_valid_commands(
+			image in any_image(),
+			canvas in (1u32..16, 1u32..16),
+			at in (0u32..20, 0u32..20),
+			no_offset in proptest::prelude::any::<bool>(),
+			filter in proptest::sample::select(&["rgba", "grey", "mask"][..]),
+			overflow in proptest::sample::select(&["clip", "wrap"][..]),
+			flags in proptest::sample::subsequence(&["-l", "-c", "--grey-wire=on"][..], 0..=3),
+		)
+		{
+			let mut args = vec!["-f", filter, "--overflow", overflow];
+			args.extend(flags);
+			let opt = opt(&args);
+			let no_offset = no_offset || overflow == "wrap";
+			for chunk in encode(&opt, &image, None, canvas, at, no_offset) {
+				proptest::prop_assert!(chunk.len() <= CHUNK_LEN);
+				for line in chunk.lines() {
+					let args = line.split(' ').collect::<Vec<_>>();
+					proptest::prop_assert!(args.len() == 4 && args[0] == "PX", "{:?}", line);
+					let (x, y) = (u32::from_str(args[1]).unwrap(), u32::from_str(args[2]).unwrap());
+					let (x, y) = if no_offset { (x, y) } else { (x + at.0, y + at.1) };
+					proptest::prop_assert!(x < canvas.0 && y < canvas.1, "{:?} off {:?}", line, canvas);
+					proptest::prop_assert!(server::parse_color(args[3]).is_some(), "{:?}", line);
+				}
+			}
+		}
+	}
+}
\ No newline at end of file
@@ -0,0 +1,137 @@
+use std::{
+	collections::VecDeque,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+};
+
+use chrono::Local;
+use clap::ValueEnum;
+use image::{imageops, DynamicImage, RgbaImage};
+
+use crate::{clock, transform::Transform};
+
+
+/// Lines of the debug HUD
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum Hud
+{
+	/// Frames per second that changed
+	Fps,
+	/// The local time of the render, to hold a clock against when it shows up on the canvas
+	Timestamp,
+	/// The number of the frame and the bytes sent per second
+	Stats,
+}
+
+/// Canvas pixels per font pixel
+const SCALE: u32 = 2;
+/// Seconds over which the rates are measured
+const WINDOW: f32 = 1.0;
+
+/// A HUD drawn into the top left corner of every frame
+pub struct Overlay
+{
+	lines: Vec<Hud>,
+	/// Bytes handed to the connections so far
+	sent: Arc<AtomicU64>,
+	last: Option<DynamicImage>,
+	/// When the frames within the window changed
+	changes: VecDeque<f32>,
+	frames: u64,
+	/// Bytes sent at the start of the window, and when it started
+	mark: (f32, u64),
+	rate: f64,
+}
+
+impl Overlay
+{
+	pub fn new(lines: Vec<Hud>, sent: Arc<AtomicU64>) -> Self
+	{
+		Self { lines, sent, last: None, changes: VecDeque::new(), frames: 0, mark: (0.0, 0), rate: 0.0 }
+	}
+
+	/// The lines about the image `t` seconds in
+	fn text(&mut self, image: &DynamicImage, t: f32) -> Vec<String>
+	{
+		if self.last.as_ref() != Some(image) {
+			self.frames += 1;
+			self.changes.push_back(t);
+			self.last = Some(image.clone());
+		}
+		while self.changes.front().is_some_and(|&at| at <= t - WINDOW) {
+			self.changes.pop_front();
+		}
+		let sent = self.sent.load(Ordering::Relaxed);
+		if t - self.mark.0 >= WINDOW {
+			self.rate = (sent - self.mark.1) as f64 / (t - self.mark.0) as f64;
+			self.mark = (t, sent);
+		}
+		self.lines.iter()
+			.map(|line| match line {
+				Hud::Fps => format!("{:.1} FPS", self.changes.len() as f32 / WINDOW),
+				Hud::Timestamp => Local::now().format("%H:%M:%S%.3f").to_string(),
+				Hud::Stats => format!("#{} {:.2}MB/S", self.frames, self.rate / 1e6),
+			})
+			.collect()
+	}
+}
+
+impl Transform for Overlay
+{
+	fn apply(&mut self, image: DynamicImage, t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let lines = self.text(&image, t)
+			.iter()
+			.map(|line| clock::render(line, SCALE))
+			.collect::<Vec<_>>();
+		// a border of a font pixel around the lines keeps them readable on any image
+		let w = lines.iter().map(RgbaImage::width).max().unwrap_or(0) + 2 * SCALE;
+		let h = lines.iter().map(|line| line.height() + SCALE).sum::<u32>() + SCALE;
+		let mut hud = RgbaImage::from_pixel(w, h, clock::BACKGROUND);
+		for (i, line) in lines.iter().enumerate() {
+			imageops::replace(&mut hud, line, SCALE as i64, (SCALE + i as u32 * (line.height() + SCALE)) as i64);
+		}
+		let mut image = image.into_rgba8();
+		imageops::replace(&mut image, &hud, 0, 0);
+		Ok(DynamicImage::ImageRgba8(image))
+	}
+
+	fn animated(&self, _t: f32) -> bool
+	{
+		true
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use image::Rgba;
+
+	#[test]
+	fn hud()
+	{
+		let sent = Arc::new(AtomicU64::new(0));
+		let mut overlay = Overlay::new(vec![ Hud::Fps, Hud::Stats ], sent.clone());
+		let red = DynamicImage::ImageRgba8(RgbaImage::from_pixel(200, 100, Rgba([0xff, 0, 0, 0xff])));
+		let blue = DynamicImage::ImageRgba8(RgbaImage::from_pixel(200, 100, Rgba([0, 0, 0xff, 0xff])));
+		assert_eq!(overlay.text(&red, 0.0), ["1.0 FPS", "#1 0.00MB/S"]);
+		assert_eq!(overlay.text(&red, 0.5), ["1.0 FPS", "#1 0.00MB/S"]);
+		sent.store(3_000_000, Ordering::Relaxed);
+		assert_eq!(overlay.text(&blue, 1.5), ["1.0 FPS", "#2 2.00MB/S"]);
+
+		let shown = overlay.apply(red.clone(), 2.0).unwrap().into_rgba8();
+		assert_eq!(*shown.get_pixel(0, 0), clock::BACKGROUND);
+		assert!(shown.pixels().any(|&px| px == clock::FOREGROUND));
+		// two lines of 7 font pixels, with a font pixel around and between them
+		assert_eq!(*shown.get_pixel(0, 17 * SCALE - 1), clock::BACKGROUND);
+		assert_eq!(*shown.get_pixel(0, 17 * SCALE), Rgba([0xff, 0, 0, 0xff]));
+		assert_eq!(*shown.get_pixel(199, 99), Rgba([0xff, 0, 0, 0xff]));
+
+		let stamp = Overlay::new(vec![ Hud::Timestamp ], sent).text(&red, 0.0);
+		assert_eq!(stamp[0].len(), "00:00:00.000".len());
+	}
+}
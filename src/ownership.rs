@@ -0,0 +1,80 @@
+/// Fewest pixels sent per cycle, so losses are still noticed
+const MIN_RATE: f64 = 0.02;
+/// Cut of the send rate per report while the ownership stays above the target
+const DECREASE: f64 = 0.8;
+/// Rise of the send rate per report while the ownership is below the target
+const INCREASE: f64 = 2.0;
+/// How far above the target the ownership has to be to save bandwidth,
+/// so the rate does not swing around it
+const MARGIN: f64 = 0.05;
+
+/// Sends just enough to keep a share of the verified pixels ours:
+/// backing off slowly while uncontested, ramping up fast when painted over
+#[derive(Debug)]
+pub struct Target
+{
+	/// Share of the sampled pixels to keep
+	target: f64,
+	/// Fraction of the pixels sent per cycle
+	rate: f64,
+}
+
+impl Target
+{
+	pub fn new(target: f64) -> Self
+	{
+		Self { target, rate: 1.0 }
+	}
+
+	/// Takes a report of `ours` out of `total` sampled pixels, returning the new send rate if it changed
+	pub fn report(&mut self, ours: u64, total: u64) -> Option<f64>
+	{
+		if total == 0 {
+			return None;
+		}
+		let share = ours as f64 / total as f64;
+		let rate = if share < self.target {
+			(self.rate * INCREASE).min(1.0)
+		} else if share > self.target + MARGIN {
+			(self.rate * DECREASE).max(MIN_RATE)
+		} else {
+			self.rate
+		};
+		if rate == self.rate {
+			return None;
+		}
+		self.rate = rate;
+		Some(rate)
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn control_loop()
+	{
+		let mut target = Target::new(0.8);
+		// uncontested, so the rate goes down to the floor
+		assert_eq!(target.report(100, 100), Some(0.8));
+		assert_eq!(target.report(95, 100), Some(0.8 * 0.8));
+		// close to the target it is kept
+		assert_eq!(target.report(82, 100), None);
+		assert_eq!(target.report(0, 0), None);
+		for _ in 0..32 {
+			target.report(100, 100);
+		}
+		assert_eq!(target.rate, MIN_RATE);
+
+		// attacked, so it ramps up to full speed
+		assert_eq!(target.report(50, 100), Some(2.0 * MIN_RATE));
+		for _ in 0..8 {
+			target.report(50, 100);
+		}
+		assert_eq!(target.rate, 1.0);
+		assert_eq!(target.report(79, 100), None);
+	}
+}
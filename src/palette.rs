@@ -0,0 +1,192 @@
+use std::path::Path;
+
+use anyhow::Context;
+use image::DynamicImage;
+
+use crate::transform::Transform;
+
+
+/// Colors a canvas is able to show
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette
+{
+	colors: Vec<[u8; 3]>,
+}
+
+impl Palette
+{
+	/// Reads a palette of one `RRGGBB` or `#RRGGBB` color per line, other `#` lines are comments
+	pub fn load(path: &Path) -> anyhow::Result<Self>
+	{
+		let text = std::fs::read_to_string(path)
+			.with_context(|| format!("failed to read {}", path.display()))?;
+		Self::parse(&text)
+			.with_context(|| format!("invalid palette in {}", path.display()))
+	}
+
+	fn parse(text: &str) -> anyhow::Result<Self>
+	{
+		let mut colors = Vec::new();
+		for (n, line) in text.lines().enumerate().map(|(n, line)| (n + 1, line.trim())) {
+			let hex = line.strip_prefix('#').unwrap_or(line);
+			if line.is_empty() || (line.starts_with('#') && !is_color(hex)) {
+				continue;
+			}
+			anyhow::ensure!(is_color(hex), "line {}: expected RRGGBB, got '{}'", n, line);
+			let v = u32::from_str_radix(hex, 16)?;
+			colors.push([(v >> 16) as u8, (v >> 8) as u8, v as u8]);
+		}
+		anyhow::ensure!(!colors.is_empty(), "no colors");
+		Ok(Self { colors })
+	}
+
+	pub fn nearest(&self, [r, g, b]: [f32; 3]) -> [u8; 3]
+	{
+		let dist = |c: &[u8; 3]| {
+			let (dr, dg, db) = (c[0] as f32 - r, c[1] as f32 - g, c[2] as f32 - b);
+			dr * dr + dg * dg + db * db
+		};
+		*self.colors.iter()
+			.min_by(|a, b| dist(a).total_cmp(&dist(b)))
+			.unwrap()
+	}
+}
+
+fn is_color(s: &str) -> bool
+{
+	s.len() == 6 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Maps images onto a palette, carrying the quantization error of every pixel over to the next frame
+///
+/// Over a few frames the colors average out to the original.
+#[derive(Debug)]
+pub struct Dither
+{
+	palette: Palette,
+	error: Vec<[f32; 3]>,
+}
+
+impl Dither
+{
+	pub fn new(palette: Palette) -> Self
+	{
+		Self { palette, error: Vec::new() }
+	}
+
+	pub fn apply(&mut self, image: &DynamicImage) -> DynamicImage
+	{
+		let mut image = image.to_rgba8();
+		let len = (image.width() * image.height()) as usize;
+		if self.error.len() != len {
+			self.error = vec![[0.0; 3]; len];
+		}
+		for (px, error) in image.pixels_mut().zip(self.error.iter_mut()) {
+			if px.0[3] == 0 {
+				continue;
+			}
+			let want = [0, 1, 2].map(|i| px.0[i] as f32 + error[i]);
+			let got = self.palette.nearest(want);
+			for i in 0..3 {
+				error[i] = (want[i] - got[i] as f32).clamp(-255.0, 255.0);
+			}
+			px.0[..3].copy_from_slice(&got);
+		}
+		DynamicImage::ImageRgba8(image)
+	}
+}
+
+impl Transform for Dither
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		Ok(Dither::apply(self, &image))
+	}
+
+	/// New frames are needed to even out the colors
+	fn animated(&self, _t: f32) -> bool
+	{
+		true
+	}
+}
+
+/// Black and white with the error diffused to the neighbors (Floyd-Steinberg),
+/// for displays like e-ink that would flicker from dithering over time
+#[derive(Debug)]
+pub struct Monochrome;
+
+impl Transform for Monochrome
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let mut image = image.to_rgba8();
+		let (w, h) = (image.width() as usize, image.height() as usize);
+		let mut levels = image.pixels()
+			.map(|px| 0.299 * px.0[0] as f32 + 0.587 * px.0[1] as f32 + 0.114 * px.0[2] as f32)
+			.collect::<Vec<_>>();
+		for (i, px) in image.pixels_mut().enumerate() {
+			if px.0[3] == 0 {
+				continue;
+			}
+			let level = if levels[i] < 128.0 { 0 } else { 0xff };
+			let error = levels[i] - level as f32;
+			let (x, y) = (i % w, i / w);
+			for (dx, dy, share) in [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)] {
+				let (x, y) = (x as isize + dx, y + dy);
+				if (0..w as isize).contains(&x) && y < h {
+					levels[y * w + x as usize] += error * share / 16.0;
+				}
+			}
+			px.0[..3].fill(level);
+		}
+		Ok(DynamicImage::ImageRgba8(image))
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use image::{GenericImageView, Rgba, RgbaImage};
+
+	#[test]
+	fn parse_palette()
+	{
+		let palette = Palette::parse("# LED wall\n#000000\nFFFFFF\n\n").unwrap();
+		assert_eq!(palette.colors, [[0, 0, 0], [0xff, 0xff, 0xff]]);
+		assert!(Palette::parse("# nothing").is_err());
+		assert!(Palette::parse("red").is_err());
+	}
+
+	#[test]
+	fn temporal_dither()
+	{
+		let palette = Palette::parse("000000\nFFFFFF").unwrap();
+		let mut dither = Dither::new(palette);
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([0x40, 0x40, 0x40, 0xff])));
+
+		// a quarter grey is white every fourth frame
+		let white = (0..8)
+			.filter(|_| dither.apply(&image).get_pixel(0, 0) == Rgba([0xff, 0xff, 0xff, 0xff]))
+			.count();
+		assert_eq!(white, 2);
+	}
+
+	#[test]
+	fn monochrome()
+	{
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, Rgba([0x40, 0x40, 0x40, 0xff])));
+		let out = Monochrome.apply(image, 0.0).unwrap().into_rgba8();
+		assert!(out.pixels().all(|px| px.0 == [0, 0, 0, 0xff] || px.0 == [0xff; 4]));
+		// a quarter grey is about every fourth pixel white, spread over the area
+		let white = out.pixels().filter(|px| px.0[0] == 0xff).count();
+		assert!((56..=72).contains(&white), "{}", white);
+		for (qx, qy) in [(0, 0), (8, 0), (0, 8), (8, 8)] {
+			let white = (qy..qy + 8).flat_map(|y| (qx..qx + 8).map(move |x| (x, y)))
+				.filter(|&(x, y)| out.get_pixel(x, y).0[0] == 0xff)
+				.count();
+			assert!((12..=20).contains(&white), "{}", white);
+		}
+	}
+}
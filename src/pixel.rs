@@ -0,0 +1,220 @@
+use std::fmt;
+
+use image::Rgba;
+
+use crate::{transform, Filter, GreyMetric};
+
+
+/// Color of a PX command in the shortest form that keeps it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value
+{
+	/// Opaque grey in the 2-digit form, which not every server takes
+	Grey(u8),
+	Rgb([u8; 3]),
+	Rgba([u8; 4]),
+}
+
+impl From<Rgba<u8>> for Value
+{
+	fn from(color: Rgba<u8>) -> Self
+	{
+		match color.0 {
+			[r, g, b, 0xff] if r == g && g == b => Value::Grey(r),
+			[r, g, b, 0xff] => Value::Rgb([r, g, b]),
+			rgba => Value::Rgba(rgba),
+		}
+	}
+}
+
+impl fmt::Display for Value
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		match *self {
+			Value::Grey(v) => write!(f, "{:02X}", v),
+			Value::Rgb([r, g, b]) => write!(f, "{:02X}{:02X}{:02X}", r, g, b),
+			Value::Rgba([r, g, b, a]) => write!(f, "{:02X}{:02X}{:02X}{:02X}", r, g, b, a),
+		}
+	}
+}
+
+/// Lowest alpha sent as opaque by `strip_alpha`, as blending it is at most one level off
+pub const OPAQUE: u8 = 0xfe;
+
+/// A PX command for a single pixel, shown without the newline
+///
+/// Coordinates are decimal without leading zeros, the fewest bytes the protocol allows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Command
+{
+	pub x: u32,
+	pub y: u32,
+	pub value: Value,
+}
+
+impl fmt::Display for Command
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		write!(f, "PX {} {} {}", self.x, self.y, self.value)
+	}
+}
+
+/// Options deciding how pixels are written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Opts
+{
+	/// Color of the mask filter
+	pub mask: Rgba<u8>,
+	/// Send near grey pixels as grey
+	pub same_ch_opt: bool,
+	/// How far a pixel may be from grey to be sent as grey
+	pub grey_metric: GreyMetric,
+	/// Largest distance by the metric that still counts as grey
+	pub grey_tolerance: f32,
+	/// Lowest alpha of the pixels sent, 0 to send even fully transparent ones
+	pub min_alpha: u8,
+	/// Whether the server takes the 2-digit grey form
+	pub grey_wire: bool,
+	/// Send near opaque pixels without alpha
+	pub strip_alpha: bool,
+}
+
+impl Opts
+{
+	/// Whether a pixel of alpha `a` is sent at all
+	pub fn visible(&self, a: u8) -> bool
+	{
+		a >= self.min_alpha
+	}
+}
+
+/// The grey of the same lightness and the CIE76 color difference to it,
+/// which is the chroma in CIELAB as both share their L*
+fn nearest_grey(rgb: [u8; 3]) -> (u8, f32)
+{
+	let levels = transform::linear_levels();
+	let [r, g, b] = rgb.map(|c| levels[c as usize]);
+	// linear sRGB to XYZ, relative to the D65 white point
+	let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+	let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+	let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+	let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+	let (fx, fy, fz) = (f(x), f(y), f(z));
+	let (a, b) = (500.0 * (fx - fy), 200.0 * (fy - fz));
+	(transform::srgb_level(y), a.hypot(b))
+}
+
+/// The PX command for the pixel at `x`,`y` of color `rgba` under `filter`
+pub fn encode_pixel(filter: Filter, opts: &Opts, x: u32, y: u32, [mut r, g, b, mut a]: [u8; 4]) -> Command
+{
+	let mut filter = filter;
+	if opts.strip_alpha && a >= OPAQUE {
+		a = 0xff;
+	}
+	if opts.same_ch_opt && filter != Filter::Mask {
+		let grey = match opts.grey_metric {
+			GreyMetric::Channel => {
+				let rg = r.abs_diff(g);
+				let gb = g.abs_diff(b);
+				let br = b.abs_diff(r);
+				let dist = *[rg, gb, br].iter().max().unwrap() as f32;
+				(dist <= opts.grey_tolerance).then(|| ((r as usize + g as usize + b as usize) / 3) as u8)
+			},
+			GreyMetric::DeltaE => {
+				let (grey, dist) = nearest_grey([r, g, b]);
+				(dist <= opts.grey_tolerance).then_some(grey)
+			},
+		};
+		if let Some(grey) = grey {
+			r = grey;
+			filter = Filter::Grey;
+		}
+	}
+
+	// the grey form has no alpha, so only opaque pixels qualify
+	if opts.grey_wire && filter == Filter::Rgba && a == 0xff && r == g && g == b {
+		filter = Filter::Grey;
+	}
+
+	let value = match filter {
+		Filter::Mask => match Value::from(opts.mask) {
+			// just like grey pixels, unless the server reads the short form
+			Value::Grey(v) if !opts.grey_wire => Value::Rgb([v; 3]),
+			value => value,
+		},
+		Filter::Grey if opts.grey_wire => Value::Grey(r),
+		Filter::Grey => Value::Rgb([r, r, r]),
+		Filter::Rgba if a == 0xff => Value::Rgb([r, g, b]),
+		Filter::Rgba => Value::Rgba([r, g, b, a]),
+	};
+	Command { x, y, value }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn pixels()
+	{
+		let opts = Opts { mask: Rgba([0xff, 0x80, 0, 0xff]), same_ch_opt: false, grey_metric: GreyMetric::Channel, grey_tolerance: 4.0, min_alpha: 0x10, grey_wire: false, strip_alpha: false };
+		let px = |filter, opts: &Opts, rgba| encode_pixel(filter, opts, 3, 4, rgba).to_string();
+		assert_eq!(px(Filter::Rgba, &opts, [1, 2, 3, 0xff]), "PX 3 4 010203");
+		assert_eq!(px(Filter::Rgba, &opts, [1, 2, 3, 0x80]), "PX 3 4 01020380");
+		assert_eq!(px(Filter::Grey, &opts, [0x40, 2, 3, 0x80]), "PX 3 4 404040");
+		assert_eq!(px(Filter::Mask, &opts, [1, 2, 3, 0xff]), "PX 3 4 FF8000");
+		assert!(!opts.visible(0xf) && opts.visible(0x10));
+
+		let opts = Opts { same_ch_opt: true, grey_wire: true, ..opts };
+		assert_eq!(px(Filter::Rgba, &opts, [0x40, 0x44, 0x42, 0xff]), "PX 3 4 42");
+		assert_eq!(px(Filter::Rgba, &opts, [0x40, 0x45, 0x42, 0xff]), "PX 3 4 404542");
+		let wide = Opts { grey_tolerance: 8.0, ..opts };
+		assert_eq!(px(Filter::Rgba, &wide, [0x40, 0x48, 0x41, 0xff]), "PX 3 4 43");
+		let opts = Opts { grey_tolerance: 0.0, min_alpha: 0, ..opts };
+		assert_eq!(px(Filter::Rgba, &opts, [0x42, 0x42, 0x42, 0xff]), "PX 3 4 42");
+		assert_eq!(px(Filter::Rgba, &opts, [0x40, 0x44, 0x42, 0xff]), "PX 3 4 404442");
+		assert!(opts.visible(0));
+		assert_eq!(px(Filter::Rgba, &opts, [1, 2, 3, 0]), "PX 3 4 01020300");
+
+		// perceptually, a small shift toward red is less visible than one toward green
+		let opts = Opts { grey_metric: GreyMetric::DeltaE, grey_tolerance: 2.3, ..opts };
+		assert_eq!(px(Filter::Rgba, &opts, [0x84, 0x80, 0x80, 0xff]), "PX 3 4 81");
+		assert_eq!(px(Filter::Rgba, &opts, [0x80, 0x84, 0x80, 0xff]), "PX 3 4 808480");
+		assert_eq!(px(Filter::Rgba, &opts, [0xff, 0xff, 0xff, 0xff]), "PX 3 4 FF");
+
+		assert_eq!(Value::from(Rgba([0xff; 4])).to_string(), "FF");
+		assert_eq!(Value::from(Rgba([0xff, 0xa5, 0, 0xff])).to_string(), "FFA500");
+		assert_eq!(Value::from(Rgba([0; 4])).to_string(), "00000000");
+	}
+
+	#[test]
+	fn wire()
+	{
+		let opts = Opts { mask: Rgba([0xff; 4]), same_ch_opt: false, grey_metric: GreyMetric::Channel, grey_tolerance: 0.0, min_alpha: 1, grey_wire: false, strip_alpha: false };
+		let px = |opts: &Opts, x, y, rgba| encode_pixel(Filter::Rgba, opts, x, y, rgba).to_string();
+		// grey only takes 2 digits where the server does
+		assert_eq!(px(&opts, 0, 0, [0x40, 0x40, 0x40, 0xff]), "PX 0 0 404040");
+		let grey = Opts { grey_wire: true, ..opts };
+		assert_eq!(px(&grey, 0, 0, [0x40, 0x40, 0x40, 0xff]), "PX 0 0 40");
+		assert_eq!(px(&grey, 0, 0, [0x40, 0x40, 0x40, 0x80]), "PX 0 0 40404080");
+		// opaque pixels never carry alpha, near opaque ones only when stripped
+		assert_eq!(px(&opts, 0, 0, [1, 2, 3, 0xff]), "PX 0 0 010203");
+		assert_eq!(px(&opts, 0, 0, [1, 2, 3, 0xfe]), "PX 0 0 010203FE");
+		assert_eq!(encode_pixel(Filter::Mask, &opts, 0, 0, [1, 2, 3, 0xff]).to_string(), "PX 0 0 FFFFFF");
+		assert_eq!(encode_pixel(Filter::Mask, &grey, 0, 0, [1, 2, 3, 0xff]).to_string(), "PX 0 0 FF");
+		let strip = Opts { strip_alpha: true, ..grey };
+		assert_eq!(px(&strip, 0, 0, [1, 2, 3, 0xfe]), "PX 0 0 010203");
+		assert_eq!(px(&strip, 0, 0, [0x40, 0x40, 0x40, 0xfe]), "PX 0 0 40");
+		// translucent pixels keep theirs
+		assert_eq!(px(&strip, 0, 0, [1, 2, 3, 0xfd]), "PX 0 0 010203FD");
+		assert_eq!(px(&strip, 0, 0, [1, 2, 3, 0x10]), "PX 0 0 01020310");
+		// coordinates without padding
+		assert_eq!(px(&opts, 7, 10, [1, 2, 3, 0xff]), "PX 7 10 010203");
+		assert_eq!(px(&opts, 1920, 1079, [1, 2, 3, 0xff]), "PX 1920 1079 010203");
+		assert_eq!(px(&opts, u32::MAX, 0, [1, 2, 3, 0xff]), "PX 4294967295 0 010203");
+	}
+}
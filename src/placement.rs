@@ -0,0 +1,177 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+
+/// A length in pixels or in percent of the canvas, like `120` or `50%`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length
+{
+	Px(u32),
+	Percent(f32),
+}
+
+impl Length
+{
+	/// Pixels on a canvas of `space` along the axis
+	pub fn resolve(self, space: u32) -> u32
+	{
+		match self {
+			Length::Px(px) => px,
+			Length::Percent(p) => (space as f32 * p / 100.0).round() as u32,
+		}
+	}
+}
+
+impl FromStr for Length
+{
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err>
+	{
+		match s.strip_suffix('%') {
+			Some(p) => match f32::from_str(p) {
+				Ok(p) if p.is_finite() && p >= 0.0 => Ok(Length::Percent(p)),
+				Ok(_) => Err(format!("invalid percentage '{}'", s)),
+				Err(err) => Err(format!("invalid percentage '{}': {}", s, err)),
+			},
+			None => u32::from_str(s).map(Length::Px).map_err(|err| format!("invalid length '{}': {}", s, err)),
+		}
+	}
+}
+
+/// Parses `WxH`, where both may be percentages of the canvas and one may be `_` to keep the aspect ratio
+pub fn parse_size(s: &str) -> Result<(Option<Length>, Option<Length>), String>
+{
+	let (w, h) = s.split_once('x')
+		.ok_or_else(|| format!("expected WxH, got '{}'", s))?;
+	let side = |s: &str| if s == "_" { Ok(None) } else { s.parse().map(Some) };
+	match (side(w)?, side(h)?) {
+		(None, None) => Err(format!("expected at least one of width and height, got '{}'", s)),
+		size => Ok(size),
+	}
+}
+
+/// Position of the image along one axis of the canvas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pos
+{
+	At(Length),
+	Start,
+	Middle,
+	End,
+}
+
+impl Pos
+{
+	/// Where an image of `len` starts on a canvas of `space`, keeping `margin` from the edge it is aligned to
+	fn resolve(self, len: u32, space: u32, margin: u32) -> u32
+	{
+		let free = space.saturating_sub(len);
+		match self {
+			Pos::At(at) => at.resolve(space),
+			Pos::Start => margin.min(free),
+			Pos::Middle => free / 2,
+			Pos::End => free.saturating_sub(margin),
+		}
+	}
+}
+
+/// Offset of the image, numbers or aligned with `M` for the middle and `E` for the end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Offset(pub Pos, pub Pos);
+
+impl Default for Offset
+{
+	fn default() -> Self
+	{
+		Offset(Pos::At(Length::Px(0)), Pos::At(Length::Px(0)))
+	}
+}
+
+/// Parses `XxY`, where both may also be percentages of the canvas, `M` or `E`
+pub fn parse_offset(s: &str) -> Result<Offset, String>
+{
+	let pos = |s: &str| match s {
+		"E" => Ok(Pos::End),
+		"M" => Ok(Pos::Middle),
+		_ => s.parse().map(Pos::At).map_err(|err| format!("invalid offset: {}", err)),
+	};
+	let (x, y) = s.split_once('x')
+		.ok_or_else(|| format!("expected XxY, got '{}'", s))?;
+	Ok(Offset(pos(x)?, pos(y)?))
+}
+
+/// Where on the canvas to put the image
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum Anchor
+{
+	TopLeft,
+	Top,
+	TopRight,
+	Left,
+	Center,
+	Right,
+	BottomLeft,
+	Bottom,
+	BottomRight,
+}
+
+impl From<Anchor> for Offset
+{
+	fn from(anchor: Anchor) -> Self
+	{
+		use Pos::*;
+		match anchor {
+			Anchor::TopLeft => Offset(Start, Start),
+			Anchor::Top => Offset(Middle, Start),
+			Anchor::TopRight => Offset(End, Start),
+			Anchor::Left => Offset(Start, Middle),
+			Anchor::Center => Offset(Middle, Middle),
+			Anchor::Right => Offset(End, Middle),
+			Anchor::BottomLeft => Offset(Start, End),
+			Anchor::Bottom => Offset(Middle, End),
+			Anchor::BottomRight => Offset(End, End),
+		}
+	}
+}
+
+/// Offset of an image of `size` on the `canvas`
+///
+/// Images larger than the canvas end up at its start on that axis.
+pub fn place(offset: Offset, margin: u32, (w, h): (u32, u32), (sw, sh): (u32, u32)) -> (u32, u32)
+{
+	(offset.0.resolve(w, sw, margin), offset.1.resolve(h, sh, margin))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn presets()
+	{
+		let canvas = (100, 50);
+		assert_eq!(parse_offset("ExM"), Ok(Offset(Pos::End, Pos::Middle)));
+		assert_eq!(parse_offset("12x3"), Ok(Offset(Pos::At(Length::Px(12)), Pos::At(Length::Px(3)))));
+		assert!(parse_offset("12").is_err());
+		assert!(parse_offset("Lx3").is_err());
+		assert!(parse_offset("-5%x3").is_err());
+		assert_eq!(place(parse_offset("10%x90%").unwrap(), 0, (20, 10), canvas), (10, 45));
+		assert_eq!(parse_size("50%x120"), Ok((Some(Length::Percent(50.0)), Some(Length::Px(120)))));
+		assert_eq!(parse_size("800x_"), Ok((Some(Length::Px(800)), None)));
+		assert!(parse_size("_x_").is_err());
+		assert!(parse_size("800x").is_err());
+		assert_eq!(Length::Percent(33.3).resolve(1920), 639);
+
+		assert_eq!(place(parse_offset("ExM").unwrap(), 0, (20, 10), canvas), (80, 20));
+		assert_eq!(place(Anchor::TopRight.into(), 10, (20, 10), canvas), (70, 10));
+		assert_eq!(place(Anchor::BottomLeft.into(), 10, (20, 10), canvas), (10, 30));
+		assert_eq!(place(Anchor::Center.into(), 10, (20, 10), canvas), (40, 20));
+		// the margin gives way before the image leaves the canvas
+		assert_eq!(place(Anchor::BottomRight.into(), 10, (95, 50), canvas), (0, 0));
+		assert_eq!(place(Anchor::TopLeft.into(), 10, (95, 60), canvas), (5, 0));
+	}
+}
@@ -0,0 +1,265 @@
+use std::{
+	fmt::Write as _,
+	io::{IsTerminal, Write},
+	net::SocketAddr,
+	sync::{mpsc, Arc},
+	time::Duration,
+};
+
+use image::{DynamicImage, GenericImageView, Rgba};
+use minifb::{ScaleMode, Window, WindowOptions};
+use tokio::{
+	sync::{oneshot, watch},
+	time,
+};
+use tracing as log;
+
+use crate::probe;
+
+
+/// Width of each pane in characters
+const COLUMNS: u32 = 60;
+/// Height of each pane in characters, each showing two rows of pixels
+const ROWS: u32 = 20;
+/// Samples per side of the window at most
+const SAMPLES: u32 = 160;
+/// Size the window opens with
+const WINDOW: (usize, usize) = (640, 480);
+
+/// Leaves the alternate screen when the preview stops
+struct Screen;
+
+impl Screen
+{
+	fn enter() -> Self
+	{
+		eprint!("\x1b[?1049h\x1b[?25l");
+		Self
+	}
+}
+
+impl Drop for Screen
+{
+	fn drop(&mut self)
+	{
+		eprint!("\x1b[?25h\x1b[?1049l");
+	}
+}
+
+/// Pixels of the image sampled one per cell and what the canvas at `at` shows there
+struct Samples
+{
+	size: (u32, u32),
+	ours: Vec<Rgba<u8>>,
+	theirs: Vec<Option<Rgba<u8>>>,
+}
+
+/// Reads back one pixel per cell of `step` pixels, in the middle of it
+async fn sample(host: SocketAddr, canvas: (u32, u32), at: (u32, u32), image: &DynamicImage, step: u32) -> anyhow::Result<Samples>
+{
+	let (w, h) = image.dimensions();
+	let cells = (0..h.div_ceil(step))
+		.flat_map(|cy| (0..w.div_ceil(step)).map(move |cx| (cx * step + step / 2, cy * step + step / 2)))
+		.map(|(x, y)| (x.min(w - 1), y.min(h - 1)))
+		.collect::<Vec<_>>();
+	let coords = cells.iter()
+		.map(|&(x, y)| (at.0 + x, at.1 + y))
+		.filter(|&(x, y)| x < canvas.0 && y < canvas.1)
+		.collect::<Vec<_>>();
+	let read = probe::grab(host, &coords).await?;
+	Ok(Samples {
+		size: (w.div_ceil(step), h.div_ceil(step)),
+		ours: cells.iter().map(|&(x, y)| image.get_pixel(x, y)).collect(),
+		theirs: cells.iter()
+			.map(|&(x, y)| read.get(&(at.0 + x, at.1 + y)).map(|&[r, g, b]| Rgba([r, g, b, 0xff])))
+			.collect(),
+	})
+}
+
+/// A frame of the window in 0RGB
+struct View
+{
+	size: (u32, u32),
+	pixels: Vec<u32>,
+	title: String,
+}
+
+/// Shows the views in a window until it is closed or they stop coming
+fn window(views: mpsc::Receiver<View>, opened: oneshot::Sender<anyhow::Result<()>>)
+{
+	let options = WindowOptions { resize: true, scale_mode: ScaleMode::AspectRatioStretch, ..WindowOptions::default() };
+	let mut window = match Window::new("pixelspray preview", WINDOW.0, WINDOW.1, options) {
+		Ok(window) => window,
+		Err(err) => {
+			opened.send(Err(anyhow::anyhow!("{}", err))).ok();
+			return;
+		},
+	};
+	opened.send(Ok(())).ok();
+	window.set_target_fps(30);
+	let mut view = View { size: (1, 1), pixels: vec![ 0 ], title: String::new() };
+	while window.is_open() {
+		match views.try_recv() {
+			Ok(next) => {
+				window.set_title(&next.title);
+				view = next;
+			},
+			Err(mpsc::TryRecvError::Empty) => {},
+			Err(mpsc::TryRecvError::Disconnected) => break,
+		}
+		if let Err(err) = window.update_with_buffer(&view.pixels, view.size.0 as usize, view.size.1 as usize) {
+			log::warn!("preview: {}", err);
+			break;
+		}
+	}
+}
+
+/// Shows what the canvas at `at` looks like with the image over it at half opacity, read back every `interval`
+///
+/// Without a display to open a window on, both are drawn next to each other on stderr instead.
+pub async fn run(host: SocketAddr, canvas: (u32, u32), at: (u32, u32), mut shown: watch::Receiver<Arc<DynamicImage>>, interval: Duration)
+{
+	let (views, rx) = mpsc::sync_channel(1);
+	let (opened, window_opened) = oneshot::channel();
+	std::thread::spawn(move || window(rx, opened));
+	match window_opened.await {
+		Ok(Ok(())) => {},
+		Ok(Err(err)) => return terminal(host, canvas, at, shown, interval, err).await,
+		Err(_) => return,
+	}
+
+	let mut ticker = time::interval(interval);
+	loop {
+		ticker.tick().await;
+		let image = shown.borrow_and_update().clone();
+		let (w, h) = image.dimensions();
+		let step = w.max(h).div_ceil(SAMPLES).max(1);
+		let samples = match sample(host, canvas, at, &image, step).await {
+			Ok(samples) => samples,
+			Err(err) => {
+				log::warn!("preview: {:#}", err);
+				continue;
+			},
+		};
+		let title = match owned(&samples.ours, &samples.theirs) {
+			Some((share, total)) => format!("pixelspray preview - ours: {:.1}% of {} sampled pixels", 100.0 * share, total),
+			None => "pixelspray preview".to_owned(),
+		};
+		let pixels = samples.ours.iter().zip(&samples.theirs)
+			.map(|(&ours, &theirs)| overlay(ours, theirs))
+			.collect();
+		match views.try_send(View { size: samples.size, pixels, title }) {
+			Ok(()) | Err(mpsc::TrySendError::Full(_)) => {},
+			// closed
+			Err(mpsc::TrySendError::Disconnected(_)) => return,
+		}
+	}
+}
+
+/// The preview in the terminal, if stderr is one
+async fn terminal(host: SocketAddr, canvas: (u32, u32), at: (u32, u32), mut shown: watch::Receiver<Arc<DynamicImage>>, interval: Duration, why: anyhow::Error)
+{
+	if !std::io::stderr().is_terminal() {
+		log::warn!("the preview needs a window or a terminal, but opening a window failed: {:#}", why);
+		return;
+	}
+	log::debug!("previewing in the terminal: {:#}", why);
+	let _screen = Screen::enter();
+	let mut ticker = time::interval(interval);
+	loop {
+		ticker.tick().await;
+		let image = shown.borrow_and_update().clone();
+		let (w, h) = image.dimensions();
+		let step = w.div_ceil(COLUMNS).max(h.div_ceil(2 * ROWS)).max(1);
+		let samples = match sample(host, canvas, at, &image, step).await {
+			Ok(samples) => samples,
+			Err(err) => {
+				log::warn!("preview: {:#}", err);
+				continue;
+			},
+		};
+		let mut stderr = std::io::stderr().lock();
+		write!(stderr, "\x1b[H\x1b[2J{}", render(samples.size, &samples.ours, &samples.theirs)).ok();
+		stderr.flush().ok();
+	}
+}
+
+/// A pixel of the canvas with ours over it at half its alpha, in 0RGB
+fn overlay(ours: Rgba<u8>, theirs: Option<Rgba<u8>>) -> u32
+{
+	// unknown pixels are drawn dark
+	let theirs = theirs.map_or([0x20; 3], |px| [px.0[0], px.0[1], px.0[2]]);
+	let a = ours.0[3] as u32;
+	let [r, g, b] = [0, 1, 2].map(|i| (theirs[i] as u32 * (510 - a) + ours.0[i] as u32 * a + 255) / 510);
+	r << 16 | g << 8 | b
+}
+
+/// The share of our opaque pixels found on the canvas, and how many there are
+fn owned(ours: &[Rgba<u8>], theirs: &[Option<Rgba<u8>>]) -> Option<(f64, u32)>
+{
+	let (mut owned, mut total) = (0, 0);
+	for (ours, theirs) in ours.iter().zip(theirs) {
+		if ours.0[3] == 0xff {
+			total += 1;
+			owned += theirs.is_some_and(|theirs| theirs.0[..3] == ours.0[..3]) as u32;
+		}
+	}
+	(total > 0).then(|| (owned as f64 / total as f64, total))
+}
+
+/// Draws both images side by side with half blocks, followed by the share of our pixels on the canvas
+fn render((w, h): (u32, u32), ours: &[Rgba<u8>], theirs: &[Option<Rgba<u8>>]) -> String
+{
+	// transparent and unknown pixels are drawn dark
+	let color = |px: Option<Rgba<u8>>| px.filter(|px| px.0[3] > 0).map_or([0x20; 3], |px| [px.0[0], px.0[1], px.0[2]]);
+	let cell = |out: &mut String, top: [u8; 3], bottom: [u8; 3]| {
+		write!(out, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+			top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]).ok();
+	};
+
+	let mut out = String::new();
+	for y in (0..h).step_by(2) {
+		let at = |x: u32, y: u32| (y.min(h - 1) * w + x) as usize;
+		for x in 0..w {
+			cell(&mut out, color(Some(ours[at(x, y)])), color((y + 1 < h).then(|| ours[at(x, y + 1)])));
+		}
+		out += "\x1b[0m  ";
+		for x in 0..w {
+			cell(&mut out, color(theirs[at(x, y)]), color(theirs.get(at(x, y + 1)).copied().flatten().filter(|_| y + 1 < h)));
+		}
+		out += "\x1b[0m\n";
+	}
+
+	if let Some((share, total)) = owned(ours, theirs) {
+		writeln!(out, "ours: {:.1}% of {} sampled pixels", 100.0 * share, total).ok();
+	}
+	out
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn side_by_side()
+	{
+		let red = Rgba([0xff, 0, 0, 0xff]);
+		let blue = Rgba([0, 0, 0xff, 0xff]);
+		let out = render((1, 2), &[red, red], &[Some(red), Some(blue)]);
+		assert_eq!(out, "\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m\u{2580}\x1b[0m  \
+			\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m\u{2580}\x1b[0m\n\
+			ours: 50.0% of 2 sampled pixels\n");
+	}
+
+	#[test]
+	fn overlaid()
+	{
+		let red = Rgba([0xff, 0, 0, 0xff]);
+		assert_eq!(overlay(red, Some(Rgba([0, 0, 0xff, 0xff]))), 0x80_00_80);
+		assert_eq!(overlay(red, Some(red)), 0xff_00_00);
+		assert_eq!(overlay(Rgba([0xff; 4]), None), 0x90_90_90);
+		assert_eq!(overlay(Rgba([0xff, 0xff, 0xff, 0]), Some(red)), 0xff_00_00);
+	}
+}
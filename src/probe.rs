@@ -0,0 +1,350 @@
+use std::{
+	collections::HashMap,
+	fmt,
+	net::SocketAddr,
+	str::FromStr,
+};
+
+use anyhow::Context;
+
+use futures::{
+	sink::SinkExt,
+	stream::StreamExt,
+};
+use tokio::{
+	io::{self, AsyncBufReadExt, AsyncWriteExt},
+	net,
+	time,
+};
+use tokio_util::codec::{Decoder, Framed, LinesCodec};
+use tracing as log;
+
+use crate::profile::{self, Profile};
+
+
+/// Canvas size when nothing else is known
+pub const DEFAULT_SIZE: (u32, u32) = (1024, 768);
+/// Longest side of a canvas believed, longer ones are taken for garbage
+const MAX_SIDE: u32 = 1 << 16;
+/// Longest reply line taken, so a server without line breaks can not fill up the memory
+const MAX_REPLY: usize = 4096;
+
+/// How the canvas size was determined
+#[derive(Debug, Clone, PartialEq)]
+pub enum Strategy
+{
+	Size,
+	Help,
+	Fingerprint(String),
+	Profile(String),
+	Default,
+}
+
+impl fmt::Display for Strategy
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		match self {
+			Strategy::Size => write!(f, "SIZE reply"),
+			Strategy::Help => write!(f, "HELP text"),
+			Strategy::Fingerprint(name) => write!(f, "{} default", name),
+			Strategy::Profile(name) => write!(f, "{} profile", name),
+			Strategy::Default => write!(f, "fallback default"),
+		}
+	}
+}
+
+/// What the server told about itself
+#[derive(Debug)]
+pub struct Probed
+{
+	pub size: (u32, u32),
+	pub strategy: Strategy,
+	/// The HELP reply, if asked for
+	pub help: Vec<String>,
+}
+
+/// Asks for the canvas size, falling back to HELP, server fingerprints and the `profile`
+///
+/// HELP is always asked for with `help` set, otherwise only if SIZE fails.
+pub async fn canvas(stream: net::TcpStream, profiles: &[Profile], profile: Option<&Profile>, help: bool) -> (net::TcpStream, Probed)
+{
+	let mut stream = LinesCodec::new_with_max_length(MAX_REPLY).framed(stream);
+
+	let size = ask(&mut stream, "SIZE", time::Duration::from_secs(2), |line| parse_size_reply(line).is_some()).await
+		.iter().find_map(|line| parse_size_reply(line));
+	let help = if size.is_none() || help {
+		// HELP has no last line, so only silence ends it
+		ask(&mut stream, "HELP", time::Duration::from_millis(500), |_| false).await
+	} else {
+		Vec::new()
+	};
+	for line in help.iter() {
+		log::debug!("HELP: {}", line);
+	}
+
+	let (size, strategy) = if let Some(size) = size {
+		(size, Strategy::Size)
+	} else if let Some(size) = size_from_help(&help) {
+		(size, Strategy::Help)
+	} else if let Some((name, size)) = profile.and_then(|p| Some((&p.name, p.canvas?))) {
+		(size, Strategy::Profile(name.clone()))
+	} else if let Some((name, size)) = profile::detect(profiles, &help).and_then(|p| Some((&p.name, p.canvas?))) {
+		(size, Strategy::Fingerprint(name.clone()))
+	} else {
+		(DEFAULT_SIZE, Strategy::Default)
+	};
+	(stream.into_inner(), Probed { size, strategy, help })
+}
+
+/// Sends a command and collects reply lines until one is `done` or none arrives for `wait`
+async fn ask(stream: &mut Framed<net::TcpStream, LinesCodec>, cmd: &str, wait: time::Duration, done: impl Fn(&str) -> bool) -> Vec<String>
+{
+	let mut lines = Vec::new();
+	if stream.send(cmd).await.is_err() {
+		return lines;
+	}
+	while lines.len() < 64 {
+		match time::timeout(wait, stream.next()).await {
+			Ok(Some(Ok(line))) => {
+				let last = done(&line);
+				lines.push(line);
+				if last {
+					break;
+				}
+			},
+			_ => break,
+		}
+	}
+	lines
+}
+
+/// Reads back the colors of the pixels at `coords`
+///
+/// Pixels the server did not answer for are left out.
+pub async fn grab(host: SocketAddr, coords: &[(u32, u32)]) -> anyhow::Result<HashMap<(u32, u32), [u8; 3]>>
+{
+	let stream = net::TcpStream::connect(host).await
+		.context("failed to connect")?;
+	let (rd, mut wr) = stream.into_split();
+
+	let reads = coords.iter()
+		.map(|(x, y)| format!("PX {} {}\n", x, y))
+		.collect::<String>();
+	let writer = tokio::spawn(async move {
+		wr.write_all(reads.as_bytes()).await?;
+		io::Result::Ok(wr)
+	});
+
+	let mut pixels = HashMap::with_capacity(coords.len());
+	let mut lines = io::BufReader::new(rd).lines();
+	while pixels.len() < coords.len() {
+		let line = match time::timeout(time::Duration::from_secs(2), lines.next_line()).await {
+			Ok(line) => line.context("failed to read pixels")?,
+			Err(_) => break,
+		};
+		let Some(line) = line else { break };
+		if let Some(px) = parse_px_reply(&line) {
+			pixels.insert(px.0, px.1);
+		}
+	}
+	writer.abort();
+	if pixels.len() < coords.len() {
+		log::warn!("got only {} of {} pixels back", pixels.len(), coords.len());
+	}
+	Ok(pixels)
+}
+
+/// Whether the server takes the 2-digit grey form of PX
+///
+/// The pixel at 0,0 is painted grey for a moment and gets its color back afterwards.
+pub async fn grey(host: SocketAddr) -> anyhow::Result<bool>
+{
+	let wait = time::Duration::from_millis(500);
+	let stream = net::TcpStream::connect(host).await
+		.context("failed to connect")?;
+	let mut stream = LinesCodec::new_with_max_length(MAX_REPLY).framed(stream);
+	let read = |lines: Vec<String>| lines.iter().find_map(|line| parse_px_reply(line)).map(|(_, color)| color);
+
+	let before = read(ask(&mut stream, "PX 0 0", wait, |line| parse_px_reply(line).is_some()).await)
+		.context("no reply to a read")?;
+	// a level the pixel does not have yet, so an ignored command shows
+	let level = if before == [0x5a; 3] { 0xa5 } else { 0x5a };
+	stream.send(format!("PX 0 0 {:02X}", level)).await?;
+	let after = read(ask(&mut stream, "PX 0 0", wait, |line| parse_px_reply(line).is_some()).await);
+	let [r, g, b] = before;
+	stream.send(format!("PX 0 0 {:02X}{:02X}{:02X}", r, g, b)).await?;
+	// the reply to a read after it tells the color is back before anything else is painted
+	read(ask(&mut stream, "PX 0 0", wait, |line| parse_px_reply(line).is_some()).await)
+		.context("no reply to a read")?;
+	Ok(after == Some([level; 3]))
+}
+
+/// Parses `PX x y RRGGBB`, ignoring any alpha
+pub fn parse_px_reply(line: &str) -> Option<((u32, u32), [u8; 3])>
+{
+	let mut i = line.split_ascii_whitespace();
+	if i.next()? != "PX" {
+		return None;
+	}
+	let x = u32::from_str(i.next()?).ok()?;
+	let y = u32::from_str(i.next()?).ok()?;
+	let color = i.next()?;
+	let v = u32::from_str_radix(color.get(..6)?, 16).ok()?;
+	Some(((x, y), [(v >> 16) as u8, (v >> 8) as u8, v as u8]))
+}
+
+/// Parses `SIZE w h`
+pub fn parse_size_reply(line: &str) -> Option<(u32, u32)>
+{
+	let mut i = line.split_ascii_whitespace();
+	if i.next()? != "SIZE" {
+		return None;
+	}
+	let w = u32::from_str(i.next()?).ok()?;
+	let h = u32::from_str(i.next()?).ok()?;
+	Some((w, h)).filter(plausible)
+}
+
+/// Whether a canvas can have this size
+fn plausible(&(w, h): &(u32, u32)) -> bool
+{
+	(1..=MAX_SIDE).contains(&w) && (1..=MAX_SIDE).contains(&h)
+}
+
+/// Looks for a `SIZE w h` line or a `WxH` word
+fn size_from_help(help: &[String]) -> Option<(u32, u32)>
+{
+	help.iter().find_map(|line| parse_size_reply(line))
+		.or_else(|| help.iter()
+			.flat_map(|line| line.split(|c: char| !c.is_ascii_alphanumeric()))
+			.find_map(|word| {
+				let (w, h) = word.split_once('x')?;
+				Some((u32::from_str(w).ok()?, u32::from_str(h).ok()?))
+			})
+			.filter(plausible))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn lines(s: &[&str]) -> Vec<String>
+	{
+		s.iter().map(|s| s.to_string()).collect()
+	}
+
+	#[test]
+	fn size_reply()
+	{
+		assert_eq!(parse_size_reply("SIZE 800 600"), Some((800, 600)));
+		assert_eq!(parse_size_reply("SIZE 800"), None);
+		assert_eq!(parse_size_reply("ERROR unknown command"), None);
+		assert_eq!(parse_size_reply("SIZE 0 0"), None);
+		assert_eq!(parse_size_reply("SIZE 4294967295 1"), None);
+	}
+
+	#[test]
+	fn px_reply()
+	{
+		assert_eq!(parse_px_reply("PX 3 4 A0B0C0"), Some(((3, 4), [0xa0, 0xb0, 0xc0])));
+		assert_eq!(parse_px_reply("PX 3 4 A0B0C0FF"), Some(((3, 4), [0xa0, 0xb0, 0xc0])));
+		assert_eq!(parse_px_reply("PX 3 4"), None);
+	}
+
+	#[tokio::test]
+	async fn grey_form()
+	{
+		let server = crate::server::Server::bind("127.0.0.1:0".parse().unwrap(), (4, 4)).await.unwrap();
+		let addr = server.local_addr().unwrap();
+		let state = server.state();
+		tokio::spawn(server.run());
+
+		state.canvas.lock().unwrap().put_pixel(0, 0, image::Rgba([1, 2, 3, 0xff]));
+		assert!(grey(addr).await.unwrap());
+		assert_eq!(state.canvas.lock().unwrap().get_pixel(0, 0).0, [1, 2, 3, 0xff]);
+	}
+
+	#[test]
+	fn help_text()
+	{
+		assert_eq!(size_from_help(&lines(&["Commands:", "PX x y rrggbb (canvas: 1920x1080)"])), Some((1920, 1080)));
+		assert_eq!(size_from_help(&lines(&["Commands:", "SIZE 640 480"])), Some((640, 480)));
+		assert_eq!(size_from_help(&lines(&["PX x y rrggbb"])), None);
+	}
+
+	/// Probes a server that answers anything with `reply` and hangs up
+	fn handshake(reply: Vec<u8>) -> Probed
+	{
+		let rt = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+		rt.block_on(async {
+			let listener = net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+			let addr = listener.local_addr().unwrap();
+			tokio::spawn(async move {
+				let (mut stream, _) = listener.accept().await.unwrap();
+				stream.write_all(&reply).await.ok();
+			});
+			let stream = net::TcpStream::connect(addr).await.unwrap();
+			canvas(stream, &profile::builtin(), None, true).await.1
+		})
+	}
+
+	#[tokio::test]
+	async fn size_answered()
+	{
+		let listener = net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		tokio::spawn(async move {
+			let (mut stream, _) = listener.accept().await.unwrap();
+			stream.write_all(b"SIZE 800 600\n").await.unwrap();
+			// the connection stays open, as with a real server
+			time::sleep(time::Duration::from_secs(10)).await;
+		});
+		let stream = net::TcpStream::connect(addr).await.unwrap();
+		let start = time::Instant::now();
+		let (_, probed) = canvas(stream, &[], None, false).await;
+		assert_eq!((probed.size, probed.strategy), ((800, 600), Strategy::Size));
+		assert!(start.elapsed() < time::Duration::from_secs(1), "{:?}", start.elapsed());
+	}
+
+	fn reply() -> impl proptest::strategy::Strategy<Value = Vec<u8>>
+	{
+		use proptest::prelude::*;
+		let line = prop_oneof![
+			(any::<u32>(), any::<u32>()).prop_map(|(w, h)| format!("SIZE {} {}", w, h)),
+			"SIZE [0-9 -]{0,24}",
+			"ERROR[ -~]{0,32}",
+			"[ -~]{0,64}x[0-9]{0,12}",
+			"\\PC{0,64}",
+		].prop_map(String::into_bytes);
+		prop_oneof![
+			proptest::collection::vec(line, 0..8).prop_map(|lines| lines.join(&b'\n')),
+			// invalid UTF-8 and lines longer than taken
+			proptest::collection::vec(any::<u8>(), 0..8192),
+		]
+	}
+
+	proptest::proptest! {
+		#![proptest_config(proptest::prelude::ProptestConfig::with_cases(64))]
+
+		#[test]
+		fn any_reply(reply in reply())
+		{
+			let probed = handshake(reply);
+			proptest::prop_assert!(plausible(&probed.size), "{:?}", probed.size);
+			proptest::prop_assert!(probed.help.len() <= 64);
+		}
+
+		#[test]
+		fn any_line(line in "\\PC*")
+		{
+			if let Some(size) = parse_size_reply(&line) {
+				proptest::prop_assert!(plausible(&size));
+			}
+			parse_px_reply(&line);
+			size_from_help(&[line]);
+		}
+	}
+}
@@ -0,0 +1,170 @@
+use std::{
+	path::Path,
+	str::FromStr,
+};
+
+use anyhow::Context;
+
+
+/// Known quirks of a server implementation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile
+{
+	pub name: String,
+	/// Text in the HELP reply identifying the server
+	pub fingerprint: Option<String>,
+	/// Canvas size if the server does not tell
+	pub canvas: Option<(u32, u32)>,
+	/// How many connections are accepted per client
+	pub max_connections: Option<usize>,
+	/// Whether OFFSET is supported
+	pub offset: bool,
+	/// Whether the 2-digit grey form of PX is supported
+	pub grey: bool,
+	/// Whether a binary pixel extension is supported
+	pub binary: bool,
+}
+
+impl Profile
+{
+	fn new(name: &str) -> Self
+	{
+		Self {
+			name: name.to_owned(),
+			fingerprint: None,
+			canvas: None,
+			max_connections: None,
+			offset: false,
+			grey: false,
+			binary: false,
+		}
+	}
+}
+
+pub fn builtin() -> Vec<Profile>
+{
+	vec![
+		Profile::new("pixelflut"),
+		Profile {
+			fingerprint: Some("shoreline".to_owned()),
+			canvas: Some((1024, 768)),
+			offset: true,
+			..Profile::new("shoreline")
+		},
+		Profile {
+			fingerprint: Some("pixelnuke".to_owned()),
+			..Profile::new("pixelnuke")
+		},
+		Profile {
+			fingerprint: Some("wellenbrecher".to_owned()),
+			offset: true,
+			grey: true,
+			binary: true,
+			..Profile::new("wellenbrecher")
+		},
+		Profile {
+			fingerprint: Some("breakwater".to_owned()),
+			canvas: Some((1280, 720)),
+			offset: true,
+			grey: true,
+			binary: true,
+			..Profile::new("breakwater")
+		},
+	]
+}
+
+/// Reads profiles from a file of `[name]` sections with `key = value` lines
+pub fn load(path: &Path) -> anyhow::Result<Vec<Profile>>
+{
+	let text = std::fs::read_to_string(path)
+		.with_context(|| format!("failed to read {}", path.display()))?;
+	parse(&text)
+		.with_context(|| format!("invalid profiles in {}", path.display()))
+}
+
+fn parse(text: &str) -> anyhow::Result<Vec<Profile>>
+{
+	let mut profiles: Vec<Profile> = Vec::new();
+	for (n, line) in text.lines().enumerate().map(|(n, line)| (n + 1, line.trim())) {
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+			profiles.push(Profile::new(name.trim()));
+			continue;
+		}
+
+		let profile = profiles.last_mut()
+			.with_context(|| format!("line {}: setting outside of a [profile] section", n))?;
+		let (key, value) = line.split_once('=')
+			.with_context(|| format!("line {}: expected key = value", n))?;
+		let (key, value) = (key.trim(), value.trim());
+		let bool = || bool::from_str(value)
+			.with_context(|| format!("line {}: expected true or false for {}", n, key));
+		match key {
+			"fingerprint" => profile.fingerprint = Some(value.to_lowercase()),
+			"canvas" => profile.canvas = Some(crate::parse_size(value)
+				.map_err(|err| anyhow::anyhow!("line {}: {}", n, err))?),
+			"max_connections" => profile.max_connections = Some(usize::from_str(value)
+				.with_context(|| format!("line {}: invalid max_connections", n))?),
+			"offset" => profile.offset = bool()?,
+			"grey" => profile.grey = bool()?,
+			"binary" => profile.binary = bool()?,
+			_ => anyhow::bail!("line {}: unknown setting {}", n, key),
+		}
+	}
+	Ok(profiles)
+}
+
+pub fn find<'a>(profiles: &'a [Profile], name: &str) -> Option<&'a Profile>
+{
+	profiles.iter().find(|profile| profile.name == name)
+}
+
+/// Identifies the server by its HELP reply
+pub fn detect<'a>(profiles: &'a [Profile], help: &[String]) -> Option<&'a Profile>
+{
+	let help = help.join("\n").to_lowercase();
+	profiles.iter()
+		.find(|profile| profile.fingerprint.as_ref().is_some_and(|fp| help.contains(fp.as_str())))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn parse_profiles()
+	{
+		let profiles = parse("
+			# event server
+			[camp]
+			fingerprint = CampFlut
+			canvas = 1920x1080
+			max_connections = 16
+			offset = true
+		").unwrap();
+
+		assert_eq!(profiles, [Profile {
+			fingerprint: Some("campflut".to_owned()),
+			canvas: Some((1920, 1080)),
+			max_connections: Some(16),
+			offset: true,
+			..Profile::new("camp")
+		}]);
+		assert!(parse("offset = true").is_err());
+		assert!(parse("[x]\noffset = maybe").is_err());
+		assert!(parse("[x]\ncolor = red").is_err());
+	}
+
+	#[test]
+	fn detect_by_help()
+	{
+		let profiles = builtin();
+		let help = ["Pixelflut server powered by Breakwater".to_owned()];
+		assert_eq!(detect(&profiles, &help).map(|p| p.name.as_str()), Some("breakwater"));
+		assert_eq!(detect(&profiles, &["something else".to_owned()]), None);
+	}
+}
@@ -0,0 +1,131 @@
+use std::{
+	cell::RefCell,
+	io::{IsTerminal, Write},
+	sync::{
+		atomic::{AtomicBool, AtomicU64, Ordering},
+		Arc,
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+
+const WIDTH: usize = 30;
+
+/// Progress of a long task, drawn as a bar on stderr
+#[derive(Debug)]
+pub struct Progress
+{
+	label: &'static str,
+	total: u64,
+	done: AtomicU64,
+	finished: AtomicBool,
+	start: Instant,
+}
+
+thread_local! {
+	static CURRENT: RefCell<Option<Arc<Progress>>> = const { RefCell::new(None) };
+}
+
+impl Progress
+{
+	pub fn new(label: &'static str, total: u64) -> Arc<Self>
+	{
+		Arc::new(Self {
+			label,
+			total: total.max(1),
+			done: AtomicU64::new(0),
+			finished: AtomicBool::new(false),
+			start: Instant::now(),
+		})
+	}
+
+	pub fn inc(&self, n: u64)
+	{
+		if self.done.fetch_add(n, Ordering::Relaxed) + n >= self.total {
+			self.finish();
+		}
+	}
+
+	pub fn finish(&self)
+	{
+		self.finished.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_finished(&self) -> bool
+	{
+		self.finished.load(Ordering::Relaxed)
+	}
+
+	fn line(&self, elapsed: Duration) -> String
+	{
+		let done = self.done.load(Ordering::Relaxed).min(self.total);
+		let f = done as f64 / self.total as f64;
+		let filled = (f * WIDTH as f64) as usize;
+		let eta = if done > 0 && done < self.total {
+			let left = elapsed.as_secs_f64() * (self.total - done) as f64 / done as f64;
+			format!(" ETA {}s", left.ceil() as u64)
+		} else {
+			String::new()
+		};
+		format!("{} [{}{}] {:3.0}%{}", self.label, "#".repeat(filled), "-".repeat(WIDTH - filled), f * 100.0, eta)
+	}
+
+	/// Draws the bar on stderr until it is finished, if stderr is a terminal
+	pub fn show(self: &Arc<Self>) -> Option<thread::JoinHandle<()>>
+	{
+		if !std::io::stderr().is_terminal() {
+			return None;
+		}
+		let progress = self.clone();
+		Some(thread::spawn(move || {
+			loop {
+				let finished = progress.is_finished();
+				let mut stderr = std::io::stderr().lock();
+				write!(stderr, "\r{}\x1b[K", progress.line(progress.start.elapsed())).ok();
+				if finished {
+					writeln!(stderr).ok();
+					break;
+				}
+				drop(stderr);
+				thread::sleep(Duration::from_millis(100));
+			}
+		}))
+	}
+
+	/// Makes the progress `current` on this thread while running `f`
+	pub fn scope<R>(self: &Arc<Self>, f: impl FnOnce() -> R) -> R
+	{
+		CURRENT.with(|current| *current.borrow_mut() = Some(self.clone()));
+		let res = f();
+		CURRENT.with(|current| *current.borrow_mut() = None);
+		res
+	}
+}
+
+/// The progress tracked on this thread, if any
+pub fn current() -> Option<Arc<Progress>>
+{
+	CURRENT.with(|current| current.borrow().clone())
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn bar()
+	{
+		let progress = Progress::new("Encoding", 200);
+		progress.scope(|| current().unwrap().inc(50));
+		assert!(current().is_none());
+		assert_eq!(progress.line(Duration::from_secs(3)),
+			format!("Encoding [{}{}]  25% ETA 9s", "#".repeat(7), "-".repeat(23)));
+
+		progress.inc(150);
+		assert!(progress.is_finished());
+		assert_eq!(progress.line(Duration::from_secs(12)), format!("Encoding [{}] 100%", "#".repeat(30)));
+	}
+}
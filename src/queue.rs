@@ -0,0 +1,178 @@
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex},
+};
+
+use clap::ValueEnum;
+use tokio::sync::Notify;
+
+
+/// Chunks queued per connection by default, few so animations and pings do not wait behind a backlog
+pub const DEPTH: usize = 4;
+
+/// What happens to a chunk for a full queue
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq,Default)]
+pub enum Policy
+{
+	/// Wait for room, so every chunk is sent
+	#[default]
+	Block,
+	/// Make room by dropping the chunk queued the longest, so stale frames go first
+	DropOldest,
+	/// Drop the new chunk
+	DropNewest,
+}
+
+#[derive(Debug)]
+struct State<T>
+{
+	items: VecDeque<T>,
+	sender: bool,
+	receiver: bool,
+}
+
+#[derive(Debug)]
+struct Shared<T>
+{
+	state: Mutex<State<T>>,
+	capacity: usize,
+	policy: Policy,
+	readable: Notify,
+	writable: Notify,
+}
+
+/// Bounded queue from one sender to one receiver, with a policy for when it is full
+pub fn channel<T>(capacity: usize, policy: Policy) -> (Sender<T>, Receiver<T>)
+{
+	let shared = Arc::new(Shared {
+		state: Mutex::new(State { items: VecDeque::with_capacity(capacity), sender: true, receiver: true }),
+		capacity: capacity.max(1),
+		policy,
+		readable: Notify::new(),
+		writable: Notify::new(),
+	});
+	(Sender(shared.clone()), Receiver(shared))
+}
+
+#[derive(Debug)]
+pub struct Sender<T>(Arc<Shared<T>>);
+
+impl<T> Sender<T>
+{
+	/// Queues an item, which is only given back once the receiver is gone
+	pub async fn send(&self, item: T) -> Result<(), T>
+	{
+		loop {
+			let writable = self.0.writable.notified();
+			{
+				let mut state = self.0.state.lock().unwrap();
+				if !state.receiver {
+					return Err(item);
+				}
+				if state.items.len() >= self.0.capacity {
+					match self.0.policy {
+						Policy::Block => {},
+						Policy::DropOldest => { state.items.pop_front(); },
+						Policy::DropNewest => return Ok(()),
+					}
+				}
+				if state.items.len() < self.0.capacity {
+					state.items.push_back(item);
+					self.0.readable.notify_one();
+					return Ok(());
+				}
+			}
+			writable.await;
+		}
+	}
+}
+
+impl<T> Drop for Sender<T>
+{
+	fn drop(&mut self)
+	{
+		self.0.state.lock().unwrap().sender = false;
+		self.0.readable.notify_one();
+	}
+}
+
+#[derive(Debug)]
+pub struct Receiver<T>(Arc<Shared<T>>);
+
+impl<T> Receiver<T>
+{
+	/// Waits for the next item, `None` once the sender is gone and the queue is empty
+	pub async fn recv(&mut self) -> Option<T>
+	{
+		loop {
+			let readable = self.0.readable.notified();
+			{
+				let mut state = self.0.state.lock().unwrap();
+				if let Some(item) = state.items.pop_front() {
+					self.0.writable.notify_one();
+					return Some(item);
+				}
+				if !state.sender {
+					return None;
+				}
+			}
+			readable.await;
+		}
+	}
+
+	/// The next item, if one is queued
+	pub fn try_recv(&mut self) -> Option<T>
+	{
+		let item = self.0.state.lock().unwrap().items.pop_front();
+		if item.is_some() {
+			self.0.writable.notify_one();
+		}
+		item
+	}
+}
+
+impl<T> Drop for Receiver<T>
+{
+	fn drop(&mut self)
+	{
+		self.0.state.lock().unwrap().receiver = false;
+		self.0.writable.notify_one();
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[tokio::test]
+	async fn policies()
+	{
+		let (tx, mut rx) = channel(2, Policy::DropOldest);
+		for i in 0..4 {
+			tx.send(i).await.unwrap();
+		}
+		assert_eq!((rx.try_recv(), rx.try_recv(), rx.try_recv()), (Some(2), Some(3), None));
+
+		let (tx, mut rx) = channel(2, Policy::DropNewest);
+		for i in 0..4 {
+			tx.send(i).await.unwrap();
+		}
+		drop(tx);
+		assert_eq!((rx.recv().await, rx.recv().await, rx.recv().await), (Some(0), Some(1), None));
+
+		// a full queue waits for the receiver
+		let (tx, mut rx) = channel(1, Policy::Block);
+		tx.send(0).await.unwrap();
+		let sent = tokio::spawn(async move { tx.send(1).await });
+		assert_eq!(rx.recv().await, Some(0));
+		assert_eq!(rx.recv().await, Some(1));
+		sent.await.unwrap().unwrap();
+		assert_eq!(rx.recv().await, None);
+
+		let (tx, rx) = channel(1, Policy::Block);
+		drop(rx);
+		assert_eq!(tx.send(0).await, Err(0));
+	}
+}
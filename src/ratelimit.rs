@@ -0,0 +1,219 @@
+use std::{
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use tokio::time;
+use tracing as log;
+
+
+/// Halve the rate on every notice
+const DECREASE: f64 = 0.5;
+/// Notices within this time after a cut belong to the same throttling
+const COOLDOWN: Duration = Duration::from_secs(1);
+/// Raise the rate by one step this often while the server is quiet
+const INCREASE_INTERVAL: Duration = Duration::from_secs(1);
+/// Fraction of the unthrottled rate added per step
+const STEP: f64 = 0.05;
+/// Never limit below this many bytes per second
+const MIN_RATE: f64 = 1024.0;
+/// Throughput is measured over windows this long
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Whether a line from the server asks to send slower
+pub fn is_throttle(line: &str) -> bool
+{
+	let line = line.to_ascii_lowercase();
+	["rate limit", "ratelimit", "rate-limit", "throttl", "too many", "too fast", "slow down"]
+		.iter()
+		.any(|notice| line.contains(notice))
+}
+
+/// Send rate shared by all connections, cut in half when the server complains
+/// and raised step by step while it does not (AIMD)
+#[derive(Debug, Default)]
+pub struct Aimd
+{
+	state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State
+{
+	/// Allowed bytes per second, unlimited if `None`
+	rate: Option<f64>,
+	/// Rate before the first cut
+	ceiling: f64,
+	tokens: f64,
+	refilled: Option<Instant>,
+	cut: Option<Instant>,
+	/// Last change of the rate
+	changed: Option<Instant>,
+	/// Bytes sent in the current and the previous window
+	window: (Option<Instant>, u64),
+	measured: f64,
+}
+
+impl Aimd
+{
+	/// Allowed bytes per second, if limited
+	pub fn rate(&self) -> Option<f64>
+	{
+		self.state.lock().unwrap().rate
+	}
+
+	/// Reacts to a throttle notice of the server
+	pub fn throttled(&self)
+	{
+		self.throttled_at(Instant::now());
+	}
+
+	fn throttled_at(&self, now: Instant)
+	{
+		let mut state = self.state.lock().unwrap();
+		if state.cut.is_some_and(|at| now < at + COOLDOWN) && state.rate.is_some() {
+			return;
+		}
+		let rate = match state.rate {
+			Some(rate) => rate,
+			None => {
+				// early on there is only the current window to go by
+				let current = match state.window {
+					(Some(start), sent) if now > start => sent as f64 / (now - start).as_secs_f64(),
+					_ => 0.0,
+				};
+				// e.g. a greeting, nothing was sent to be too fast yet
+				if state.measured == 0.0 && current == 0.0 {
+					return;
+				}
+				state.ceiling = state.measured.max(current).max(MIN_RATE);
+				state.tokens = 0.0;
+				state.refilled = Some(now);
+				state.ceiling
+			},
+		};
+		let rate = rate * DECREASE;
+		log::warn!("server is throttling, limiting to {:.1} kB/s", rate / 1e3);
+		state.rate = Some(rate);
+		state.cut = Some(now);
+		state.changed = Some(now);
+	}
+
+	/// Waits until `bytes` may be sent
+	pub async fn acquire(&self, bytes: usize)
+	{
+		let wait = self.reserve(bytes, Instant::now());
+		if !wait.is_zero() {
+			time::sleep(wait).await;
+		}
+	}
+
+	/// Takes `bytes` from the budget and returns how long to wait for them
+	fn reserve(&self, bytes: usize, now: Instant) -> Duration
+	{
+		let mut state = self.state.lock().unwrap();
+		let state = &mut *state;
+
+		let (start, sent) = state.window;
+		match start {
+			Some(start) if now < start + WINDOW => state.window.1 += bytes as u64,
+			_ => {
+				if let Some(start) = start {
+					state.measured = sent as f64 / (now - start).as_secs_f64();
+				}
+				state.window = (Some(now), bytes as u64);
+			},
+		}
+
+		let Some(mut rate) = state.rate else {
+			return Duration::ZERO;
+		};
+		if let Some(changed) = state.changed.filter(|&at| now >= at + INCREASE_INTERVAL) {
+			rate += state.ceiling * STEP;
+			state.changed = Some(changed + INCREASE_INTERVAL);
+			if rate >= state.ceiling {
+				log::info!("server stopped throttling, rate limit lifted");
+				state.rate = None;
+				state.changed = None;
+				return Duration::ZERO;
+			}
+			state.rate = Some(rate);
+		}
+
+		let refilled = state.refilled.replace(now).unwrap_or(now);
+		// allow bursts of a tenth of a second
+		state.tokens = (state.tokens + (now - refilled).as_secs_f64() * rate).min(rate / 10.0);
+		state.tokens -= bytes as f64;
+		if state.tokens >= 0.0 {
+			Duration::ZERO
+		} else {
+			Duration::from_secs_f64(-state.tokens / rate)
+		}
+	}
+}
+
+/// Logs the rate limit every `interval` while there is one
+pub async fn report(aimd: std::sync::Arc<Aimd>, interval: Duration)
+{
+	let mut ticker = time::interval(interval);
+	ticker.tick().await;
+	loop {
+		ticker.tick().await;
+		if let Some(rate) = aimd.rate() {
+			log::info!("rate limit: {:.1} kB/s", rate / 1e3);
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn notices()
+	{
+		assert!(is_throttle("ERROR: You are rate limited"));
+		assert!(is_throttle("Throttling connection, slow down!"));
+		assert!(!is_throttle("SIZE 1920 1080"));
+		assert!(!is_throttle("PX 1 2 ff0000"));
+	}
+
+	#[test]
+	fn aimd()
+	{
+		let aimd = Aimd::default();
+		let start = Instant::now();
+		let at = |ms| start + Duration::from_millis(ms);
+		assert_eq!(aimd.reserve(4000, at(0)), Duration::ZERO);
+		assert_eq!(aimd.reserve(4000, at(1000)), Duration::ZERO);
+		assert_eq!(aimd.rate(), None);
+
+		// measured 4000 B/s, so half of that is left
+		aimd.throttled_at(at(1000));
+		aimd.throttled_at(at(1500));
+		assert_eq!(aimd.rate(), Some(2000.0));
+		assert_eq!(aimd.reserve(400, at(1000)), Duration::from_millis(200));
+
+		// one step back up per quiet second, until it is lifted
+		aimd.reserve(0, at(2000));
+		assert_eq!(aimd.rate(), Some(2200.0));
+		aimd.throttled_at(at(2100));
+		assert_eq!(aimd.rate(), Some(1100.0));
+		for s in 3..=16 {
+			aimd.reserve(0, at(s * 1000 + 100));
+		}
+		assert_eq!(aimd.rate(), Some(4000.0 - 100.0));
+		aimd.reserve(0, at(17100));
+		assert_eq!(aimd.rate(), None);
+
+		// before a full window the current one is used
+		let aimd = Aimd::default();
+		aimd.throttled_at(at(0));
+		assert_eq!(aimd.rate(), None);
+		aimd.reserve(3000, at(0));
+		aimd.throttled_at(at(500));
+		assert_eq!(aimd.rate(), Some(3000.0));
+	}
+}
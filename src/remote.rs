@@ -0,0 +1,145 @@
+use std::{
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use image::DynamicImage;
+use tokio::{
+	io::{self, AsyncBufReadExt, AsyncWriteExt},
+	sync::mpsc,
+	task,
+};
+use tracing as log;
+
+
+/// A new image to show in place of the source, blended in over `fade`
+#[derive(Debug)]
+pub struct Swap
+{
+	pub image: DynamicImage,
+	pub fade: Option<Duration>,
+}
+
+/// Parses `swap-image PATH [--fade DURATION]`
+fn parse(line: &str) -> Result<(PathBuf, Option<Duration>), String>
+{
+	let args = line.trim().strip_prefix("swap-image ")
+		.ok_or_else(|| format!("unknown command '{}'", line.trim()))?;
+	let (path, fade) = match args.rsplit_once(" --fade ") {
+		Some((path, fade)) => {
+			let fade = humantime::parse_duration(fade.trim())
+				.map_err(|err| format!("invalid duration '{}': {}", fade.trim(), err))?;
+			(path, Some(fade))
+		},
+		None => (args, None),
+	};
+	let path = path.trim();
+	if path.is_empty() {
+		return Err("no image given".to_owned());
+	}
+	Ok((PathBuf::from(path), fade))
+}
+
+/// Answers the commands of one connection, one per line, with `ok` or `error: ...`
+async fn serve<S>(stream: S, swaps: &mpsc::Sender<Swap>) -> io::Result<()>
+	where S: io::AsyncRead + io::AsyncWrite
+{
+	let (rd, mut wr) = io::split(stream);
+	let mut lines = io::BufReader::new(rd).lines();
+	while let Some(line) = lines.next_line().await? {
+		if line.trim().is_empty() {
+			continue;
+		}
+		let swap = async {
+			let (path, fade) = parse(&line)?;
+			// encoded in the background, the old image is sent until then
+			let image = task::spawn_blocking(move || image::open(&path).map_err(|err| format!("{}: {}", path.display(), err)))
+				.await
+				.map_err(|err| err.to_string())??;
+			Ok::<_, String>(Swap { image, fade })
+		}.await;
+		let reply = match swap {
+			Ok(swap) => {
+				log::info!("swapping the image{}", swap.fade.map(|fade| format!(" over {:?}", fade)).unwrap_or_default());
+				match swaps.send(swap).await {
+					Ok(()) => "ok\n".to_owned(),
+					Err(_) => "error: not painting anymore\n".to_owned(),
+				}
+			},
+			Err(err) => format!("error: {}\n", err),
+		};
+		wr.write_all(reply.as_bytes()).await?;
+	}
+	Ok(())
+}
+
+/// Takes commands from connections to a Unix socket at `path`, which is replaced if it exists
+#[cfg(unix)]
+pub async fn run(path: &Path, swaps: mpsc::Sender<Swap>) -> anyhow::Result<()>
+{
+	use anyhow::Context;
+
+	if path.exists() {
+		std::fs::remove_file(path)
+			.with_context(|| format!("failed to remove {}", path.display()))?;
+	}
+	let listener = tokio::net::UnixListener::bind(path)
+		.with_context(|| format!("failed to bind {}", path.display()))?;
+	log::info!("control socket listening on {}", path.display());
+	loop {
+		let (stream, _) = listener.accept().await?;
+		let swaps = swaps.clone();
+		tokio::spawn(async move {
+			if let Err(err) = serve(stream, &swaps).await {
+				log::debug!("control socket connection failed: {}", err);
+			}
+		});
+	}
+}
+
+#[cfg(not(unix))]
+pub async fn run(_path: &Path, _swaps: mpsc::Sender<Swap>) -> anyhow::Result<()>
+{
+	anyhow::bail!("control sockets need a Unix system")
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn commands()
+	{
+		assert_eq!(parse("swap-image /tmp/a b.png\n"), Ok((PathBuf::from("/tmp/a b.png"), None)));
+		assert_eq!(parse("swap-image x.png --fade 1s"), Ok((PathBuf::from("x.png"), Some(Duration::from_secs(1)))));
+		assert!(parse("swap-image x.png --fade soon").is_err());
+		assert!(parse("swap-image  ").is_err());
+		assert!(parse("rotate").is_err());
+	}
+
+	#[tokio::test]
+	async fn swap()
+	{
+		let path = std::env::temp_dir().join(format!("pixelspray-remote-{}.png", std::process::id()));
+		image::RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 0xff])).save(&path).unwrap();
+
+		let (tx, mut rx) = mpsc::channel(1);
+		let (client, server) = io::duplex(256);
+		let server = tokio::spawn(async move { serve(server, &tx).await });
+		let (rd, mut wr) = io::split(client);
+		let mut replies = io::BufReader::new(rd).lines();
+		wr.write_all(format!("swap-image {} --fade 500ms\nswap-image /nonexistent.png\n", path.display()).as_bytes()).await.unwrap();
+
+		assert_eq!(replies.next_line().await.unwrap().unwrap(), "ok");
+		let swap = rx.recv().await.unwrap();
+		assert_eq!(swap.image.to_rgba8().get_pixel(1, 1).0, [1, 2, 3, 0xff]);
+		assert_eq!(swap.fade, Some(Duration::from_millis(500)));
+		assert!(replies.next_line().await.unwrap().unwrap().starts_with("error: /nonexistent.png"));
+
+		drop((wr, replies));
+		server.await.unwrap().unwrap();
+		std::fs::remove_file(&path).unwrap();
+	}
+}
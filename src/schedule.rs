@@ -0,0 +1,89 @@
+use std::{
+	collections::HashMap,
+	time::Duration,
+};
+
+
+/// Weighted round-robin over connections, favoring those with low latency
+#[derive(Debug, Default)]
+pub struct Scheduler
+{
+	/// Smoothed round trip time per connection in seconds
+	rtt: HashMap<usize, f64>,
+	current: HashMap<usize, f64>,
+}
+
+impl Scheduler
+{
+	/// Records a round trip time measurement of a connection
+	pub fn report(&mut self, id: usize, rtt: Duration)
+	{
+		let rtt = rtt.as_secs_f64().max(1e-4);
+		self.rtt.entry(id)
+			.and_modify(|avg| *avg = 0.75 * *avg + 0.25 * rtt)
+			.or_insert(rtt);
+	}
+
+	pub fn forget(&mut self, id: usize)
+	{
+		self.rtt.remove(&id);
+		self.current.remove(&id);
+	}
+
+	/// Returns which connection gets the next chunk for a round of `ids.len()` chunks
+	///
+	/// Connections without measurements are treated as average.
+	pub fn round(&mut self, ids: &[usize]) -> Vec<usize>
+	{
+		if self.rtt.is_empty() {
+			return ids.to_vec();
+		}
+		let avg = self.rtt.values().sum::<f64>() / self.rtt.len() as f64;
+		let weights = ids.iter()
+			.map(|id| (*id, 1.0 / self.rtt.get(id).copied().unwrap_or(avg)))
+			.collect::<Vec<_>>();
+		let total = weights.iter().map(|(_, w)| w).sum::<f64>();
+
+		// smooth weighted round-robin: the connection with the most credit
+		// gets the chunk and pays for it with the total weight
+		(0..ids.len())
+			.map(|_| {
+				for (id, weight) in weights.iter() {
+					*self.current.entry(*id).or_default() += weight;
+				}
+				let (id, _) = weights.iter()
+					.map(|(id, _)| (*id, self.current[id]))
+					.max_by(|a, b| a.1.total_cmp(&b.1))
+					.unwrap();
+				*self.current.get_mut(&id).unwrap() -= total;
+				id
+			})
+			.collect()
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn unmeasured_is_round_robin()
+	{
+		let mut sched = Scheduler::default();
+		assert_eq!(sched.round(&[0, 1, 2]), [0, 1, 2]);
+	}
+
+	#[test]
+	fn favors_low_latency()
+	{
+		let mut sched = Scheduler::default();
+		sched.report(0, Duration::from_millis(10));
+		sched.report(1, Duration::from_millis(30));
+
+		let picks = (0..10).flat_map(|_| sched.round(&[0, 1])).collect::<Vec<_>>();
+		assert_eq!(picks.iter().filter(|&&id| id == 0).count(), 15);
+		assert_eq!(picks.iter().filter(|&&id| id == 1).count(), 5);
+	}
+}
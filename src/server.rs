@@ -0,0 +1,266 @@
+use std::{
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::{
+		atomic::{AtomicU64, AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use anyhow::Context;
+use futures::future::FutureExt;
+use image::{Rgba, RgbaImage};
+use tokio::{
+	io::{self, AsyncBufReadExt, AsyncWriteExt},
+	net,
+	signal,
+	task,
+	time,
+};
+use tracing as log;
+
+
+/// Minimal pixelflut server recording received pixels into a framebuffer
+pub struct Server
+{
+	listener: net::TcpListener,
+	state: Arc<State>,
+}
+
+/// Framebuffer and counters shared by all connections
+///
+/// Translucent pixels are blended over the canvas, like most servers do.
+pub struct State
+{
+	pub canvas: Mutex<RgbaImage>,
+	pub pixels: AtomicU64,
+	pub connections: AtomicUsize,
+}
+
+impl Server
+{
+	pub async fn bind(addr: SocketAddr, size: (u32, u32)) -> io::Result<Self>
+	{
+		let listener = net::TcpListener::bind(addr).await?;
+		Ok(Self {
+			listener,
+			state: Arc::new(State::new(size)),
+		})
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr>
+	{
+		self.listener.local_addr()
+	}
+
+	pub fn state(&self) -> Arc<State>
+	{
+		self.state.clone()
+	}
+
+	/// Accepts connections until an error occurs
+	pub async fn run(self) -> io::Result<()>
+	{
+		loop {
+			let (stream, peer) = self.listener.accept().await?;
+			let state = self.state.clone();
+			state.connections.fetch_add(1, Ordering::Relaxed);
+			tokio::spawn(async move {
+				if let Err(err) = handle(stream, &state).await {
+					log::debug!("server: {}: {}", peer, err);
+				}
+			});
+		}
+	}
+}
+
+/// Runs a server until interrupted, saving snapshots of the canvas every `interval`
+pub async fn serve(addr: SocketAddr, size: (u32, u32), snapshot: Option<PathBuf>, interval: time::Duration) -> anyhow::Result<()>
+{
+	let server = Server::bind(addr, size).await
+		.with_context(|| format!("failed to listen on {}", addr))?;
+	log::info!("serving {}x{} canvas on {}", size.0, size.1, server.local_addr()?);
+
+	let state = server.state();
+	let mut server = tokio::spawn(server.run()).fuse();
+	let mut ticker = time::interval(interval);
+	ticker.tick().await;
+
+	let mut last = 0;
+	loop {
+		futures::select! {
+			_ = signal::ctrl_c().fuse() => break,
+			res = server => return res?.context("server failed"),
+			_ = ticker.tick().fuse() => {
+				let pixels = state.pixels.load(Ordering::Relaxed);
+				log::info!("connections: {} pixels: {} ({:.0}/s)",
+					state.connections.load(Ordering::Relaxed), pixels,
+					(pixels - last) as f64 / interval.as_secs_f64());
+				last = pixels;
+
+				if let Some(path) = snapshot.as_ref() {
+					save(&state, path).await;
+				}
+			},
+		}
+	}
+
+	log::info!("stopping...");
+	if let Some(path) = snapshot.as_ref() {
+		save(&state, path).await;
+	}
+	Ok(())
+}
+
+async fn save(state: &State, path: &Path)
+{
+	let canvas = state.canvas.lock().unwrap().clone();
+	let path = path.to_owned();
+	let res = task::spawn_blocking(move || canvas.save(&path).map(|_| path)).await;
+	match res {
+		Ok(Ok(path)) => log::debug!("saved snapshot to {}", path.display()),
+		Ok(Err(err)) => log::warn!("failed to save snapshot: {}", err),
+		Err(err) => log::warn!("failed to save snapshot: {}", err),
+	}
+}
+
+async fn handle(stream: net::TcpStream, state: &State) -> io::Result<()>
+{
+	let (rd, mut wr) = stream.into_split();
+	let mut lines = io::BufReader::new(rd).lines();
+	let mut offset = (0, 0);
+	while let Some(line) = lines.next_line().await? {
+		if let Some(reply) = state.command(&line, &mut offset) {
+			wr.write_all(reply.as_bytes()).await?;
+		}
+	}
+	Ok(())
+}
+
+impl State
+{
+	pub fn new((w, h): (u32, u32)) -> Self
+	{
+		Self {
+			canvas: Mutex::new(RgbaImage::new(w, h)),
+			pixels: AtomicU64::new(0),
+			connections: AtomicUsize::new(0),
+		}
+	}
+
+	/// Executes a single command, returning the reply if there is one
+	pub fn command(&self, line: &str, offset: &mut (u32, u32)) -> Option<String>
+	{
+		let mut args = line.split_ascii_whitespace();
+		match args.next()? {
+			"SIZE" => {
+				let (w, h) = self.canvas.lock().unwrap().dimensions();
+				Some(format!("SIZE {} {}\n", w, h))
+			},
+			"HELP" => Some("HELP pixelspray mock server: SIZE, OFFSET x y, PX x y [GG|RRGGBB|RRGGBBAA]\n".to_owned()),
+			"OFFSET" => {
+				let x = u32::from_str(args.next()?).ok()?;
+				let y = u32::from_str(args.next()?).ok()?;
+				*offset = (x, y);
+				None
+			},
+			"PX" => {
+				let (x, y) = (args.next()?, args.next()?);
+				let cx = u32::from_str(x).ok()?.checked_add(offset.0)?;
+				let cy = u32::from_str(y).ok()?.checked_add(offset.1)?;
+
+				let mut canvas = self.canvas.lock().unwrap();
+				if cx >= canvas.width() || cy >= canvas.height() {
+					return None;
+				}
+				match args.next() {
+					None => {
+						let [r,g,b,_a] = canvas.get_pixel(cx, cy).0;
+						Some(format!("PX {} {} {:02X}{:02X}{:02X}\n", x, y, r, g, b))
+					},
+					Some(color) => {
+						let under = *canvas.get_pixel(cx, cy);
+						canvas.put_pixel(cx, cy, blend(under, parse_color(color)?));
+						self.pixels.fetch_add(1, Ordering::Relaxed);
+						None
+					},
+				}
+			},
+			_ => None,
+		}
+	}
+}
+
+/// `over` on top of `under` by its alpha, leaving the pixel opaque
+fn blend(under: Rgba<u8>, over: Rgba<u8>) -> Rgba<u8>
+{
+	let a = over.0[3] as u32;
+	let mix = |i: usize| ((under.0[i] as u32 * (255 - a) + over.0[i] as u32 * a + 127) / 255) as u8;
+	Rgba([mix(0), mix(1), mix(2), 0xff])
+}
+
+/// Parses a color in the grey, RGB or RGBA form
+pub fn parse_color(s: &str) -> Option<Rgba<u8>>
+{
+	let v = u32::from_str_radix(s, 16).ok()?;
+	match s.len() {
+		2 => Some(Rgba([v as u8, v as u8, v as u8, 0xff])),
+		6 => Some(Rgba([(v >> 16) as u8, (v >> 8) as u8, v as u8, 0xff])),
+		8 => Some(Rgba(v.to_be_bytes())),
+		_ => None,
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn size()
+	{
+		let state = State::new((32, 16));
+		assert_eq!(state.command("SIZE", &mut (0, 0)).as_deref(), Some("SIZE 32 16\n"));
+	}
+
+	#[test]
+	fn colors()
+	{
+		let state = State::new((4, 4));
+		let mut offset = (0, 0);
+		state.command("PX 0 0 7F", &mut offset);
+		state.command("PX 1 0 102030", &mut offset);
+		state.command("PX 2 0 10203040", &mut offset);
+		state.command("PX 3 0 XYZ", &mut offset);
+
+		let canvas = state.canvas.lock().unwrap();
+		assert_eq!(canvas.get_pixel(0, 0), &Rgba([0x7f, 0x7f, 0x7f, 0xff]));
+		assert_eq!(canvas.get_pixel(1, 0), &Rgba([0x10, 0x20, 0x30, 0xff]));
+		assert_eq!(canvas.get_pixel(2, 0), &Rgba([0x04, 0x08, 0x0c, 0xff]));
+		assert_eq!(canvas.get_pixel(3, 0), &Rgba([0, 0, 0, 0]));
+		assert_eq!(state.pixels.load(Ordering::Relaxed), 3);
+		drop(canvas);
+
+		state.command("PX 1 0 FFFFFF80", &mut offset);
+		state.command("PX 0 0 00000000", &mut offset);
+		let canvas = state.canvas.lock().unwrap();
+		assert_eq!(canvas.get_pixel(1, 0), &Rgba([0x88, 0x90, 0x98, 0xff]));
+		assert_eq!(canvas.get_pixel(0, 0), &Rgba([0x7f, 0x7f, 0x7f, 0xff]));
+	}
+
+	#[test]
+	fn offset_and_read_back()
+	{
+		let state = State::new((8, 8));
+		let mut offset = (0, 0);
+		state.command("OFFSET 4 2", &mut offset);
+		state.command("PX 1 1 ABCDEF", &mut offset);
+		state.command("PX 7 7 ABCDEF", &mut offset);
+
+		assert_eq!(state.canvas.lock().unwrap().get_pixel(5, 3), &Rgba([0xab, 0xcd, 0xef, 0xff]));
+		assert_eq!(state.command("PX 1 1", &mut offset).as_deref(), Some("PX 1 1 ABCDEF\n"));
+		assert_eq!(state.pixels.load(Ordering::Relaxed), 1);
+	}
+}
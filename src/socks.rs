@@ -0,0 +1,138 @@
+use std::{
+	net::SocketAddr,
+	sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::Context;
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net,
+};
+
+
+/// Connections made so far, to tell their credentials apart
+static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// A SOCKS5 proxy to connect through, like Tor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Proxy
+{
+	pub addr: SocketAddr,
+	/// Log in with new credentials on every connection, which Tor gives a circuit of its own
+	pub isolate: bool,
+}
+
+impl Proxy
+{
+	/// Connects to `target` through the proxy
+	pub async fn connect(&self, target: SocketAddr) -> anyhow::Result<net::TcpStream>
+	{
+		let mut stream = net::TcpStream::connect(self.addr).await
+			.with_context(|| format!("failed to connect to proxy {}", self.addr))?;
+		let auth = self.isolate.then(|| {
+			let n = CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+			(format!("pixelspray-{}-{}", std::process::id(), n), "x".to_owned())
+		});
+		handshake(&mut stream, target, auth.as_ref().map(|(user, pass)| (user.as_str(), pass.as_str()))).await
+			.context("SOCKS handshake failed")?;
+		Ok(stream)
+	}
+}
+
+async fn handshake(stream: &mut net::TcpStream, target: SocketAddr, auth: Option<(&str, &str)>) -> anyhow::Result<()>
+{
+	// only the chosen method is offered, so the proxy can not skip the login
+	let method = if auth.is_some() { 2 } else { 0 };
+	stream.write_all(&[5, 1, method]).await?;
+	let mut reply = [0; 2];
+	stream.read_exact(&mut reply).await?;
+	anyhow::ensure!(reply == [5, method], "authentication method {} refused", method);
+
+	if let Some((user, pass)) = auth {
+		let mut login = vec![1, user.len() as u8];
+		login.extend(user.as_bytes());
+		login.push(pass.len() as u8);
+		login.extend(pass.as_bytes());
+		stream.write_all(&login).await?;
+		stream.read_exact(&mut reply).await?;
+		anyhow::ensure!(reply[1] == 0, "login refused");
+	}
+
+	let mut request = vec![5, 1, 0];
+	match target {
+		SocketAddr::V4(addr) => {
+			request.push(1);
+			request.extend(addr.ip().octets());
+		},
+		SocketAddr::V6(addr) => {
+			request.push(4);
+			request.extend(addr.ip().octets());
+		},
+	}
+	request.extend(target.port().to_be_bytes());
+	stream.write_all(&request).await?;
+
+	let mut head = [0; 4];
+	stream.read_exact(&mut head).await?;
+	anyhow::ensure!(head[1] == 0, "connect refused with code {}", head[1]);
+	// the bound address is of no interest
+	let len = match head[3] {
+		1 => 4,
+		4 => 16,
+		3 => stream.read_u8().await? as usize,
+		atyp => anyhow::bail!("unknown address type {}", atyp),
+	};
+	let mut bound = vec![0; len + 2];
+	stream.read_exact(&mut bound).await?;
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	/// Accepts one login and connect request, answering with what it got
+	async fn proxy(listener: net::TcpListener) -> Vec<u8>
+	{
+		let (mut stream, _) = listener.accept().await.unwrap();
+		let mut greeting = [0; 3];
+		stream.read_exact(&mut greeting).await.unwrap();
+		stream.write_all(&[5, greeting[2]]).await.unwrap();
+		let mut user = Vec::new();
+		if greeting[2] == 2 {
+			let mut len = [0; 2];
+			stream.read_exact(&mut len).await.unwrap();
+			user = vec![0; len[1] as usize];
+			stream.read_exact(&mut user).await.unwrap();
+			let mut pass = vec![0; stream.read_u8().await.unwrap() as usize];
+			stream.read_exact(&mut pass).await.unwrap();
+			stream.write_all(&[1, 0]).await.unwrap();
+		}
+		let mut request = [0; 10];
+		stream.read_exact(&mut request).await.unwrap();
+		assert_eq!(request[..4], [5, 1, 0, 1]);
+		stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await.unwrap();
+		stream.write_all(&request[4..]).await.unwrap();
+		user
+	}
+
+	#[tokio::test]
+	async fn circuits()
+	{
+		let target = "10.1.2.3:1337".parse().unwrap();
+		for isolate in [false, true, true] {
+			let listener = net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+			let addr = listener.local_addr().unwrap();
+			let server = tokio::spawn(proxy(listener));
+
+			let mut stream = Proxy { addr, isolate }.connect(target).await.unwrap();
+			let mut to = [0; 6];
+			stream.read_exact(&mut to).await.unwrap();
+			assert_eq!(to, [10, 1, 2, 3, 0x05, 0x39]);
+			let user = String::from_utf8(server.await.unwrap()).unwrap();
+			assert_eq!(user.starts_with("pixelspray-"), isolate);
+		}
+	}
+}
@@ -0,0 +1,829 @@
+use std::{
+	collections::hash_map::DefaultHasher,
+	hash::Hasher,
+	path::{Path, PathBuf},
+	time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use futures::future::{BoxFuture, FutureExt};
+use tracing as log;
+use image::{DynamicImage, RgbaImage};
+use reqwest::header;
+use tokio::{
+	sync::mpsc,
+	task,
+	time,
+};
+
+use crate::{capture, clock, cycle, Opt};
+
+
+/// Where the images to spray come from
+pub trait Source: Send
+{
+	/// Feeds images into `tx` until the source is exhausted or the receiver is gone
+	///
+	/// Static sources are reloaded every `refresh` if set, live sources are
+	/// limited to `max_fps` frames per second and drop frames while `tx` is full.
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, refresh: Option<Duration>, max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>;
+}
+
+/// Opens the image source chosen by the options
+pub fn open(opt: &Opt) -> anyhow::Result<Box<dyn Source>>
+{
+	if opt.clock {
+		return Ok(Box::new(clock::Clock::new(clock::Mode::Time)));
+	}
+	if let Some(end) = opt.countdown {
+		return Ok(Box::new(clock::Clock::new(clock::Mode::Countdown(end))));
+	}
+	let location = opt.source.as_ref().or(opt.image.as_ref())
+		.context("no image given")?;
+	Ok(match opt.slideshow {
+		Some(interval) => Box::new(Slideshow::new(location.into(), interval.into())),
+		None if !opt.palette_cycle.is_empty() => Box::new(cycle::Cycle::open(location.as_ref(), opt.palette_cycle.clone())?),
+		None => locate(location)?,
+	})
+}
+
+/// Picks the source by the scheme of `location`, defaulting to a file path
+pub fn locate(location: &str) -> anyhow::Result<Box<dyn Source>>
+{
+	Ok(if let Some(raw) = location.strip_prefix("raw:") {
+		Box::new(Raw::parse(raw)
+			.with_context(|| format!("invalid raw source '{}'", location))?)
+	} else if let Some(path) = location.strip_prefix("fifo:") {
+		Box::new(Fifo { path: path.into() })
+	} else if let Some(backend) = location.strip_prefix("screen:") {
+		let backend = match backend {
+			"" | "auto" => None,
+			backend => Some(capture::Backend::from_str(backend, true)
+				.map_err(|err| anyhow::anyhow!("invalid screen source '{}': {}", location, err))?),
+		};
+		Box::new(Screen { backend })
+	} else if let Some(ffmpeg) = Ffmpeg::parse(location) {
+		Box::new(ffmpeg)
+	} else if let Some(stream) = location.strip_prefix("mjpeg://") {
+		Box::new(Mjpeg {
+			client: reqwest::Client::new(),
+			url: format!("http://{}", stream),
+		})
+	} else if location.starts_with("http://") || location.starts_with("https://") {
+		Box::new(Still::Http {
+			client: reqwest::Client::new(),
+			url: location.to_owned(),
+			etag: None,
+		})
+	} else {
+		Box::new(Still::File {
+			path: location.into(),
+			modified: None,
+		})
+	})
+}
+
+/// A single image, reloaded when it changes
+#[derive(Debug)]
+pub enum Still
+{
+	File {
+		path: PathBuf,
+		modified: Option<SystemTime>,
+	},
+	Http {
+		client: reqwest::Client,
+		url: String,
+		etag: Option<header::HeaderValue>,
+	},
+}
+
+impl Source for Still
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, refresh: Option<Duration>, _max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		self.reload(tx, refresh).boxed()
+	}
+}
+
+impl Still
+{
+	async fn reload(mut self: Box<Self>, tx: mpsc::Sender<DynamicImage>, refresh: Option<Duration>) -> anyhow::Result<()>
+	{
+		let mut first = true;
+		loop {
+			match self.load().await {
+				Ok(Some(image)) => {
+					if tx.send(image).await.is_err() {
+						return Ok(());
+					}
+				},
+				Ok(None) => {},
+				Err(err) if first => return Err(err),
+				Err(err) => log::warn!("failed to refresh image: {:#}", err),
+			}
+			first = false;
+
+			match refresh {
+				Some(refresh) => time::sleep(refresh).await,
+				None => return Ok(()),
+			}
+		}
+	}
+
+	/// Loads the image, or returns `None` if it did not change since the last load
+	async fn load(&mut self) -> anyhow::Result<Option<DynamicImage>>
+	{
+		match self {
+			Still::File { path, modified } => {
+				let mtime = std::fs::metadata(&path)
+					.and_then(|meta| meta.modified())
+					.ok();
+				if mtime.is_some() && mtime == *modified {
+					return Ok(None);
+				}
+				let image = image::open(&path)
+					.with_context(|| format!("failed to open {}", path.display()))?;
+				*modified = mtime;
+				Ok(Some(image))
+			},
+			Still::Http { client, url, etag } => {
+				let mut req = client.get(url.as_str());
+				if let Some(etag) = etag.as_ref() {
+					req = req.header(header::IF_NONE_MATCH, etag.clone());
+				}
+				let res = req.send().await
+					.and_then(|res| res.error_for_status())
+					.with_context(|| format!("failed to fetch {}", url))?;
+
+				if res.status() == reqwest::StatusCode::NOT_MODIFIED {
+					return Ok(None);
+				}
+				let new_etag = res.headers().get(header::ETAG).cloned();
+				let body = res.bytes().await
+					.context("failed to download image")?;
+				let image = image::load_from_memory(&body)
+					.context("failed to decode image")?;
+				*etag = new_etag;
+				Ok(Some(image))
+			},
+		}
+	}
+}
+
+/// The images of a directory in name order, one after the other, looping
+#[derive(Debug)]
+pub struct Slideshow
+{
+	dir: PathBuf,
+	interval: Duration,
+}
+
+impl Slideshow
+{
+	pub fn new(dir: PathBuf, interval: Duration) -> Self
+	{
+		Self { dir, interval }
+	}
+
+	fn slides(&self) -> anyhow::Result<Vec<PathBuf>>
+	{
+		let mut slides = std::fs::read_dir(&self.dir)
+			.with_context(|| format!("failed to read {}", self.dir.display()))?
+			.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+			.filter(|path| is_image(path))
+			.collect::<Vec<_>>();
+		slides.sort();
+		anyhow::ensure!(!slides.is_empty(), "no images in {}", self.dir.display());
+		Ok(slides)
+	}
+}
+
+impl Source for Slideshow
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, _refresh: Option<Duration>, _max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		async move {
+			// the directory is listed again every round, so slides can be added and removed
+			loop {
+				for path in self.slides()? {
+					let image = task::spawn_blocking({
+						let path = path.clone();
+						move || image::open(path)
+					}).await?;
+					match image {
+						Ok(image) => {
+							log::info!("showing {}", path.display());
+							if tx.send(image).await.is_err() {
+								return Ok(());
+							}
+							time::sleep(self.interval).await;
+						},
+						Err(err) => log::warn!("failed to open {}: {}", path.display(), err),
+					}
+				}
+			}
+		}.boxed()
+	}
+}
+
+/// How often an unchanged frame of a live source is passed on anyway
+const KEEPALIVE: Duration = Duration::from_secs(10);
+
+/// Tells new frames of live sources from repeated ones by a hash of their data
+#[derive(Debug, Default)]
+struct Dedup
+{
+	hash: Option<u64>,
+	passed: Option<Instant>,
+}
+
+impl Dedup
+{
+	/// Whether a frame of `data` is to be decoded and passed on at `now`
+	fn pass(&mut self, data: &[u8], now: Instant) -> bool
+	{
+		let mut hasher = DefaultHasher::new();
+		hasher.write(data);
+		let hash = hasher.finish();
+		if self.hash == Some(hash) && self.passed.is_some_and(|at| now.duration_since(at) < KEEPALIVE) {
+			return false;
+		}
+		self.hash = Some(hash);
+		self.passed = Some(now);
+		true
+	}
+}
+
+fn is_image(path: &Path) -> bool
+{
+	path.is_file() && image::ImageFormat::from_path(path).is_ok()
+}
+
+/// Pixel layout of raw framebuffer data
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum RawFormat
+{
+	Bgra,
+	Rgba,
+	/// BGRA with the alpha byte unused, as in most framebuffers
+	Bgrx,
+	Rgbx,
+	Rgb,
+	Bgr,
+	/// 16 bit little endian, 5 bits red, 6 green and 5 blue
+	Rgb565,
+}
+
+impl RawFormat
+{
+	fn bytes_per_pixel(self) -> usize
+	{
+		match self {
+			RawFormat::Bgra | RawFormat::Rgba | RawFormat::Bgrx | RawFormat::Rgbx => 4,
+			RawFormat::Rgb | RawFormat::Bgr => 3,
+			RawFormat::Rgb565 => 2,
+		}
+	}
+
+	fn rgba(self, px: &[u8]) -> [u8; 4]
+	{
+		match self {
+			RawFormat::Bgra => [px[2], px[1], px[0], px[3]],
+			RawFormat::Rgba => [px[0], px[1], px[2], px[3]],
+			RawFormat::Bgrx => [px[2], px[1], px[0], 0xff],
+			RawFormat::Rgbx | RawFormat::Rgb => [px[0], px[1], px[2], 0xff],
+			RawFormat::Bgr => [px[2], px[1], px[0], 0xff],
+			RawFormat::Rgb565 => {
+				let v = u16::from_le_bytes([px[0], px[1]]);
+				let (r, g, b) = ((v >> 11) as u8, (v >> 5) as u8 & 0x3f, v as u8 & 0x1f);
+				[r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 0xff]
+			},
+		}
+	}
+}
+
+/// Uncompressed pixels in a file or device like `/dev/fb0`, read again and again
+///
+/// Memory mapped files and framebuffers are read through the page cache,
+/// so updates show up on the next read.
+#[derive(Debug)]
+pub struct Raw
+{
+	path: PathBuf,
+	size: (u32, u32),
+	format: RawFormat,
+}
+
+/// Frame rate of raw sources without `--max-fps`
+const RAW_FPS: f32 = 10.0;
+/// Longest side of a raw image, larger ones are most likely typos
+const RAW_MAX_SIDE: u32 = 1 << 15;
+
+impl Raw
+{
+	/// Parses `path:WxH:format`
+	fn parse(s: &str) -> anyhow::Result<Self>
+	{
+		let mut parts = s.rsplitn(3, ':');
+		let (Some(format), Some(size), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+			anyhow::bail!("expected raw:path:WxH:format");
+		};
+		let format = RawFormat::from_str(format, true)
+			.map_err(|err| anyhow::anyhow!("invalid format: {}", err))?;
+		let (w, h) = size.split_once('x')
+			.and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+			.with_context(|| format!("expected WxH, got '{}'", size))?;
+		anyhow::ensure!((1..=RAW_MAX_SIDE).contains(&w) && (1..=RAW_MAX_SIDE).contains(&h), "size {}x{} is not within 1x1 and {}x{}", w, h, RAW_MAX_SIDE, RAW_MAX_SIDE);
+		Ok(Self { path: path.into(), size: (w, h), format })
+	}
+
+	fn read(&self) -> anyhow::Result<Vec<u8>>
+	{
+		use std::io::Read;
+
+		let len = (self.size.0 as usize).checked_mul(self.size.1 as usize)
+			.and_then(|pixels| pixels.checked_mul(self.format.bytes_per_pixel()))
+			.with_context(|| format!("{}x{} is too large", self.size.0, self.size.1))?;
+		let mut buf = vec![0; len];
+		std::fs::File::open(&self.path)
+			.and_then(|mut file| file.read_exact(&mut buf))
+			.with_context(|| format!("failed to read {} bytes from {}", len, self.path.display()))?;
+		Ok(buf)
+	}
+}
+
+/// Converts tightly packed pixels of `format` into an image
+fn decode(format: RawFormat, (w, h): (u32, u32), data: &[u8]) -> RgbaImage
+{
+	let bpp = format.bytes_per_pixel();
+	let mut image = RgbaImage::new(w, h);
+	for (px, raw) in image.pixels_mut().zip(data.chunks_exact(bpp)) {
+		px.0 = format.rgba(raw);
+	}
+	image
+}
+
+impl Source for Raw
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, _refresh: Option<Duration>, max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		async move {
+			let mut ticker = time::interval(Duration::from_secs_f32(1.0 / max_fps.unwrap_or(RAW_FPS)));
+			ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+			let raw = std::sync::Arc::new(*self);
+			let mut dedup = Dedup::default();
+			loop {
+				ticker.tick().await;
+				let data = task::spawn_blocking({
+					let raw = raw.clone();
+					move || raw.read()
+				}).await?;
+				let data = match data {
+					Ok(data) => data,
+					Err(err) if dedup.hash.is_none() => return Err(err),
+					Err(err) => {
+						log::warn!("{:#}", err);
+						continue;
+					},
+				};
+				// unchanged screens are not encoded again
+				if !dedup.pass(&data, Instant::now()) {
+					continue;
+				}
+				let image = DynamicImage::ImageRgba8(decode(raw.format, raw.size, &data));
+				match tx.try_send(image) {
+					Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => {},
+					Err(mpsc::error::TrySendError::Closed(_)) => return Ok(()),
+				}
+			}
+		}.boxed()
+	}
+}
+
+/// The screen of the X11 or Wayland session, captured again and again
+#[derive(Debug)]
+pub struct Screen
+{
+	/// Detected from the environment if not given
+	backend: Option<capture::Backend>,
+}
+
+impl Source for Screen
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, _refresh: Option<Duration>, max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		let interval = Duration::from_secs_f32(1.0 / max_fps.unwrap_or(RAW_FPS));
+		// the display server is talked to in blocking calls
+		let (res_tx, res) = tokio::sync::oneshot::channel();
+		std::thread::spawn(move || res_tx.send(self.capture(tx, interval)));
+		async move {
+			res.await?
+		}.boxed()
+	}
+}
+
+impl Screen
+{
+	fn capture(&self, tx: mpsc::Sender<DynamicImage>, interval: Duration) -> anyhow::Result<()>
+	{
+		let mut capture = capture::open(self.backend)
+			.context("failed to capture the screen")?;
+		let mut dedup = Dedup::default();
+		loop {
+			let start = Instant::now();
+			if tx.is_closed() {
+				return Ok(());
+			}
+			let frame = capture.grab()?;
+			if dedup.pass(&frame.data, start) {
+				let image = DynamicImage::ImageRgba8(decode(frame.format, frame.size, &frame.data));
+				match tx.try_send(image) {
+					Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => {},
+					Err(mpsc::error::TrySendError::Closed(_)) => return Ok(()),
+				}
+			}
+			std::thread::sleep(interval.saturating_sub(start.elapsed()));
+		}
+	}
+}
+
+/// Encoded frames written to a named pipe, each prefixed with its length as 32 bit big endian
+///
+/// Cheap formats like QOI, farbfeld, PPM or BMP spare fast writers the cost of PNG.
+/// Writers may come and go, the pipe is opened again after each one.
+#[derive(Debug)]
+pub struct Fifo
+{
+	path: PathBuf,
+}
+
+/// Longest frame accepted from a pipe
+const FIFO_MAX_FRAME: usize = 256 << 20;
+
+impl Source for Fifo
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, _refresh: Option<Duration>, max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		let interval = max_fps.map(|fps| Duration::from_secs_f32(1.0 / fps));
+		// a thread of its own, as opening and reading block without end and would hold up the runtime shutdown
+		let (res_tx, res) = tokio::sync::oneshot::channel();
+		std::thread::spawn(move || res_tx.send(self.read(tx, interval)));
+		async move {
+			res.await?
+		}.boxed()
+	}
+}
+
+impl Fifo
+{
+	fn read(&self, tx: mpsc::Sender<DynamicImage>, interval: Option<Duration>) -> anyhow::Result<()>
+	{
+		let mut last: Option<Instant> = None;
+		let mut dedup = Dedup::default();
+		loop {
+			// blocks until a writer shows up
+			let file = std::fs::File::open(&self.path)
+				.with_context(|| format!("failed to open {}", self.path.display()))?;
+			log::info!("reading frames from {}...", self.path.display());
+			let mut file = std::io::BufReader::new(file);
+			while let Some(frame) = read_frame(&mut file)
+				.with_context(|| format!("failed to read {}", self.path.display()))?
+			{
+				if tx.is_closed() {
+					return Ok(());
+				}
+				if let (Some(last), Some(interval)) = (last, interval) {
+					if last.elapsed() < interval {
+						continue;
+					}
+				}
+				last = Some(Instant::now());
+				if !dedup.pass(&frame, Instant::now()) {
+					continue;
+				}
+
+				match image::load_from_memory(&frame) {
+					Ok(image) => match tx.try_send(image) {
+						Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => {},
+						Err(mpsc::error::TrySendError::Closed(_)) => return Ok(()),
+					},
+					Err(err) => log::warn!("failed to decode frame: {}", err),
+				}
+			}
+			log::debug!("writer of {} is gone", self.path.display());
+		}
+	}
+}
+
+/// Reads a length-prefixed frame, or `None` at the end
+fn read_frame(rd: &mut impl std::io::Read) -> std::io::Result<Option<Vec<u8>>>
+{
+	let mut len = [0; 4];
+	match rd.read_exact(&mut len) {
+		Ok(()) => {},
+		Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+		Err(err) => return Err(err),
+	}
+	let len = u32::from_be_bytes(len) as usize;
+	if len > FIFO_MAX_FRAME {
+		return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("frame of {} bytes is too large", len)));
+	}
+	let mut frame = vec![0; len];
+	rd.read_exact(&mut frame)?;
+	Ok(Some(frame))
+}
+
+/// Video streams decoded by an ffmpeg process, like RTMP from OBS or NDI
+#[derive(Debug, PartialEq)]
+pub struct Ffmpeg
+{
+	/// Arguments for the input
+	input: Vec<String>,
+}
+
+
+impl Ffmpeg
+{
+	/// Takes `rtmp://` streams to pull, `rtmp-listen://` to wait for a push from OBS and `ndi:` sources,
+	/// the latter only with an ffmpeg built with NDI support
+	fn parse(location: &str) -> Option<Self>
+	{
+		let input = if let Some(addr) = location.strip_prefix("rtmp-listen://") {
+			vec![ "-listen".into(), "1".into(), "-i".into(), format!("rtmp://{}", addr) ]
+		} else if location.starts_with("rtmp://") || location.starts_with("rtmps://") {
+			vec![ "-i".into(), location.into() ]
+		} else if let Some(name) = location.strip_prefix("ndi:") {
+			vec![ "-f".into(), "libndi_newtek".into(), "-i".into(), name.into() ]
+		} else {
+			return None;
+		};
+		Some(Self { input })
+	}
+
+	fn read(&self, tx: mpsc::Sender<DynamicImage>, max_fps: Option<f32>) -> anyhow::Result<()>
+	{
+		use std::process::{Command, Stdio};
+
+		let mut cmd = Command::new("ffmpeg");
+		cmd.args(["-loglevel", "error", "-nostdin"].iter())
+			.args(&self.input)
+			.arg("-an");
+		if let Some(fps) = max_fps {
+			cmd.arg("-r").arg(fps.to_string());
+		}
+		// uncompressed, as PNG would cost more to encode and decode than sending the bytes
+		cmd.args(["-f", "image2pipe", "-c:v", "ppm", "-"].iter())
+			.stdin(Stdio::null())
+			.stdout(Stdio::piped());
+		let mut child = cmd.spawn().context("failed to run ffmpeg")?;
+		log::info!("decoding {} with ffmpeg...", self.input.last().map(String::as_str).unwrap_or_default());
+
+		let mut out = std::io::BufReader::new(child.stdout.take().unwrap());
+		let mut dedup = Dedup::default();
+		let res = (|| -> anyhow::Result<()> {
+			while let Some(frame) = read_ppm(&mut out).context("failed to read from ffmpeg")? {
+				if tx.is_closed() {
+					break;
+				}
+				if !dedup.pass(&frame, Instant::now()) {
+					continue;
+				}
+				match image::load_from_memory_with_format(&frame, image::ImageFormat::Pnm) {
+					Ok(image) => match tx.try_send(image) {
+						Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => {},
+						Err(mpsc::error::TrySendError::Closed(_)) => break,
+					},
+					Err(err) => log::warn!("failed to decode frame: {}", err),
+				}
+			}
+			Ok(())
+		})();
+		child.kill().ok();
+		let status = child.wait()?;
+		res?;
+		if !status.success() && !tx.is_closed() {
+			anyhow::bail!("ffmpeg failed with {}", status);
+		}
+		Ok(())
+	}
+}
+
+impl Source for Ffmpeg
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, _refresh: Option<Duration>, max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		let (res_tx, res) = tokio::sync::oneshot::channel();
+		std::thread::spawn(move || res_tx.send(self.read(tx, max_fps)));
+		async move {
+			res.await?
+		}.boxed()
+	}
+}
+
+/// Reads the next of back to back binary PPMs with 8 bit channels, or `None` at the end
+fn read_ppm(rd: &mut impl std::io::Read) -> std::io::Result<Option<Vec<u8>>>
+{
+	let invalid = |msg| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+	let mut ppm = vec![0; 2];
+	match rd.read_exact(&mut ppm) {
+		Ok(()) => {},
+		Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+		Err(err) => return Err(err),
+	}
+	if ppm != b"P6" {
+		return Err(invalid("not a PPM"));
+	}
+	// width, height and maximum value, each after whitespace, and one more whitespace before the data
+	let mut fields = [0usize; 3];
+	for field in fields.iter_mut() {
+		let mut digits = false;
+		loop {
+			let mut b = [0];
+			rd.read_exact(&mut b)?;
+			ppm.push(b[0]);
+			match b[0] {
+				b'0'..=b'9' if *field < FIFO_MAX_FRAME => {
+					*field = *field * 10 + (b[0] - b'0') as usize;
+					digits = true;
+				},
+				b if b.is_ascii_whitespace() && !digits => {},
+				b if b.is_ascii_whitespace() => break,
+				_ => return Err(invalid("invalid PPM header")),
+			}
+		}
+	}
+	let [w, h, max] = fields;
+	if max > 0xff {
+		return Err(invalid("16 bit PPM"));
+	}
+	let len = w.checked_mul(h).and_then(|n| n.checked_mul(3))
+		.filter(|&len| len <= FIFO_MAX_FRAME)
+		.ok_or_else(|| invalid("frame is too large"))?;
+	let at = ppm.len();
+	ppm.resize(at + len, 0);
+	rd.read_exact(&mut ppm[at..])?;
+	Ok(Some(ppm))
+}
+
+/// Motion JPEG stream over HTTP
+#[derive(Debug)]
+pub struct Mjpeg
+{
+	client: reqwest::Client,
+	url: String,
+}
+
+impl Source for Mjpeg
+{
+	fn feed(self: Box<Self>, tx: mpsc::Sender<DynamicImage>, _refresh: Option<Duration>, max_fps: Option<f32>) -> BoxFuture<'static, anyhow::Result<()>>
+	{
+		let interval = max_fps.map(|fps| Duration::from_secs_f32(1.0 / fps));
+		async move { stream_mjpeg(self.client, &self.url, tx, interval).await }.boxed()
+	}
+}
+
+async fn stream_mjpeg(client: reqwest::Client, url: &str, tx: mpsc::Sender<DynamicImage>, interval: Option<Duration>) -> anyhow::Result<()>
+{
+	let mut res = client.get(url).send().await
+		.and_then(|res| res.error_for_status())
+		.with_context(|| format!("failed to open stream {}", url))?;
+
+	log::info!("streaming {}...", url);
+
+	let mut buf = Vec::new();
+	let mut last: Option<Instant> = None;
+	let mut dedup = Dedup::default();
+	while let Some(data) = res.chunk().await.context("failed to read stream")? {
+		buf.extend_from_slice(&data);
+
+		// frames are delimited by the JPEG start and end of image markers,
+		// the multipart headers in between are skipped
+		while let Some(start) = find(&buf, &[0xFF, 0xD8]) {
+			let end = match find(&buf[start..], &[0xFF, 0xD9]) {
+				Some(end) => start + end + 2,
+				None => {
+					buf.drain(..start);
+					break;
+				},
+			};
+			let frame = buf[start..end].to_vec();
+			buf.drain(..end);
+
+			if let (Some(last), Some(interval)) = (last, interval) {
+				if last.elapsed() < interval {
+					continue;
+				}
+			}
+			last = Some(Instant::now());
+			if !dedup.pass(&frame, Instant::now()) {
+				continue;
+			}
+
+			let image = task::spawn_blocking(move || image::load_from_memory_with_format(&frame, image::ImageFormat::Jpeg)).await?;
+			match image {
+				Ok(image) => match tx.try_send(image) {
+					Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => {},
+					Err(mpsc::error::TrySendError::Closed(_)) => return Ok(()),
+				},
+				Err(err) => log::warn!("failed to decode frame: {}", err),
+			}
+		}
+	}
+	log::info!("stream {} ended", url);
+	Ok(())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize>
+{
+	haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn duplicates()
+	{
+		let mut dedup = Dedup::default();
+		let start = Instant::now();
+		assert!(dedup.pass(b"frame", start));
+		assert!(!dedup.pass(b"frame", start + Duration::from_secs(1)));
+		assert!(dedup.pass(b"other", start + Duration::from_secs(2)));
+		assert!(!dedup.pass(b"other", start + Duration::from_secs(11)));
+		// kept alive once in a while
+		assert!(dedup.pass(b"other", start + Duration::from_secs(12)));
+	}
+
+	#[test]
+	fn ffmpeg()
+	{
+		assert_eq!(Ffmpeg::parse("rtmp-listen://0.0.0.0:1935/live").unwrap().input, ["-listen", "1", "-i", "rtmp://0.0.0.0:1935/live"]);
+		assert_eq!(Ffmpeg::parse("ndi:OBS (Program)").unwrap().input, ["-f", "libndi_newtek", "-i", "OBS (Program)"]);
+		assert_eq!(Ffmpeg::parse("mjpeg://camera"), None);
+
+		// as ffmpeg writes them
+		let stream = [&b"P6\n2 1\n255\n"[..], &[0x10, 0, 0, 0x10, 0, 0], b"P6 1 1 255\n", &[0x20, 0, 0]].concat();
+		let mut rd = &stream[..];
+		let first = read_ppm(&mut rd).unwrap().unwrap();
+		assert_eq!(image::load_from_memory(&first).unwrap().to_rgba8().get_pixel(1, 0).0, [0x10, 0, 0, 0xff]);
+		assert_eq!(first.len() + read_ppm(&mut rd).unwrap().unwrap().len(), stream.len());
+		assert!(read_ppm(&mut rd).unwrap().is_none());
+		assert!(read_ppm(&mut &stream[1..]).is_err());
+		assert!(read_ppm(&mut &b"P6\n99999 99999\n255\n"[..]).is_err());
+		assert!(read_ppm(&mut &b"P6\n1 1\n65535\n"[..]).is_err());
+	}
+
+	#[test]
+	fn raw()
+	{
+		let raw = Raw::parse("/dev/fb0:2x1:rgb565").unwrap();
+		assert_eq!((raw.path, raw.size, raw.format), (PathBuf::from("/dev/fb0"), (2, 1), RawFormat::Rgb565));
+		assert!(Raw::parse("/dev/fb0:2x1").is_err());
+		assert!(Raw::parse("/dev/fb0:2x1:yuv").is_err());
+		assert!(Raw::parse("/dev/fb0:0x1:rgb").is_err());
+		assert!(Raw::parse("/dev/fb0:65536x65536:rgba").is_err());
+		assert!(Raw::parse("/dev/fb0:32768x32768:rgba").is_ok());
+
+		// pure red and a mid green
+		let image = decode(RawFormat::Rgb565, (2, 1), &[0x00, 0xf8, 0x00, 0x04]);
+		assert_eq!(image.get_pixel(0, 0).0, [0xff, 0, 0, 0xff]);
+		assert_eq!(image.get_pixel(1, 0).0, [0, 0x82, 0, 0xff]);
+
+		let image = decode(RawFormat::Bgrx, (1, 1), &[1, 2, 3, 0]);
+		assert_eq!(image.get_pixel(0, 0).0, [3, 2, 1, 0xff]);
+	}
+
+	#[test]
+	fn cheap_formats()
+	{
+		let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 0x80])));
+		for format in [image::ImageFormat::Qoi, image::ImageFormat::Farbfeld, image::ImageFormat::Bmp] {
+			let mut frame = std::io::Cursor::new(Vec::new());
+			// farbfeld has 16 bits per channel
+			let image = match format {
+				image::ImageFormat::Farbfeld => DynamicImage::ImageRgba16(image.to_rgba16()),
+				_ => image.clone(),
+			};
+			image.write_to(&mut frame, format).unwrap();
+			// the formats are told apart by their content, as with frames from a pipe
+			let decoded = image::load_from_memory(frame.get_ref()).unwrap();
+			assert_eq!(decoded.to_rgba8().get_pixel(1, 1).0, [1, 2, 3, 0x80], "{:?}", format);
+		}
+	}
+
+	#[test]
+	fn frames()
+	{
+		let data = [&[0, 0, 0, 3][..], b"abc", &[0, 0, 0, 0], &[0, 0, 0, 5], b"de"].concat();
+		let mut rd = &data[..];
+		assert_eq!(read_frame(&mut rd).unwrap(), Some(b"abc".to_vec()));
+		assert_eq!(read_frame(&mut rd).unwrap(), Some(Vec::new()));
+		assert!(read_frame(&mut rd).is_err());
+		assert_eq!(read_frame(&mut &[][..]).unwrap(), None);
+	}
+}
@@ -0,0 +1,191 @@
+use std::{
+	fmt,
+	fs::File,
+	io::{self, Write},
+	ops::{Deref, Range},
+	path::Path,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
+};
+
+use anyhow::Context;
+
+
+/// Files created so far, to give them unique names
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// PX commands sent at once, kept on the heap or in a memory-mapped file
+#[derive(Clone)]
+pub struct Chunk(Repr);
+
+#[derive(Clone)]
+enum Repr
+{
+	Heap(Arc<String>),
+	Mapped(Arc<Mapping>, Range<usize>),
+}
+
+impl From<String> for Chunk
+{
+	fn from(s: String) -> Self
+	{
+		Chunk(Repr::Heap(Arc::new(s)))
+	}
+}
+
+impl Deref for Chunk
+{
+	type Target = str;
+
+	fn deref(&self) -> &str
+	{
+		match &self.0 {
+			Repr::Heap(heap) => heap,
+			// SAFETY: the mapping was written from whole `str`s in `spill` and never changes
+			Repr::Mapped(mapping, range) => unsafe { std::str::from_utf8_unchecked(&mapping.bytes()[range.clone()]) },
+		}
+	}
+}
+
+impl PartialEq for Chunk
+{
+	fn eq(&self, other: &Self) -> bool
+	{
+		**self == **other
+	}
+}
+
+impl fmt::Debug for Chunk
+{
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		fmt::Debug::fmt(&**self, f)
+	}
+}
+
+/// A read-only file mapping, unmapped once no chunk uses it anymore
+struct Mapping
+{
+	ptr: *const u8,
+	len: usize,
+}
+
+// SAFETY: the mapping is read-only
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping
+{
+	fn bytes(&self) -> &[u8]
+	{
+		if self.len == 0 {
+			return &[];
+		}
+		// SAFETY: the pointer is valid for `len` bytes until unmapped on drop
+		unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+	}
+
+	#[cfg(target_os = "linux")]
+	fn new(file: &File, len: usize) -> io::Result<Self>
+	{
+		use std::os::unix::io::AsRawFd;
+
+		if len == 0 {
+			return Ok(Self { ptr: std::ptr::null(), len });
+		}
+		// SAFETY: a fresh shared read-only mapping of a file only written before
+		let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+		if ptr == libc::MAP_FAILED {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(Self { ptr: ptr as *const u8, len })
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	fn new(_file: &File, _len: usize) -> io::Result<Self>
+	{
+		Err(io::Error::new(io::ErrorKind::Unsupported, "memory-mapped chunks need Linux"))
+	}
+}
+
+impl Drop for Mapping
+{
+	fn drop(&mut self)
+	{
+		#[cfg(target_os = "linux")]
+		if self.len > 0 {
+			// SAFETY: mapped in `new` and no chunk refers to it anymore
+			unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+		}
+	}
+}
+
+/// Moves the chunks of the `lanes` into a memory-mapped file in `dir`
+///
+/// The file is removed right away, the mapping keeps its pages until the chunks
+/// are dropped. The kernel can page them out and read them back when needed,
+/// which keeps huge frames from taking up all the memory.
+pub fn spill(lanes: &[Vec<Chunk>], dir: &Path) -> anyhow::Result<Vec<Vec<Chunk>>>
+{
+	let path = dir.join(format!("pixelspray-{}-{}.chunks", std::process::id(), FILES.fetch_add(1, Ordering::Relaxed)));
+	let file = File::options().read(true).write(true).create_new(true).open(&path)
+		.with_context(|| format!("failed to create {}", path.display()))?;
+	let res = (|| -> io::Result<Vec<Vec<Chunk>>> {
+		let mut out = io::BufWriter::new(&file);
+		let mut len = 0;
+		let ranges = lanes.iter()
+			.map(|chunks| chunks.iter()
+				.map(|chunk| {
+					out.write_all(chunk.as_bytes())?;
+					len += chunk.len();
+					Ok(len - chunk.len()..len)
+				})
+				.collect::<io::Result<Vec<_>>>())
+			.collect::<io::Result<Vec<_>>>()?;
+		out.flush()?;
+		drop(out);
+		let mapping = Arc::new(Mapping::new(&file, len)?);
+		Ok(ranges.into_iter()
+			.map(|ranges| ranges.into_iter()
+				.map(|range| Chunk(Repr::Mapped(mapping.clone(), range)))
+				.collect())
+			.collect())
+	})();
+	std::fs::remove_file(&path).ok();
+	res.with_context(|| format!("failed to map {}", path.display()))
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn chunks()
+	{
+		let chunk = Chunk::from("PX 0 0 FF\nPX 1 0 00\n".to_owned());
+		assert_eq!(&*chunk, "PX 0 0 FF\nPX 1 0 00\n");
+		assert_eq!(chunk, Chunk::from("PX 0 0 FF\nPX 1 0 00\n".to_owned()));
+		assert_ne!(chunk, Chunk::from("PX 0 0 FF\n".to_owned()));
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn spilled()
+	{
+		let lanes = vec![
+			vec![Chunk::from("PX 0 0 FF\n".to_owned()), Chunk::from("PX 1 0 00\n".to_owned())],
+			vec![],
+			vec![Chunk::from("PX 2 0 80\n".to_owned())],
+		];
+		let mapped = spill(&lanes, &std::env::temp_dir()).unwrap();
+		assert_eq!(mapped, lanes);
+		assert!(matches!(mapped[0][1].0, Repr::Mapped(..)));
+		assert_eq!(&*mapped[0][1], "PX 1 0 00\n");
+		assert_eq!(&*mapped[2][0], "PX 2 0 80\n");
+		assert_eq!(spill(&[], &std::env::temp_dir()).unwrap(), Vec::<Vec<Chunk>>::new());
+	}
+}
@@ -0,0 +1,162 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	future::Future,
+	pin::Pin,
+	task::{Context, Poll},
+	time::{Duration, Instant},
+};
+
+use tokio::task::JoinHandle;
+
+
+/// Wait before a restart for every restart already in the window
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// Restarts allowed per worker within a time window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budget
+{
+	pub restarts: usize,
+	pub window: Duration,
+}
+
+/// Parses `restarts/window`, e.g. `5/60s`
+pub fn parse_budget(s: &str) -> Result<Budget, String>
+{
+	let (restarts, window) = s.split_once('/')
+		.ok_or_else(|| format!("expected restarts/window, got '{}'", s))?;
+	let restarts = restarts.parse::<usize>()
+		.map_err(|err| format!("invalid number of restarts '{}': {}", restarts, err))?;
+	let window = humantime::parse_duration(window)
+		.map_err(|err| format!("invalid window '{}': {}", window, err))?;
+	Ok(Budget { restarts, window })
+}
+
+#[derive(Debug, Default)]
+struct Worker
+{
+	/// Restarts within the window
+	recent: VecDeque<Instant>,
+	total: usize,
+	last_error: Option<String>,
+	gave_up: bool,
+}
+
+/// Keeps track of failing workers and decides whether to restart them
+#[derive(Debug)]
+pub struct Supervisor
+{
+	budget: Budget,
+	workers: HashMap<usize, Worker>,
+}
+
+impl Supervisor
+{
+	pub fn new(budget: Budget) -> Self
+	{
+		Self { budget, workers: HashMap::new() }
+	}
+
+	/// Records a failure of worker `id`, returning how long to wait before restarting it,
+	/// or `None` once its budget is used up
+	pub fn failed(&mut self, id: usize, err: String, now: Instant) -> Option<Duration>
+	{
+		let window = self.budget.window;
+		let worker = self.workers.entry(id).or_default();
+		worker.last_error = Some(err);
+		while worker.recent.front().is_some_and(|&at| now.duration_since(at) > window) {
+			worker.recent.pop_front();
+		}
+		if worker.recent.len() >= self.budget.restarts {
+			worker.gave_up = true;
+			return None;
+		}
+		let delay = BACKOFF * worker.recent.len() as u32;
+		worker.recent.push_back(now);
+		worker.total += 1;
+		Some(delay)
+	}
+
+	/// How many workers were given up
+	pub fn given_up(&self) -> usize
+	{
+		self.workers.values().filter(|worker| worker.gave_up).count()
+	}
+
+	/// A line per worker that failed
+	pub fn summary(&self) -> Vec<String>
+	{
+		let mut ids = self.workers.keys().copied().collect::<Vec<_>>();
+		ids.sort_unstable();
+		ids.into_iter()
+			.map(|id| {
+				let worker = &self.workers[&id];
+				format!("{}: {} restarts{}, last error: {}", id, worker.total,
+					if worker.gave_up { ", given up" } else { "" },
+					worker.last_error.as_deref().unwrap_or_default())
+			})
+			.collect()
+	}
+}
+
+/// A worker task that tells its id when it ends, even by a panic
+#[derive(Debug)]
+pub struct Task<T>
+{
+	id: usize,
+	handle: JoinHandle<anyhow::Result<T>>,
+}
+
+impl<T> Task<T>
+{
+	pub fn new(id: usize, handle: JoinHandle<anyhow::Result<T>>) -> Self
+	{
+		Self { id, handle }
+	}
+
+	pub fn abort(&self)
+	{
+		self.handle.abort();
+	}
+}
+
+impl<T> Future for Task<T>
+{
+	type Output = (usize, anyhow::Result<T>);
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output>
+	{
+		let id = self.id;
+		Pin::new(&mut self.handle).poll(cx)
+			.map(|res| (id, res.unwrap_or_else(|err| Err(anyhow::anyhow!("task panicked: {}", err)))))
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn budget()
+	{
+		assert_eq!(parse_budget("5/60s"), Ok(Budget { restarts: 5, window: Duration::from_secs(60) }));
+		assert!(parse_budget("5").is_err());
+		assert!(parse_budget("x/1s").is_err());
+
+		let mut supervisor = Supervisor::new(parse_budget("2/10s").unwrap());
+		let start = Instant::now();
+		assert_eq!(supervisor.failed(0, "refused".into(), start), Some(Duration::ZERO));
+		assert_eq!(supervisor.failed(0, "refused".into(), start), Some(BACKOFF));
+		assert_eq!(supervisor.failed(1, "reset".into(), start), Some(Duration::ZERO));
+		assert_eq!(supervisor.failed(0, "refused".into(), start + Duration::from_secs(5)), None);
+		assert_eq!(supervisor.given_up(), 1);
+		// the window moved on
+		assert_eq!(supervisor.failed(1, "reset".into(), start + Duration::from_secs(11)), Some(Duration::ZERO));
+		assert_eq!(supervisor.summary(), [
+			"0: 2 restarts, given up, last error: refused",
+			"1: 2 restarts, last error: reset",
+		]);
+	}
+}
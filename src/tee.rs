@@ -0,0 +1,104 @@
+use std::{
+	io::{self, Write},
+	path::Path,
+};
+
+use anyhow::Context;
+use tokio::sync::mpsc;
+use tracing as log;
+
+use crate::store::Chunk;
+
+
+/// Chunks queued for writing before the connections wait for the copy
+const QUEUE: usize = 64;
+
+/// A chunk and the OFFSET it was sent with
+type Sent = (Option<(u32, u32)>, Chunk);
+
+/// Copy of the chunks sent by all connections, as one command stream
+///
+/// The OFFSET of the connection that sent a chunk is repeated in front of it
+/// whenever it differs from the one before, so the copy can be replayed on a
+/// single connection.
+#[derive(Debug, Clone)]
+pub struct Tee
+{
+	tx: mpsc::Sender<Sent>,
+}
+
+impl Tee
+{
+	/// Writes to the file at `path`, or to stdout for `-`
+	pub fn open(path: &Path) -> anyhow::Result<Self>
+	{
+		let out: Box<dyn Write + Send> = if path == Path::new("-") {
+			Box::new(io::stdout())
+		} else {
+			Box::new(std::fs::File::create(path)
+				.with_context(|| format!("failed to create {}", path.display()))?)
+		};
+		let (tx, rx) = mpsc::channel(QUEUE);
+		std::thread::Builder::new()
+			.name("tee".into())
+			.spawn(move || {
+				if let Err(err) = write(io::BufWriter::new(out), rx) {
+					log::warn!("stopped copying the commands: {}", err);
+				}
+			})?;
+		Ok(Self { tx })
+	}
+
+	/// Copies a `chunk` sent with `offset`, waiting if the writing falls behind
+	pub async fn send(&self, offset: Option<(u32, u32)>, chunk: Chunk)
+	{
+		self.tx.send((offset, chunk)).await.ok();
+	}
+}
+
+fn write(mut out: impl Write, mut rx: mpsc::Receiver<Sent>) -> io::Result<()>
+{
+	let mut last = None;
+	loop {
+		let (offset, chunk) = match rx.try_recv() {
+			Ok(next) => next,
+			Err(mpsc::error::TryRecvError::Empty) => {
+				out.flush()?;
+				match rx.blocking_recv() {
+					Some(next) => next,
+					None => break,
+				}
+			},
+			Err(mpsc::error::TryRecvError::Disconnected) => break,
+		};
+		if offset != last {
+			let (x, y) = offset.unwrap_or((0, 0));
+			writeln!(out, "OFFSET {} {}", x, y)?;
+			last = offset;
+		}
+		out.write_all(chunk.as_bytes())?;
+	}
+	out.flush()
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn offsets()
+	{
+		let (tx, rx) = mpsc::channel(QUEUE);
+		let chunks = [(Some((10, 10)), "PX 0 0 ff\n"), (Some((10, 10)), "PX 1 0 ff\n"), (Some((20, 0)), "PX 0 0 00\n"), (None, "PX 5 5 00\n")];
+		for (offset, chunk) in chunks.iter().cloned() {
+			tx.try_send((offset, Chunk::from(chunk.to_string()))).unwrap();
+		}
+		drop(tx);
+		let mut out = Vec::new();
+		write(&mut out, rx).unwrap();
+		assert_eq!(String::from_utf8(out).unwrap(),
+			"OFFSET 10 10\nPX 0 0 ff\nPX 1 0 ff\nOFFSET 20 0\nPX 0 0 00\nOFFSET 0 0\nPX 5 5 00\n");
+	}
+}
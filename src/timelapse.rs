@@ -0,0 +1,89 @@
+use std::{
+	collections::HashMap,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use anyhow::Context;
+use image::{Rgba, RgbaImage};
+use tokio::{task, time};
+use tracing as log;
+
+use crate::probe;
+
+
+/// Path of the `n`th snapshot in `dir`
+pub fn frame_path(dir: &Path, n: usize) -> PathBuf
+{
+	dir.join(format!("{:05}.png", n))
+}
+
+/// The first snapshot number not taken yet, so a new run goes on after an earlier one
+pub fn first_free(dir: &Path) -> usize
+{
+	(0..).find(|&n| !frame_path(dir, n).exists()).unwrap_or_default()
+}
+
+/// The canvas as read back, with the pixels the server left out transparent
+fn snapshot((w, h): (u32, u32), pixels: &HashMap<(u32, u32), [u8; 3]>) -> RgbaImage
+{
+	RgbaImage::from_fn(w, h, |x, y| match pixels.get(&(x, y)) {
+		Some(&[r, g, b]) => Rgba([r, g, b, 0xff]),
+		None => Rgba([0; 4]),
+	})
+}
+
+/// Reads the whole canvas back every `interval` and saves it as numbered PNGs into `dir`
+pub async fn run(host: SocketAddr, canvas: (u32, u32), dir: PathBuf, interval: Duration) -> anyhow::Result<()>
+{
+	std::fs::create_dir_all(&dir)
+		.with_context(|| format!("failed to create {}", dir.display()))?;
+	let mut n = first_free(&dir);
+	let coords = (0..canvas.1)
+		.flat_map(|y| (0..canvas.0).map(move |x| (x, y)))
+		.collect::<Vec<_>>();
+	let mut ticker = time::interval(interval);
+	loop {
+		ticker.tick().await;
+		let pixels = match probe::grab(host, &coords).await {
+			Ok(pixels) => pixels,
+			Err(err) => {
+				log::warn!("timelapse: {:#}", err);
+				continue;
+			},
+		};
+		let path = frame_path(&dir, n);
+		let saved = task::spawn_blocking(move || snapshot(canvas, &pixels).save(&path).map(|_| path)).await?;
+		match saved {
+			Ok(path) => log::debug!("saved timelapse frame {}", path.display()),
+			Err(err) => log::warn!("failed to save timelapse frame: {}", err),
+		}
+		n += 1;
+	}
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn frames()
+	{
+		let dir = std::env::temp_dir().join(format!("pixelspray-timelapse-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		assert_eq!(first_free(&dir), 0);
+
+		let pixels = HashMap::from([((1, 0), [1, 2, 3])]);
+		let image = snapshot((2, 2), &pixels);
+		assert_eq!(image.get_pixel(1, 0), &Rgba([1, 2, 3, 0xff]));
+		assert_eq!(image.get_pixel(0, 0).0[3], 0);
+		image.save(frame_path(&dir, 0)).unwrap();
+		assert_eq!(first_free(&dir), 1);
+		assert_eq!(frame_path(&dir, 1).file_name().unwrap(), "00001.png");
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
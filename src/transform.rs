@@ -0,0 +1,852 @@
+use std::{
+	convert::TryInto,
+	sync::{atomic::AtomicU64, Arc},
+	time::Duration,
+};
+
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView};
+
+use crate::{overlay, palette, Opt};
+
+
+/// A step of the image preprocessing
+pub trait Transform: Send
+{
+	/// Transforms an image as it looks `t` seconds in
+	fn apply(&mut self, image: DynamicImage, t: f32) -> anyhow::Result<DynamicImage>;
+
+	/// Whether the output still changes over time `t` seconds in
+	fn animated(&self, _t: f32) -> bool
+	{
+		false
+	}
+}
+
+/// Transforms applied one after the other
+#[derive(Default)]
+pub struct Pipeline
+{
+	stages: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline
+{
+	pub fn builder() -> Builder
+	{
+		Builder::default()
+	}
+
+	pub fn is_empty(&self) -> bool
+	{
+		self.stages.is_empty()
+	}
+
+	/// Whether frames have to be rendered periodically
+	pub fn animated(&self, t: f32) -> bool
+	{
+		self.stages.iter().any(|stage| stage.animated(t))
+	}
+}
+
+impl Transform for Pipeline
+{
+	fn apply(&mut self, image: DynamicImage, t: f32) -> anyhow::Result<DynamicImage>
+	{
+		self.stages.iter_mut()
+			.try_fold(image, |image, stage| stage.apply(image, t))
+	}
+
+	fn animated(&self, t: f32) -> bool
+	{
+		Pipeline::animated(self, t)
+	}
+}
+
+#[derive(Default)]
+pub struct Builder
+{
+	stages: Vec<Box<dyn Transform>>,
+}
+
+impl Builder
+{
+	pub fn then(mut self, stage: impl Transform + 'static) -> Self
+	{
+		self.stages.push(Box::new(stage));
+		self
+	}
+
+	pub fn then_some(self, stage: Option<impl Transform + 'static>) -> Self
+	{
+		match stage {
+			Some(stage) => self.then(stage),
+			None => self,
+		}
+	}
+
+	pub fn build(self) -> Pipeline
+	{
+		Pipeline { stages: self.stages }
+	}
+}
+
+/// Applies mirroring, resizes the image to fit the canvas and projects it
+pub fn preprocess(opt: &Opt, canvas: (u32, u32)) -> Builder
+{
+	let size = opt.resize.map(|(w, h)| (w.map(|w| w.resolve(canvas.0)), h.map(|h| h.resolve(canvas.1))));
+	Pipeline::builder()
+		.then(Mirror { horizontal: opt.mirror_v, vertical: opt.mirror })
+		.then(Fit { size, canvas, strict: opt.no_resize, resampling: opt.resampling() })
+		.then_some(opt.map.map(Map))
+		.then_some(opt.edges.map(|threshold| Edges { threshold, color: opt.edge_color.map(|color| color.0) }))
+		.then_some(opt.accessibility)
+}
+
+/// Preprocesses new images of the source into the `footprint` of the first, over the `background`
+pub fn prepare(opt: &Opt, canvas: (u32, u32), footprint: (u32, u32), background: Option<Arc<image::RgbaImage>>) -> Pipeline
+{
+	preprocess(opt, canvas)
+		.then(Stretch(footprint, opt.resampling()))
+		.then_some(background.map(Composite))
+		.build()
+}
+
+/// Turns prepared images into the frames sent, counting `sent` bytes for the HUD
+pub fn render(opt: &Opt, sent: Arc<AtomicU64>) -> anyhow::Result<Pipeline>
+{
+	let dither = opt.target_palette.as_deref()
+		.map(palette::Palette::load).transpose()?
+		.map(palette::Dither::new);
+	Ok(Pipeline::builder()
+		.then_some(opt.interpolate.map(|duration| Crossfade::new(duration.into())))
+		.then_some(opt.transition.map(Transition::transform))
+		.then_some(opt.effect)
+		.then_some(opt.reveal)
+		.then_some(opt.auto_dim)
+		.then_some((!opt.overlay.is_empty()).then(|| overlay::Overlay::new(opt.overlay.clone(), sent)))
+		.then_some(dither)
+		.then_some(opt.mono.then_some(palette::Monochrome))
+		.build())
+}
+
+/// Flips the image
+pub struct Mirror
+{
+	pub horizontal: bool,
+	pub vertical: bool,
+}
+
+impl Transform for Mirror
+{
+	fn apply(&mut self, mut image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		if self.horizontal {
+			image = image.fliph();
+		}
+		if self.vertical {
+			image = image.flipv();
+		}
+		Ok(image)
+	}
+}
+
+/// Filter to scale images with
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq,Default)]
+pub enum Scaling
+{
+	/// Sharpest, but slowest
+	#[default]
+	Lanczos,
+	/// Linear, a lot faster
+	Triangle,
+	/// Blocky, but next to free
+	Nearest,
+}
+
+impl Scaling
+{
+	fn filter(self) -> image::imageops::FilterType
+	{
+		match self {
+			Scaling::Lanczos => image::imageops::FilterType::Lanczos3,
+			Scaling::Triangle => image::imageops::FilterType::Triangle,
+			Scaling::Nearest => image::imageops::FilterType::Nearest,
+		}
+	}
+}
+
+/// How images are scaled
+#[derive(Debug,Copy,Clone,PartialEq,Default)]
+pub struct Resampling
+{
+	pub scaling: Scaling,
+	/// Scale to this many times the size first and average the blocks down in linear light,
+	/// off at 1 or below
+	pub supersample: u32,
+	/// Filter the linear light instead of the sRGB levels, so shrunk edges do not darken
+	pub linear: bool,
+}
+
+impl Resampling
+{
+	/// Scales to exactly this size
+	fn resize_exact(self, image: &DynamicImage, w: u32, h: u32) -> DynamicImage
+	{
+		let n = self.supersample.max(1);
+		let filter = self.scaling.filter();
+		// nearest neighbors pick colors without mixing them
+		let scaled = if self.linear && self.scaling != Scaling::Nearest {
+			from_linear(&to_linear(image).resize_exact(w * n, h * n, filter))
+		} else {
+			image.resize_exact(w * n, h * n, filter)
+		};
+		if n == 1 {
+			return scaled;
+		}
+		DynamicImage::ImageRgba8(downsample(&scaled.into_rgba8(), n))
+	}
+
+	/// Scales to the largest size fitting into `(w, h)`, keeping the aspect ratio
+	fn resize(self, image: &DynamicImage, w: u32, h: u32) -> DynamicImage
+	{
+		// the same size as image::DynamicImage::resize
+		let (iw, ih) = image.dimensions();
+		if (w, h) == (iw, ih) {
+			return image.clone();
+		}
+		let ratio = f64::min(w as f64 / iw as f64, h as f64 / ih as f64);
+		let side = |side: u32| ((side as f64 * ratio).round() as u32).max(1);
+		self.resize_exact(image, side(iw), side(ih))
+	}
+}
+
+/// Linear light of each 8 bit sRGB level
+pub fn linear_levels() -> &'static [f32; 256]
+{
+	static LEVELS: std::sync::OnceLock<[f32; 256]> = std::sync::OnceLock::new();
+	LEVELS.get_or_init(|| std::array::from_fn(|v| {
+		let v = v as f32 / 255.0;
+		if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+	}))
+}
+
+/// Quantizes linear light to the nearest 8 bit sRGB level
+pub fn srgb_level(v: f32) -> u8
+{
+	let v = v.clamp(0.0, 1.0);
+	let v = if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+	(v * 255.0).round() as u8
+}
+
+/// Converts to floating point linear light, keeping the alpha as is
+fn to_linear(image: &DynamicImage) -> DynamicImage
+{
+	let levels = linear_levels();
+	let image = image.to_rgba8();
+	DynamicImage::ImageRgba32F(image::Rgba32FImage::from_fn(image.width(), image.height(), |x, y| {
+		let [r, g, b, a] = image.get_pixel(x, y).0;
+		image::Rgba([levels[r as usize], levels[g as usize], levels[b as usize], a as f32 / 255.0])
+	}))
+}
+
+/// Converts linear light back to 8 bit sRGB
+fn from_linear(image: &DynamicImage) -> DynamicImage
+{
+	let DynamicImage::ImageRgba32F(image) = image else {
+		return image.clone();
+	};
+	DynamicImage::ImageRgba8(image::RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+		let [r, g, b, a] = image.get_pixel(x, y).0;
+		image::Rgba([srgb_level(r), srgb_level(g), srgb_level(b), (a.clamp(0.0, 1.0) * 255.0).round() as u8])
+	}))
+}
+
+/// Averages blocks of `n`x`n` pixels into one, the colors in linear light and weighted by their alpha
+fn downsample(image: &image::RgbaImage, n: u32) -> image::RgbaImage
+{
+	let levels = linear_levels();
+	image::RgbaImage::from_fn(image.width() / n, image.height() / n, |x, y| {
+		let (mut sum, mut alpha) = ([0.0f32; 3], 0.0f32);
+		for dy in 0..n {
+			for dx in 0..n {
+				let [r, g, b, a] = image.get_pixel(x * n + dx, y * n + dy).0;
+				let a = a as f32 / 255.0;
+				for (sum, c) in sum.iter_mut().zip([r, g, b]) {
+					*sum += levels[c as usize] * a;
+				}
+				alpha += a;
+			}
+		}
+		if alpha == 0.0 {
+			return image::Rgba([0; 4]);
+		}
+		let [r, g, b] = sum.map(|sum| srgb_level(sum / alpha));
+		image::Rgba([r, g, b, (alpha / (n * n) as f32 * 255.0).round() as u8])
+	})
+}
+
+/// Resizes to the requested size, or shrinks to fit onto the canvas
+pub struct Fit
+{
+	/// Box to fit into, with a missing side following the aspect ratio of the image
+	pub size: Option<(Option<u32>, Option<u32>)>,
+	pub canvas: (u32, u32),
+	/// Fail instead of shrinking
+	pub strict: bool,
+	pub resampling: Resampling,
+}
+
+impl Transform for Fit
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let (w, h) = image.dimensions();
+		let (sw, sh) = self.canvas;
+		// at least a pixel, however slim the image
+		let follow = |side: u32, by: u32, of: u32| ((side as u64 * by as u64 + of as u64 / 2) / of.max(1) as u64).max(1) as u32;
+		let resampling = self.resampling;
+		match self.size {
+			Some((Some(w), Some(h))) => return Ok(resampling.resize(&image, w, h)),
+			Some((Some(nw), None)) => return Ok(resampling.resize_exact(&image, nw, follow(h, nw, w))),
+			Some((None, Some(nh))) => return Ok(resampling.resize_exact(&image, follow(w, nh, h), nh)),
+			Some((None, None)) | None => {},
+		}
+		if w > sw || h > sh {
+			if self.strict {
+				anyhow::bail!("image {}x{} is larger than canvas {}x{}", w, h, sw, sh);
+			}
+			return Ok(resampling.resize(&image, sw, sh));
+		}
+		Ok(image)
+	}
+}
+
+/// Scales to exactly this size, ignoring the aspect ratio
+pub struct Stretch(pub (u32, u32), pub Resampling);
+
+impl Transform for Stretch
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let (w, h) = self.0;
+		if image.dimensions() == (w, h) {
+			return Ok(image);
+		}
+		Ok(self.1.resize_exact(&image, w, h))
+	}
+}
+
+/// Blends semi-transparent pixels over the canvas content read back beneath them,
+/// so soft edges fade into the canvas instead of whatever the server blends with
+///
+/// Pixels of an unknown background, transparent in it, stay as they are.
+pub struct Composite(pub Arc<image::RgbaImage>);
+
+impl Transform for Composite
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let mut image = image.into_rgba8();
+		for (x, y, px) in image.enumerate_pixels_mut() {
+			let [r, g, b, a] = px.0;
+			if a == 0 || a == 0xff {
+				continue;
+			}
+			let Some(bg) = self.0.get_pixel_checked(x, y).filter(|bg| bg.0[3] != 0) else { continue };
+			let blend = |src: u8, dst: u8| ((src as u32 * a as u32 + dst as u32 * (0xff - a as u32) + 0x7f) / 0xff) as u8;
+			px.0 = [blend(r, bg.0[0]), blend(g, bg.0[1]), blend(b, bg.0[2]), 0xff];
+		}
+		Ok(DynamicImage::ImageRgba8(image))
+	}
+}
+
+/// Keeps only the outlines found by a Sobel filter, optionally in a single color
+pub struct Edges
+{
+	/// Gradient strength from 0 to 255 a pixel needs to be kept
+	pub threshold: u8,
+	pub color: Option<image::Rgba<u8>>,
+}
+
+impl Transform for Edges
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let mut out = image.to_rgba8();
+		let (w, h) = out.dimensions();
+		// transparent pixels count as dark
+		let luma = out.pixels()
+			.map(|px| {
+				let [r, g, b, a] = px.0;
+				(0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) * a as f32 / 255.0
+			})
+			.collect::<Vec<_>>();
+		let at = |x: i64, y: i64| luma[(y.clamp(0, h as i64 - 1) * w as i64 + x.clamp(0, w as i64 - 1)) as usize];
+
+		for (x, y, px) in out.enumerate_pixels_mut() {
+			let (x, y) = (x as i64, y as i64);
+			let gx = at(x + 1, y - 1) + 2.0 * at(x + 1, y) + at(x + 1, y + 1)
+				- at(x - 1, y - 1) - 2.0 * at(x - 1, y) - at(x - 1, y + 1);
+			let gy = at(x - 1, y + 1) + 2.0 * at(x, y + 1) + at(x + 1, y + 1)
+				- at(x - 1, y - 1) - 2.0 * at(x, y - 1) - at(x + 1, y - 1);
+			// a hard step from black to white is 4 * 255
+			if gx.hypot(gy) / 4.0 < self.threshold as f32 {
+				px.0 = [0; 4];
+			} else if let Some(color) = self.color {
+				*px = color;
+			} else {
+				px.0[3] = 0xff;
+			}
+		}
+		Ok(DynamicImage::ImageRgba8(out))
+	}
+}
+
+/// How the image is projected onto the canvas
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum Projection
+{
+	/// Wraps the image around the center, its top at the middle and its bottom at the rim
+	Polar,
+	/// Magnifies the middle like a wide-angle lens
+	Fisheye,
+	/// Repeats the left half mirrored on the right
+	MirrorX,
+	/// Affine map `x' = a*x + b*y + c, y' = d*x + e*y + f` from image to canvas pixels
+	Matrix([f32; 6]),
+}
+
+/// Parses `polar`, `fisheye`, `mirror-x` or `custom-matrix:a,b,c,d,e,f`
+pub fn parse_projection(s: &str) -> Result<Projection, String>
+{
+	match s.split_once(':') {
+		None => match s {
+			"polar" => Ok(Projection::Polar),
+			"fisheye" => Ok(Projection::Fisheye),
+			"mirror-x" => Ok(Projection::MirrorX),
+			_ => Err(format!("unknown projection '{}'", s)),
+		},
+		Some(("custom-matrix", m)) => {
+			let m = m.split(',')
+				.map(|v| v.trim().parse::<f32>().map_err(|err| format!("invalid matrix value '{}': {}", v, err)))
+				.collect::<Result<Vec<_>, _>>()?;
+			let m: [f32; 6] = m.try_into()
+				.map_err(|m: Vec<_>| format!("expected 6 matrix values, got {}", m.len()))?;
+			if m[0] * m[4] - m[1] * m[3] == 0.0 {
+				return Err("matrix is not invertible".into());
+			}
+			Ok(Projection::Matrix(m))
+		},
+		Some((kind, _)) => Err(format!("unknown projection '{}'", kind)),
+	}
+}
+
+/// Remaps the image pixels to where they go on the canvas, keeping its size
+pub struct Map(pub Projection);
+
+impl Map
+{
+	/// Image coordinates shown at `(x, y)`, if any
+	fn source(&self, (x, y): (f32, f32), (w, h): (f32, f32)) -> Option<(f32, f32)>
+	{
+		use std::f32::consts::PI;
+		// relative to the middle, with the shorter side spanning -1 to 1
+		let r = w.min(h) / 2.0;
+		let (u, v) = ((x - w / 2.0) / r, (y - h / 2.0) / r);
+		match self.0 {
+			Projection::Polar => {
+				let d = u.hypot(v);
+				(d <= 1.0).then(|| ((v.atan2(u) / (2.0 * PI) + 0.5) * w, d * h))
+			},
+			Projection::Fisheye => {
+				let d = u.hypot(v);
+				(d <= 1.0).then(|| (u * d * r + w / 2.0, v * d * r + h / 2.0))
+			},
+			Projection::MirrorX => Some((x.min(w - x), y)),
+			Projection::Matrix([a, b, c, d, e, f]) => {
+				let det = a * e - b * d;
+				let (x, y) = (x - c, y - f);
+				Some(((e * x - b * y) / det, (a * y - d * x) / det))
+			},
+		}
+	}
+}
+
+impl Transform for Map
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let src = image.to_rgba8();
+		let (w, h) = src.dimensions();
+		let out = image::RgbaImage::from_fn(w, h, |x, y| {
+			// sampled in the middle of the pixels
+			self.source((x as f32 + 0.5, y as f32 + 0.5), (w as f32, h as f32))
+				.filter(|&(sx, sy)| sx >= 0.0 && sy >= 0.0 && sx < w as f32 && sy < h as f32)
+				.map_or(image::Rgba([0; 4]), |(sx, sy)| *src.get_pixel(sx as u32, sy as u32))
+		});
+		Ok(DynamicImage::ImageRgba8(out))
+	}
+}
+
+/// Color adjustments that keep the image readable for color blind viewers or under colored light
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum Accessibility
+{
+	/// Shifts red-green differences lost without green cones into brightness and blue
+	Deuteranopia,
+	/// Shifts red-green differences lost without red cones into brightness and blue
+	Protanopia,
+	/// Raises contrast and saturation
+	HighContrast,
+}
+
+type Matrix = [[f32; 3]; 3];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix
+{
+	std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+impl Accessibility
+{
+	/// Color matrix and offset added afterwards, on channels from 0 to 1
+	fn matrix(self) -> (Matrix, [f32; 3])
+	{
+		let seen = match self {
+			Accessibility::Deuteranopia => [[0.625, 0.375, 0.0], [0.7, 0.3, 0.0], [0.0, 0.3, 0.7]],
+			Accessibility::Protanopia => [[0.567, 0.433, 0.0], [0.558, 0.442, 0.0], [0.0, 0.242, 0.758]],
+			Accessibility::HighContrast => {
+				// saturation, then contrast around the middle grey
+				let (s, c) = (1.3, 1.5);
+				let luma = [0.299, 0.587, 0.114];
+				let saturate = std::array::from_fn(|i| std::array::from_fn(|j| (1.0 - s) * luma[j] + if i == j { s } else { 0.0 }));
+				let contrast = IDENTITY.map(|row| row.map(|v| v * c));
+				return (mul(&contrast, &saturate), [0.5 * (1.0 - c); 3]);
+			},
+		};
+		// daltonizing: what gets lost is added to the channels still seen
+		let lost = std::array::from_fn(|i| std::array::from_fn(|j| IDENTITY[i][j] - seen[i][j]));
+		let shift = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+		let correction = mul(&shift, &lost);
+		(std::array::from_fn(|i| std::array::from_fn(|j| IDENTITY[i][j] + correction[i][j])), [0.0; 3])
+	}
+}
+
+impl Transform for Accessibility
+{
+	fn apply(&mut self, image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+	{
+		let (m, offset) = self.matrix();
+		let mut out = image.to_rgba8();
+		for px in out.pixels_mut() {
+			let c = [0, 1, 2].map(|i| px.0[i] as f32 / 255.0);
+			for i in 0..3 {
+				let v = m[i][0] * c[0] + m[i][1] * c[1] + m[i][2] * c[2] + offset[i];
+				px.0[i] = (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+			}
+		}
+		Ok(DynamicImage::ImageRgba8(out))
+	}
+}
+
+/// Fades from the previous to each new image instead of switching at once
+pub struct Crossfade
+{
+	duration: f32,
+	/// The latest input
+	last: Option<DynamicImage>,
+	/// What was shown when the latest input arrived, and when that was
+	from: Option<(DynamicImage, f32)>,
+	shown: Option<DynamicImage>,
+}
+
+impl Crossfade
+{
+	pub fn new(duration: Duration) -> Self
+	{
+		Self { duration: duration.as_secs_f32(), last: None, from: None, shown: None }
+	}
+}
+
+impl Transform for Crossfade
+{
+	fn apply(&mut self, image: DynamicImage, t: f32) -> anyhow::Result<DynamicImage>
+	{
+		if self.last.as_ref().is_some_and(|last| *last != image) {
+			self.from = self.shown.take().map(|shown| (shown, t));
+		}
+		self.last = Some(image.clone());
+
+		let out = match self.from.as_ref() {
+			Some((from, since)) if t - since < self.duration && from.dimensions() == image.dimensions() => {
+				blend(from, &image, (t - since) / self.duration)
+			},
+			_ => {
+				self.from = None;
+				image
+			},
+		};
+		self.shown = Some(out.clone());
+		Ok(out)
+	}
+
+	fn animated(&self, _t: f32) -> bool
+	{
+		self.from.is_some()
+	}
+}
+
+/// How the slides of a slideshow change
+#[derive(ValueEnum,Debug,Copy,Clone,PartialEq)]
+pub enum TransitionKind
+{
+	/// Blend the next slide in over the previous one
+	Crossfade,
+}
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub struct Transition
+{
+	pub kind: TransitionKind,
+	pub duration: Duration,
+}
+
+/// Parses `kind:duration`, e.g. `crossfade:2s`
+pub fn parse_transition(s: &str) -> Result<Transition, String>
+{
+	let (kind, duration) = s.split_once(':')
+		.ok_or_else(|| format!("expected kind:duration, got '{}'", s))?;
+	let kind = TransitionKind::from_str(kind, true)?;
+	let duration = humantime::parse_duration(duration)
+		.map_err(|err| format!("invalid duration '{}': {}", duration, err))?;
+	Ok(Transition { kind, duration })
+}
+
+impl Transition
+{
+	pub fn transform(self) -> impl Transform
+	{
+		match self.kind {
+			TransitionKind::Crossfade => Crossfade::new(self.duration),
+		}
+	}
+}
+
+/// Mixes `f` of `b` into `a`
+///
+/// Colors are weighted by their alpha, so transparent pixels do not bleed
+/// their hidden color into the other image.
+pub fn blend(a: &DynamicImage, b: &DynamicImage, f: f32) -> DynamicImage
+{
+	let mut out = a.to_rgba8();
+	let b = b.to_rgba8();
+	for (px, other) in out.pixels_mut().zip(b.pixels()) {
+		let (wa, wb) = (px.0[3] as f32 * (1.0 - f), other.0[3] as f32 * f);
+		let alpha = wa + wb;
+		if alpha > 0.0 {
+			for (c, o) in px.0[..3].iter_mut().zip(other.0) {
+				*c = ((*c as f32 * wa + o as f32 * wb) / alpha).round() as u8;
+			}
+		}
+		px.0[3] = alpha.round() as u8;
+	}
+	DynamicImage::ImageRgba8(out)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	struct Invert;
+
+	impl Transform for Invert
+	{
+		fn apply(&mut self, mut image: DynamicImage, _t: f32) -> anyhow::Result<DynamicImage>
+		{
+			image.invert();
+			Ok(image)
+		}
+
+		fn animated(&self, t: f32) -> bool
+		{
+			t < 1.0
+		}
+	}
+
+	#[test]
+	fn pipeline()
+	{
+		let image = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(8, 4, image::Rgba([0, 0x40, 0xff, 0xff])));
+		let mut pipeline = Pipeline::builder()
+			.then(Fit { size: None, canvas: (4, 4), strict: false, resampling: Resampling { scaling: Scaling::Lanczos, supersample: 1, linear: false } })
+			.then_some(Some(Invert))
+			.then_some(None::<Stretch>)
+			.build();
+
+		let out = pipeline.apply(image.clone(), 0.0).unwrap();
+		assert_eq!(out.dimensions(), (4, 2));
+		assert_eq!(out.get_pixel(0, 0), image::Rgba([0xff, 0xbf, 0, 0xff]));
+		assert!(pipeline.animated(0.5));
+		assert!(!pipeline.animated(1.0));
+
+		let out = Fit { size: Some((Some(6), None)), canvas: (4, 4), strict: true, resampling: Resampling { scaling: Scaling::Nearest, supersample: 1, linear: false } }.apply(image.clone(), 0.0).unwrap();
+		assert_eq!(out.dimensions(), (6, 3));
+		let out = Fit { size: Some((None, Some(1))), canvas: (4, 4), strict: false, resampling: Resampling { scaling: Scaling::Nearest, supersample: 1, linear: false } }.apply(image.clone(), 0.0).unwrap();
+		assert_eq!(out.dimensions(), (2, 1));
+
+		let mut strict = Pipeline::builder()
+			.then(Fit { size: None, canvas: (4, 4), strict: true, resampling: Resampling { scaling: Scaling::Lanczos, supersample: 1, linear: false } })
+			.build();
+		assert!(strict.apply(image.clone(), 0.0).is_err());
+
+		// nearest neighbors keep the exact colors
+		let out = Stretch((2, 2), Resampling { scaling: Scaling::Nearest, supersample: 1, linear: false }).apply(image, 0.0).unwrap();
+		assert_eq!(out.get_pixel(1, 1), image::Rgba([0, 0x40, 0xff, 0xff]));
+	}
+
+	#[test]
+	fn supersample()
+	{
+		// black and white stripes average to the grey of half the light, not of half the level
+		let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(8, 8, |x, _| {
+			let c = [0, 0xff][x as usize % 2];
+			image::Rgba([c, c, c, 0xff])
+		}));
+		let resampling = Resampling { scaling: Scaling::Nearest, supersample: 4, linear: true };
+		let out = Stretch((2, 2), resampling).apply(image, 0.0).unwrap();
+		assert_eq!(out.dimensions(), (2, 2));
+		assert_eq!(out.get_pixel(0, 0), image::Rgba([188, 188, 188, 0xff]));
+
+		// transparent pixels give no color
+		let image = image::RgbaImage::from_fn(2, 2, |x, y| image::Rgba(if x == y { [0xff, 0, 0, 0xff] } else { [0, 0, 0xff, 0] }));
+		assert_eq!(downsample(&image, 2).get_pixel(0, 0), &image::Rgba([0xff, 0, 0, 0x80]));
+		assert_eq!(Fit { size: Some((Some(3), Some(3))), canvas: (4, 4), strict: false, resampling }
+			.apply(DynamicImage::ImageRgba8(image::RgbaImage::new(8, 4)), 0.0).unwrap().dimensions(), (3, 2));
+	}
+
+	#[test]
+	fn linear_light()
+	{
+		let stripes = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(8, 8, |x, _| {
+			let c = [0, 0xff][x as usize % 2];
+			image::Rgba([c, c, c, 0xff])
+		}));
+		let grey = |linear| {
+			let resampling = Resampling { scaling: Scaling::Triangle, supersample: 1, linear };
+			Stretch((1, 1), resampling).apply(stripes.clone(), 0.0).unwrap().get_pixel(0, 0).0
+		};
+		assert_eq!(grey(false), [0x80, 0x80, 0x80, 0xff]);
+		assert_eq!(grey(true), [188, 188, 188, 0xff]);
+	}
+
+	#[test]
+	fn composite()
+	{
+		let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(3, 1, |x, _| image::Rgba([0xff, 0, 0, [0x80, 0xff, 0x80][x as usize]])));
+		// the last pixel was not read back
+		let background = image::RgbaImage::from_fn(3, 1, |x, _| image::Rgba([0, 0, 0xff, if x == 2 { 0 } else { 0xff }]));
+		let out = Composite(Arc::new(background)).apply(image, 0.0).unwrap();
+		assert_eq!(out.get_pixel(0, 0), image::Rgba([0x80, 0, 0x7f, 0xff]));
+		assert_eq!(out.get_pixel(1, 0), image::Rgba([0xff, 0, 0, 0xff]));
+		assert_eq!(out.get_pixel(2, 0), image::Rgba([0xff, 0, 0, 0x80]));
+	}
+
+	#[test]
+	fn edges()
+	{
+		// white square in the middle of black
+		let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(8, 8, |x, y| {
+			let v = if (2..6).contains(&x) && (2..6).contains(&y) { 0xff } else { 0 };
+			image::Rgba([v, v, v, 0xff])
+		}));
+		let red = image::Rgba([0xff, 0, 0, 0xff]);
+		let out = Edges { threshold: 64, color: Some(red) }.apply(image, 0.0).unwrap();
+		assert_eq!(out.get_pixel(2, 4), red);
+		assert_eq!(out.get_pixel(1, 4), red);
+		assert_eq!(out.get_pixel(4, 4).0[3], 0);
+		assert_eq!(out.get_pixel(0, 0).0[3], 0);
+	}
+
+	#[test]
+	fn projections()
+	{
+		assert_eq!(parse_projection("mirror-x"), Ok(Projection::MirrorX));
+		assert_eq!(parse_projection("custom-matrix:1,0,2,0,1,0"), Ok(Projection::Matrix([1.0, 0.0, 2.0, 0.0, 1.0, 0.0])));
+		assert!(parse_projection("custom-matrix:1,0,0,1,0,0").is_err());
+		assert!(parse_projection("custom-matrix:1,0").is_err());
+		assert!(parse_projection("sphere").is_err());
+
+		let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(4, 4, |x, y| image::Rgba([x as u8, y as u8, 0, 0xff])));
+		let shifted = Map(Projection::Matrix([1.0, 0.0, 2.0, 0.0, 1.0, 0.0])).apply(image.clone(), 0.0).unwrap();
+		assert_eq!(shifted.get_pixel(3, 1), image::Rgba([1, 1, 0, 0xff]));
+		assert_eq!(shifted.get_pixel(1, 1).0[3], 0);
+
+		let mirrored = Map(Projection::MirrorX).apply(image.clone(), 0.0).unwrap();
+		assert_eq!(mirrored.get_pixel(3, 2), image.get_pixel(0, 2));
+
+		// the top row ends up in the middle, the corners are outside the circle
+		let polar = Map(Projection::Polar).apply(image, 0.0).unwrap();
+		assert_eq!(polar.get_pixel(2, 2).0[1], 1);
+		assert_eq!(polar.get_pixel(0, 0).0[3], 0);
+	}
+
+	#[test]
+	fn accessibility()
+	{
+		let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(4, 1, |x, _| match x {
+			0 => image::Rgba([0x80, 0x80, 0x80, 0xff]),
+			1 => image::Rgba([0xff, 0, 0, 0xff]),
+			2 => image::Rgba([0, 0xff, 0, 0xff]),
+			_ => image::Rgba([0x20, 0x20, 0x20, 0x40]),
+		}));
+		for mut mode in [Accessibility::Deuteranopia, Accessibility::Protanopia] {
+			let out = mode.apply(image.clone(), 0.0).unwrap();
+			// greys and alpha stay
+			assert_eq!(out.get_pixel(0, 0), image.get_pixel(0, 0));
+			assert_eq!(out.get_pixel(3, 0), image.get_pixel(3, 0));
+			// red gets blue added, green stays apart from it
+			let (red, green) = (out.get_pixel(1, 0).0, out.get_pixel(2, 0).0);
+			assert!(red[2] > 0x40, "{:?} {:?}", mode, red);
+			assert_ne!(red, green);
+		}
+
+		let out = Accessibility::HighContrast.apply(image.clone(), 0.0).unwrap();
+		assert_eq!(out.get_pixel(0, 0).0[..3], [0x80; 3]);
+		assert_eq!(out.get_pixel(3, 0).0, [0, 0, 0, 0x40]);
+		assert_eq!(out.get_pixel(1, 0).0[..3], [0xff, 0, 0]);
+	}
+
+	#[test]
+	fn crossfade()
+	{
+		let grey = |v| DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(2, 2, image::Rgba([v, v, v, 0xff])));
+		let mut fade = Crossfade::new(Duration::from_secs(2));
+		assert_eq!(fade.apply(grey(0), 0.0).unwrap(), grey(0));
+		assert!(!fade.animated(0.0));
+
+		assert_eq!(fade.apply(grey(200), 1.0).unwrap(), grey(0));
+		assert!(fade.animated(1.0));
+		assert_eq!(fade.apply(grey(200), 2.0).unwrap(), grey(100));
+		assert_eq!(fade.apply(grey(200), 3.0).unwrap(), grey(200));
+		assert!(!fade.animated(3.0));
+	}
+
+	#[test]
+	fn transition()
+	{
+		assert_eq!(parse_transition("crossfade:2s"),
+			Ok(Transition { kind: TransitionKind::Crossfade, duration: Duration::from_secs(2) }));
+		assert!(parse_transition("crossfade").is_err());
+		assert!(parse_transition("wipe:1s").is_err());
+
+		// the red of the transparent pixel does not tint the fade
+		let clear = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([0xff, 0, 0, 0])));
+		let blue = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([0, 0, 0xff, 0xff])));
+		assert_eq!(blend(&clear, &blue, 0.5).get_pixel(0, 0), image::Rgba([0, 0, 0xff, 0x80]));
+	}
+}
@@ -0,0 +1,116 @@
+use std::{
+	cell::Cell,
+	sync::{
+		atomic::{AtomicU64, AtomicUsize, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
+};
+
+use tokio::{runtime, time};
+use tracing as log;
+
+
+/// Runtime settings for dedicated machines
+#[derive(Debug)]
+pub struct Tuning
+{
+	pub worker_threads: Option<usize>,
+	pub blocking_threads: Option<usize>,
+	/// Cores to pin the runtime threads to, round-robin
+	pub cores: Vec<usize>,
+}
+
+/// Scheduler statistics collected from thread hooks
+#[derive(Debug, Default)]
+pub struct Stats
+{
+	threads: AtomicUsize,
+	/// Time the worker threads spent running tasks in nanoseconds
+	busy: AtomicU64,
+	wakeups: AtomicU64,
+}
+
+thread_local! {
+	static UNPARKED: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+pub fn build(tuning: &Tuning) -> std::io::Result<(runtime::Runtime, Arc<Stats>)>
+{
+	let stats = Arc::new(Stats::default());
+	let mut builder = runtime::Builder::new_multi_thread();
+	builder.enable_all();
+	if let Some(n) = tuning.worker_threads {
+		builder.worker_threads(n);
+	}
+	if let Some(n) = tuning.blocking_threads {
+		builder.max_blocking_threads(n);
+	}
+
+	let cores = tuning.cores.clone();
+	let next = AtomicUsize::new(0);
+	let (start, stop, park, unpark) = (stats.clone(), stats.clone(), stats.clone(), stats.clone());
+	builder
+		.on_thread_start(move || {
+			start.threads.fetch_add(1, Ordering::Relaxed);
+			UNPARKED.with(|at| at.set(Some(Instant::now())));
+			if !cores.is_empty() {
+				let core = cores[next.fetch_add(1, Ordering::Relaxed) % cores.len()];
+				if let Err(err) = pin(core) {
+					log::warn!("failed to pin thread to core {}: {}", core, err);
+				}
+			}
+		})
+		.on_thread_stop(move || {
+			stop.threads.fetch_sub(1, Ordering::Relaxed);
+		})
+		.on_thread_park(move || {
+			if let Some(at) = UNPARKED.with(|at| at.take()) {
+				park.busy.fetch_add(at.elapsed().as_nanos() as u64, Ordering::Relaxed);
+			}
+		})
+		.on_thread_unpark(move || {
+			unpark.wakeups.fetch_add(1, Ordering::Relaxed);
+			UNPARKED.with(|at| at.set(Some(Instant::now())));
+		});
+	Ok((builder.build()?, stats))
+}
+
+/// Logs the scheduler statistics every `interval`
+pub async fn report(stats: Arc<Stats>, interval: Duration)
+{
+	let mut ticker = time::interval(interval);
+	ticker.tick().await;
+	let (mut busy, mut wakeups) = (0, 0);
+	loop {
+		ticker.tick().await;
+		let (now_busy, now_wakeups) = (stats.busy.load(Ordering::Relaxed), stats.wakeups.load(Ordering::Relaxed));
+		let threads = stats.threads.load(Ordering::Relaxed);
+		log::info!("runtime: {} threads, {:.1} busy threads, {:.0} wakeups/s",
+			threads,
+			(now_busy - busy) as f64 / interval.as_nanos() as f64,
+			(now_wakeups - wakeups) as f64 / interval.as_secs_f64());
+		(busy, wakeups) = (now_busy, now_wakeups);
+	}
+}
+
+#[cfg(target_os = "linux")]
+fn pin(core: usize) -> std::io::Result<()>
+{
+	// SAFETY: the set is initialized by CPU_ZERO and only read by the call
+	unsafe {
+		let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+		libc::CPU_ZERO(&mut set);
+		libc::CPU_SET(core, &mut set);
+		if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+			return Err(std::io::Error::last_os_error());
+		}
+	}
+	Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin(_core: usize) -> std::io::Result<()>
+{
+	Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "core pinning is only supported on Linux"))
+}